@@ -0,0 +1,474 @@
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event::{read_game_events, GameEvent, GAME_EVENTS_FILE},
+    game::{Card, Cards, Inventory, Trade, GAME_LOG_FILE},
+    replay::Replay,
+};
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct AnalyzeArgs {
+    /// Output directories of games, or directories containing them, e.g., `output/`.
+    #[arg(required = true)]
+    pub dirs: Vec<PathBuf>,
+    /// Directory to write the statistics into.
+    #[arg(long, default_value = "./analysis")]
+    pub out: PathBuf,
+}
+
+/// Cards played in a round, against the cards held by all players at the start of the round.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CardRound {
+    pub round: usize,
+    pub played: Cards,
+    /// Cards of all players at the start of the round, as in `PublicState`.
+    pub public: Cards,
+}
+
+/// Players alive and safe after a round.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SurvivalRound {
+    pub round: usize,
+    pub alive: usize,
+    pub safe: usize,
+}
+
+/// Statistics of a game.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GameStats {
+    pub dir: PathBuf,
+    pub players: usize,
+    pub rounds: usize,
+    pub tables: usize,
+    pub failed_tables: usize,
+    pub duels: usize,
+    /// Tables where both players sign the contract, and the rate over finished tables.
+    pub trades_accepted: usize,
+    pub acceptance_rate: f64,
+    /// Players stating in the chat what they would offer, and how many of them offer at least that.
+    pub stated_intentions: usize,
+    pub kept_intentions: usize,
+    pub intention_match_rate: f64,
+    pub survivors: usize,
+    pub final_coin_gini: f64,
+    /// Stars bought in the final trade.
+    pub star_purchases: usize,
+    pub cards: Vec<CardRound>,
+    pub survival: Vec<SurvivalRound>,
+}
+
+/// Statistics over all games.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CrossGameStats {
+    pub games: usize,
+    pub tables: usize,
+    pub acceptance_rate: f64,
+    pub intention_match_rate: f64,
+    pub survival_rate: f64,
+    pub mean_final_coin_gini: f64,
+    pub mean_star_purchases: f64,
+    /// Cards played over all games by kind.
+    pub cards_played: Cards,
+    /// Mean fraction of players alive after each round, over games lasting that long.
+    pub survival_curve: Vec<f64>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Analysis {
+    pub games: Vec<GameStats>,
+    pub overall: CrossGameStats,
+}
+
+/// Gini coefficient of the values, 0 for perfect equality.
+pub fn gini(values: &[usize]) -> f64 {
+    let total: usize = values.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    let n = values.len() as f64;
+    let weighted: f64 = values
+        .iter()
+        .sorted()
+        .enumerate()
+        .map(|(index, &x)| (index + 1) as f64 * x as f64)
+        .sum();
+    2.0 * weighted / (n * total as f64) - (n + 1.0) / n
+}
+
+/// Quote a CSV field.
+fn quote(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+fn ratio(x: usize, y: usize) -> f64 {
+    match y {
+        0 => 0.0,
+        y => x as f64 / y as f64,
+    }
+}
+
+/// What the speaker says they would give in a chat line, e.g., "I'll give you 2 coins and a rock".
+///
+/// Only offers in the first person count; the last offer in the line wins.
+pub fn stated_offer(text: &str, cards: &[Card]) -> Option<Trade> {
+    const VERBS: [&str; 6] = ["give", "offer", "pay", "trade", "send", "sell"];
+    const NUMBERS: [&str; 11] = [
+        "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    ];
+    let singular = |x: &str| x.trim_end_matches('s').to_string();
+
+    let text = text.to_lowercase();
+    let tokens = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .collect_vec();
+
+    let mut offer = None;
+    for (index, _) in tokens.iter().enumerate().filter(|(_, &x)| x == "i") {
+        let Some(verb) = tokens
+            .iter()
+            .skip(index + 1)
+            .take(3)
+            .position(|x| VERBS.contains(x))
+        else {
+            continue;
+        };
+
+        let mut trade = Trade::default();
+        let start = index + verb + 2;
+        let end = tokens[start..]
+            .iter()
+            .position(|&x| x == "i")
+            .map_or(tokens.len(), |x| start + x);
+        for (count, item) in tokens[start..end].iter().tuple_windows() {
+            let count = match (count.parse(), NUMBERS.iter().position(|x| x == count)) {
+                (Ok(count), _) | (_, Some(count)) => count,
+                _ if *count == "a" || *count == "an" => 1,
+                _ => continue,
+            };
+            match singular(item).as_str() {
+                "star" => trade.star += count,
+                "coin" => trade.coin += count,
+                item => {
+                    if let Some(card) = cards.iter().find(|x| singular(&x.to_lowercase()) == item) {
+                        *trade.cards.entry(card.clone()).or_default() += count;
+                    }
+                }
+            }
+        }
+        if trade.star + trade.coin + trade.cards.values().sum::<usize>() > 0 {
+            offer = Some(trade);
+        }
+    }
+    offer
+}
+
+/// Whether the trade gives at least what is stated.
+fn keeps(stated: &Trade, trade: &Trade) -> bool {
+    trade.star >= stated.star
+        && trade.coin >= stated.coin
+        && stated
+            .cards
+            .iter()
+            .all(|(card, &count)| trade.count(card) >= count)
+}
+
+fn public_cards<'a>(inventories: impl IntoIterator<Item = &'a Inventory>) -> Cards {
+    let mut cards = Cards::new();
+    for inventory in inventories {
+        for (card, &count) in &inventory.cards {
+            *cards.entry(card.clone()).or_default() += count;
+        }
+    }
+    cards
+}
+
+/// Compute statistics of the game dumped in the directory.
+pub fn analyze_game(dir: impl AsRef<Path>) -> Result<GameStats> {
+    let dir = dir.as_ref();
+    let replay = Replay::load(dir)?;
+    let events = match dir.join(GAME_EVENTS_FILE).exists() {
+        true => read_game_events(dir.join(GAME_EVENTS_FILE))?,
+        false => vec![],
+    };
+
+    let cards = replay
+        .players
+        .iter()
+        .flat_map(|x| x.inventory.cards.keys())
+        .unique()
+        .cloned()
+        .collect_vec();
+
+    let mut stats = GameStats {
+        dir: dir.to_path_buf(),
+        players: replay.players.len(),
+        rounds: replay.rounds.len(),
+        ..Default::default()
+    };
+
+    let mut inventories = replay
+        .players
+        .iter()
+        .map(|x| x.inventory.clone())
+        .collect_vec();
+    for round in &replay.rounds {
+        let mut played = Cards::new();
+        for table in &round.tables {
+            stats.tables += 1;
+            let Some(report) = &table.report else {
+                stats.failed_tables += 1;
+                continue;
+            };
+            if report.accepted.iter().all(|&x| x) {
+                stats.trades_accepted += 1;
+            }
+            if report.result.is_some() {
+                stats.duels += 1;
+            }
+            for card in report.cards.iter().flatten() {
+                *played.entry(card.clone()).or_default() += 1;
+            }
+
+            for (index, trade) in report.trades.iter().enumerate() {
+                let stated = report
+                    .chat
+                    .iter()
+                    .rev()
+                    .filter(|line| line.player == index)
+                    .find_map(|line| stated_offer(&line.content, &cards));
+                if let Some(stated) = stated {
+                    stats.stated_intentions += 1;
+                    if keeps(&stated, trade) {
+                        stats.kept_intentions += 1;
+                    }
+                }
+            }
+        }
+
+        stats.cards.push(CardRound {
+            round: round.index,
+            played,
+            public: public_cards(&inventories),
+        });
+        inventories = round.inventories.iter().map(|(_, x)| x.clone()).collect();
+
+        let safe = events
+            .iter()
+            .filter(|x| x.round <= round.index)
+            .filter(|x| matches!(x.event, GameEvent::PlayerSafe { .. }))
+            .count();
+        stats.survival.push(SurvivalRound {
+            round: round.index,
+            alive: inventories.iter().filter(|x| x.is_alive()).count(),
+            safe,
+        });
+    }
+
+    let finished = stats.tables - stats.failed_tables;
+    stats.acceptance_rate = ratio(stats.trades_accepted, finished);
+    stats.intention_match_rate = ratio(stats.kept_intentions, stats.stated_intentions);
+
+    let result = match replay.result.is_empty() {
+        true => inventories,
+        false => replay.result.iter().map(|x| x.inventory.clone()).collect(),
+    };
+    let coins = result.iter().map(|x| x.coin).collect_vec();
+    stats.final_coin_gini = gini(&coins);
+    stats.survivors = events
+        .iter()
+        .filter(|x| matches!(x.event, GameEvent::PlayerSafe { .. }))
+        .count();
+    stats.star_purchases = events
+        .iter()
+        .filter(|x| matches!(x.event, GameEvent::StarPurchased { .. }))
+        .count();
+    Ok(stats)
+}
+
+/// Find output directories of games under the directory, including itself.
+pub fn find_games(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    if dir.join(GAME_LOG_FILE).exists() {
+        return Ok(vec![dir.to_path_buf()]);
+    }
+    let mut games = vec![];
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        if entry.path().is_dir() {
+            games.extend(find_games(entry.path())?);
+        }
+    }
+    Ok(games.into_iter().sorted().collect())
+}
+
+impl Analysis {
+    pub fn new(games: Vec<GameStats>) -> Self {
+        let sum = |f: fn(&GameStats) -> usize| games.iter().map(f).sum::<usize>();
+        let mean = |f: fn(&GameStats) -> f64| match games.len() {
+            0 => 0.0,
+            n => games.iter().map(f).sum::<f64>() / n as f64,
+        };
+
+        let mut cards_played = Cards::new();
+        for card in games.iter().flat_map(|x| &x.cards) {
+            for (card, &count) in &card.played {
+                *cards_played.entry(card.clone()).or_default() += count;
+            }
+        }
+
+        let rounds = games
+            .iter()
+            .map(|x| x.survival.len())
+            .max()
+            .unwrap_or_default();
+        let survival_curve = (0..rounds)
+            .map(|round| {
+                let rates = games
+                    .iter()
+                    .filter_map(|x| Some(ratio(x.survival.get(round)?.alive, x.players)))
+                    .collect_vec();
+                rates.iter().sum::<f64>() / rates.len().max(1) as f64
+            })
+            .collect();
+
+        let finished = sum(|x| x.tables - x.failed_tables);
+        let overall = CrossGameStats {
+            games: games.len(),
+            tables: sum(|x| x.tables),
+            acceptance_rate: ratio(sum(|x| x.trades_accepted), finished),
+            intention_match_rate: ratio(sum(|x| x.kept_intentions), sum(|x| x.stated_intentions)),
+            survival_rate: ratio(sum(|x| x.survivors), sum(|x| x.players)),
+            mean_final_coin_gini: mean(|x| x.final_coin_gini),
+            mean_star_purchases: mean(|x| x.star_purchases as f64),
+            cards_played,
+            survival_curve,
+        };
+        Self { games, overall }
+    }
+
+    /// Per-game statistics, one row per game.
+    pub fn games_csv(&self) -> String {
+        let mut csv = String::from(
+            "game,dir,players,rounds,tables,failed_tables,duels,trades_accepted,acceptance_rate,stated_intentions,kept_intentions,intention_match_rate,survivors,final_coin_gini,star_purchases\n",
+        );
+        for (index, x) in self.games.iter().enumerate() {
+            let _ = writeln!(
+                csv,
+                "{index},{},{},{},{},{},{},{},{:.4},{},{},{:.4},{},{:.4},{}",
+                quote(&x.dir.display().to_string()),
+                x.players,
+                x.rounds,
+                x.tables,
+                x.failed_tables,
+                x.duels,
+                x.trades_accepted,
+                x.acceptance_rate,
+                x.stated_intentions,
+                x.kept_intentions,
+                x.intention_match_rate,
+                x.survivors,
+                x.final_coin_gini,
+                x.star_purchases
+            );
+        }
+        csv
+    }
+
+    /// Cards played and held by everyone in each round of each game.
+    pub fn cards_csv(&self) -> String {
+        let mut csv = String::from("game,round,card,played,played_share,public,public_share\n");
+        for (index, game) in self.games.iter().enumerate() {
+            for round in &game.cards {
+                let played: usize = round.played.values().sum();
+                let public: usize = round.public.values().sum();
+                let cards = round
+                    .played
+                    .keys()
+                    .chain(round.public.keys())
+                    .unique()
+                    .sorted();
+                for card in cards {
+                    let x = round.played.get(card).copied().unwrap_or_default();
+                    let y = round.public.get(card).copied().unwrap_or_default();
+                    let _ = writeln!(
+                        csv,
+                        "{index},{},{card},{x},{:.4},{y},{:.4}",
+                        round.round,
+                        ratio(x, played),
+                        ratio(y, public)
+                    );
+                }
+            }
+        }
+        csv
+    }
+
+    /// Players alive and safe after each round of each game.
+    pub fn survival_csv(&self) -> String {
+        let mut csv = String::from("game,round,players,alive,safe\n");
+        for (index, game) in self.games.iter().enumerate() {
+            for round in &game.survival {
+                let _ = writeln!(
+                    csv,
+                    "{index},{},{},{},{}",
+                    round.round, game.players, round.alive, round.safe
+                );
+            }
+        }
+        csv
+    }
+
+    /// Write `analysis.json`, `games.csv`, `cards.csv` and `survival.csv` into the directory.
+    pub fn write(&self, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join("analysis.json"), serde_json::to_vec_pretty(self)?)?;
+        std::fs::write(dir.join("games.csv"), self.games_csv())?;
+        std::fs::write(dir.join("cards.csv"), self.cards_csv())?;
+        std::fs::write(dir.join("survival.csv"), self.survival_csv())?;
+        Ok(())
+    }
+}
+
+pub fn run(args: AnalyzeArgs) -> Result<()> {
+    let mut dirs = vec![];
+    for dir in &args.dirs {
+        dirs.extend(find_games(dir)?);
+    }
+    if dirs.is_empty() {
+        bail!("no game outputs found in {:?}", args.dirs);
+    }
+
+    let mut games = vec![];
+    for dir in dirs {
+        match analyze_game(&dir) {
+            Ok(stats) => games.push(stats),
+            Err(err) => eprintln!("skip {}: {err:#}", dir.display()),
+        }
+    }
+    let analysis = Analysis::new(games);
+    analysis.write(&args.out)?;
+
+    let overall = &analysis.overall;
+    println!(
+        "{} games, {} tables, {:.1}% trades accepted, {:.1}% stated intentions kept, {:.1}% players survive",
+        overall.games,
+        overall.tables,
+        100.0 * overall.acceptance_rate,
+        100.0 * overall.intention_match_rate,
+        100.0 * overall.survival_rate
+    );
+    println!(
+        "mean final coin Gini {:.3}, mean star purchases {:.2}",
+        overall.mean_final_coin_gini, overall.mean_star_purchases
+    );
+    println!("written to {}", args.out.display());
+    Ok(())
+}
@@ -0,0 +1,659 @@
+use std::{
+    fmt::Debug,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use async_lock::{Semaphore, SemaphoreGuard};
+use async_std::{future::timeout, sync::Mutex, task::sleep};
+use bevy::{
+    prelude::*,
+    utils::{BoxedFuture, ConditionalSend},
+};
+use clap::ValueEnum;
+use itertools::Itertools;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    llm::{
+        Choice, ChooseItem, ChooseRequest, ChooseResponse, CompletionRequest, CompletionResponse,
+        SamplerKind,
+    },
+    Settings,
+};
+
+/// A server that completes prompts and ranks choices for [`LlmActor`](crate::llm::LlmActor).
+pub trait LlmBackend: ConditionalSend + Sync + Debug + 'static {
+    /// Continue the prompt.
+    fn complete<'a>(
+        &'a self,
+        request: &'a CompletionRequest,
+    ) -> BoxedFuture<'a, Result<CompletionResponse>>;
+
+    /// Rank the choices as continuations of the prompt, the most likely first.
+    fn choose<'a>(&'a self, request: &'a ChooseRequest) -> BoxedFuture<'a, Result<ChooseResponse>>;
+}
+
+/// Kinds of LLM servers to play with.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Reflect, Serialize, Deserialize,
+)]
+pub enum BackendKind {
+    /// Ai00 server, using `/api/oai/completions` and `/api/oai/chooses`.
+    #[default]
+    Ai00,
+    /// Any OpenAI-compatible server, using `/v1/chat/completions`.
+    Openai,
+}
+
+/// How the OpenAI-compatible backend ranks choices.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Reflect, Serialize, Deserialize,
+)]
+pub enum ChooseMode {
+    /// Score choices by the log probabilities of the first token, then fall back to `constrained`.
+    #[default]
+    Logprobs,
+    /// Ask the model to answer with one of the choices.
+    Constrained,
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum LlmError {
+    #[error("request timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("request failed: {0}")]
+    Request(String),
+    #[error("empty response")]
+    Empty,
+    #[error("cannot parse the response: {0}")]
+    Parse(String),
+    #[error("gave up after {attempts} attempt(s), last error: {last}")]
+    Exhausted {
+        attempts: usize,
+        last: Box<LlmError>,
+    },
+}
+
+/// How many times and how patiently to send a request to the LLM.
+#[derive(Debug, Clone, Copy, PartialEq, Reflect, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Maximum attempts before giving up.
+    pub max_attempts: usize,
+    /// Time to wait after the first failure, doubled after each following failure.
+    pub backoff: Duration,
+    /// Maximum time to wait between attempts.
+    pub max_backoff: Duration,
+    /// Time to wait for each response.
+    pub timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Send the request until it succeeds or the attempts run out.
+    ///
+    /// Each attempt goes through the limiter on its own, so that retries keep to the rate limit,
+    /// and players waiting to retry do not hold slots in flight meanwhile.
+    pub async fn run<T, F, Fut>(
+        &self,
+        head: impl AsRef<str>,
+        limiter: &LlmLimiter,
+        mut request: F,
+    ) -> Result<T, LlmError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, LlmError>>,
+    {
+        let head = head.as_ref();
+        let mut backoff = self.backoff;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let permit = limiter.acquire().await;
+            let result = match timeout(self.timeout, request()).await {
+                Ok(result) => result,
+                Err(_) => Err(LlmError::Timeout(self.timeout)),
+            };
+            drop(permit);
+            match result {
+                Ok(value) => break Ok(value),
+                Err(err) if attempts >= self.max_attempts => {
+                    let last = Box::new(err);
+                    break Err(LlmError::Exhausted { attempts, last });
+                }
+                Err(err) => {
+                    bevy::log::warn!(
+                        "{head} attempt {attempts} failed: {err}, retry in {backoff:?}"
+                    );
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.max_backoff);
+                }
+            }
+        }
+    }
+}
+
+/// Limits the LLM traffic of all players sharing it, both in requests in flight and requests per second.
+/// The default limiter lets everything through.
+#[derive(Debug, Default)]
+pub struct LlmLimiter {
+    semaphore: Option<Semaphore>,
+    interval: Option<Duration>,
+    /// The earliest time the next request may be sent.
+    next: Mutex<Option<Instant>>,
+    waiting: AtomicUsize,
+    in_flight: AtomicUsize,
+    completed: AtomicUsize,
+    /// Total time completed requests spent in flight, in microseconds.
+    latency: AtomicU64,
+}
+
+/// A snapshot of the traffic through a [`LlmLimiter`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LlmTraffic {
+    /// Requests queued for a permit.
+    pub waiting: usize,
+    /// Requests holding a permit.
+    pub in_flight: usize,
+    /// Requests finished so far, counting each attempt.
+    pub completed: usize,
+    /// Total time the finished requests spent in flight.
+    pub latency: Duration,
+}
+
+impl LlmTraffic {
+    /// Average time a finished request spent in flight.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        (self.completed > 0).then(|| self.latency / self.completed as u32)
+    }
+}
+
+impl LlmLimiter {
+    /// Zero stands for no limit in both arguments.
+    pub fn new(max_in_flight: usize, requests_per_second: f64) -> Self {
+        let semaphore = (max_in_flight > 0).then(|| Semaphore::new(max_in_flight));
+        let interval =
+            (requests_per_second > 0.0).then(|| Duration::from_secs_f64(1.0 / requests_per_second));
+        Self {
+            semaphore,
+            interval,
+            ..Default::default()
+        }
+    }
+
+    /// Wait for a slot to send a request. The request is in flight until the permit drops.
+    pub async fn acquire(&self) -> LlmPermit<'_> {
+        let waiting = Waiting::new(&self.waiting);
+        let guard = match &self.semaphore {
+            Some(semaphore) => Some(semaphore.acquire().await),
+            None => None,
+        };
+        if let Some(interval) = self.interval {
+            let delay = {
+                let mut next = self.next.lock().await;
+                let now = Instant::now();
+                let slot = next.map_or(now, |next| next.max(now));
+                *next = Some(slot + interval);
+                slot - now
+            };
+            sleep(delay).await;
+        }
+        drop(waiting);
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        LlmPermit {
+            limiter: self,
+            start: Instant::now(),
+            _guard: guard,
+        }
+    }
+
+    pub fn traffic(&self) -> LlmTraffic {
+        LlmTraffic {
+            waiting: self.waiting.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            latency: Duration::from_micros(self.latency.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Counts a request as waiting until dropped, even if the waiting future is cancelled.
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl<'a> Waiting<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Permission to send a request to the LLM, see [`LlmLimiter::acquire`].
+#[derive(Debug)]
+pub struct LlmPermit<'a> {
+    limiter: &'a LlmLimiter,
+    start: Instant,
+    _guard: Option<SemaphoreGuard<'a>>,
+}
+
+impl Drop for LlmPermit<'_> {
+    fn drop(&mut self) {
+        self.limiter.in_flight.fetch_sub(1, Ordering::Relaxed);
+        let latency = self.start.elapsed().as_micros() as u64;
+        self.limiter.latency.fetch_add(latency, Ordering::Relaxed);
+        self.limiter.completed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl BackendKind {
+    pub fn build(self, settings: &Settings) -> Arc<dyn LlmBackend> {
+        match self {
+            BackendKind::Ai00 => Arc::new(Ai00Backend::new(settings.url.clone())),
+            BackendKind::Openai => Arc::new(OpenAiBackend {
+                url: settings.url.clone(),
+                model: settings.model.clone(),
+                api_key: settings.api_key.clone(),
+                choose_mode: settings.choose_mode,
+            }),
+        }
+    }
+}
+
+/// Post a JSON request and parse the JSON response.
+pub async fn fetch_json<T: DeserializeOwned>(
+    url: impl ToString,
+    request: &impl Serialize,
+    api_key: Option<&str>,
+) -> Result<T> {
+    async_std::task::yield_now().await;
+    let mut request = ehttp::Request::json(url, request)?;
+    if let Some(key) = api_key {
+        request
+            .headers
+            .insert("Authorization", format!("Bearer {key}"));
+    }
+    let response = ehttp::fetch_async(request)
+        .await
+        .map_err(|err| anyhow::anyhow!(err))?;
+    if !response.ok {
+        bail!(
+            "{} {}: {}",
+            response.status,
+            response.status_text,
+            response.text().unwrap_or_default()
+        );
+    }
+    Ok(response.json()?)
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Ai00Backend {
+    pub url: String,
+}
+
+impl Ai00Backend {
+    pub fn new(url: impl ToString) -> Self {
+        let url = url.to_string();
+        Self { url }
+    }
+}
+
+impl LlmBackend for Ai00Backend {
+    fn complete<'a>(
+        &'a self,
+        request: &'a CompletionRequest,
+    ) -> BoxedFuture<'a, Result<CompletionResponse>> {
+        let url = format!("{}/api/oai/completions", self.url);
+        Box::pin(fetch_json(url, request, None))
+    }
+
+    fn choose<'a>(&'a self, request: &'a ChooseRequest) -> BoxedFuture<'a, Result<ChooseResponse>> {
+        let url = format!("{}/api/oai/chooses", self.url);
+        Box::pin(fetch_json(url, request, None))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn new(role: impl ToString, content: impl ToString) -> Self {
+        let role = role.to_string();
+        let content = content.to_string();
+        Self { role, content }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ChatCompletionRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    pub presence_penalty: f32,
+    pub frequency_penalty: f32,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub logprobs: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_logprobs: Option<u32>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatCompletionResponse {
+    pub model: String,
+    pub choices: Vec<ChatCompletionChoice>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatCompletionChoice {
+    pub index: u32,
+    pub message: ChatMessage,
+    pub logprobs: Option<ChatLogprobs>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatLogprobs {
+    pub content: Vec<TokenLogprob>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f32,
+    pub top_logprobs: Vec<TokenLogprob>,
+}
+
+/// A backend for servers implementing the OpenAI `/v1/chat/completions` API,
+/// e.g., llama.cpp and vLLM.
+///
+/// Prompts are sent as a user message to be continued. Ai00-specific fields of the request
+/// (`state`, `bias` and `bnf_schema`) are ignored.
+#[derive(Debug, Default, Clone)]
+pub struct OpenAiBackend {
+    pub url: String,
+    pub model: String,
+    pub api_key: Option<String>,
+    pub choose_mode: ChooseMode,
+}
+
+impl OpenAiBackend {
+    /// Number of alternatives requested for each token when scoring choices.
+    pub const NUM_TOP_LOGPROBS: u32 = 20;
+    /// Most stop sequences OpenAI accepts; the text is cut at the others after the response.
+    pub const MAX_STOP: usize = 4;
+
+    pub fn messages(prompt: impl AsRef<str>) -> Vec<ChatMessage> {
+        vec![
+            ChatMessage::new("system", include_str!("prompts/openai_system.md")),
+            ChatMessage::new("user", prompt.as_ref()),
+        ]
+    }
+
+    async fn chat_completions(
+        &self,
+        request: &ChatCompletionRequest,
+    ) -> Result<ChatCompletionResponse> {
+        let url = format!("{}/v1/chat/completions", self.url);
+        fetch_json(url, request, self.api_key.as_deref()).await
+    }
+
+    /// The chat completion request sent to continue the prompt.
+    pub fn chat_request(&self, request: &CompletionRequest) -> ChatCompletionRequest {
+        let sampler = &request.sampler;
+        let top_p = match sampler.kind {
+            SamplerKind::Nucleus => Some(sampler.top_p),
+            SamplerKind::Typical => None,
+        };
+        ChatCompletionRequest {
+            model: self.model.clone(),
+            messages: Self::messages(&request.prompt),
+            stop: request.stop.iter().take(Self::MAX_STOP).cloned().collect(),
+            max_tokens: request.max_tokens,
+            temperature: sampler.temperature,
+            top_p,
+            presence_penalty: sampler.presence_penalty,
+            frequency_penalty: sampler.frequency_penalty,
+            ..Default::default()
+        }
+    }
+
+    async fn complete(&self, request: &CompletionRequest) -> Result<CompletionResponse> {
+        let chat = self.chat_request(request);
+        let response = self.chat_completions(&chat).await?;
+
+        let choices = response
+            .choices
+            .into_iter()
+            .map(|choice| Choice {
+                index: choice.index,
+                text: truncate_at_stop(&choice.message.content, &request.stop).to_owned(),
+            })
+            .collect();
+        let model = response.model;
+        Ok(CompletionResponse { choices, model })
+    }
+
+    async fn choose(&self, request: &ChooseRequest) -> Result<ChooseResponse> {
+        if self.choose_mode == ChooseMode::Logprobs {
+            match self.choose_logprobs(request).await? {
+                Some(response) => return Ok(response),
+                None => bevy::log::debug!(
+                    "logprobs do not tell the choices apart, asking for one instead"
+                ),
+            }
+        }
+        self.choose_constrained(request).await
+    }
+
+    /// Score each choice by the best log probability of a first token that starts it.
+    /// Returns `None` if no choice is found among the top tokens, or if several share the best score.
+    async fn choose_logprobs(&self, request: &ChooseRequest) -> Result<Option<ChooseResponse>> {
+        let chat = ChatCompletionRequest {
+            model: self.model.clone(),
+            messages: Self::messages(&request.prompt),
+            max_tokens: 1,
+            temperature: 0.0,
+            logprobs: true,
+            top_logprobs: Some(Self::NUM_TOP_LOGPROBS),
+            ..Default::default()
+        };
+        let response = self.chat_completions(&chat).await?;
+        let tokens = response
+            .choices
+            .first()
+            .and_then(|choice| choice.logprobs.as_ref())
+            .and_then(|logprobs| logprobs.content.first())
+            .map(|token| match token.top_logprobs.is_empty() {
+                true => vec![token.clone()],
+                false => token.top_logprobs.clone(),
+            })
+            .unwrap_or_default();
+        Ok(Self::rank_by_logprobs(&request.choices, &tokens))
+    }
+
+    /// Rank choices by the first tokens in `tokens` that start them; see [`Self::choose_logprobs`].
+    pub fn rank_by_logprobs(choices: &[String], tokens: &[TokenLogprob]) -> Option<ChooseResponse> {
+        let scores = choices
+            .iter()
+            .map(|choice| {
+                let choice = choice.trim().to_lowercase();
+                tokens
+                    .iter()
+                    .filter(|token| {
+                        let token = token.token.trim().to_lowercase();
+                        !token.is_empty() && choice.starts_with(&token)
+                    })
+                    .map(|token| token.logprob)
+                    .max_by(f32::total_cmp)
+            })
+            .collect_vec();
+        let scores = scores
+            .into_iter()
+            .map(|score| score.unwrap_or(f32::NEG_INFINITY))
+            .collect_vec();
+
+        // e.g., " I would" and " I wouldn't" both start with "I", and only the first would ever win
+        let best = scores.iter().copied().max_by(f32::total_cmp)?;
+        if best == f32::NEG_INFINITY || scores.iter().filter(|&&x| x == best).count() > 1 {
+            return None;
+        }
+
+        let order = (0..scores.len())
+            .sorted_by(|&x, &y| scores[y].total_cmp(&scores[x]))
+            .collect_vec();
+        let data = order
+            .into_iter()
+            .enumerate()
+            .map(|(rank, index)| ChooseItem {
+                choice: choices[index].clone(),
+                index,
+                rank,
+                perplexity: (-scores[index]).exp(),
+            })
+            .collect();
+        Some(ChooseResponse { data })
+    }
+
+    /// Ask the model to answer with one of the choices, and rank the one it names first;
+    /// see [`Self::rank_by_answer`].
+    async fn choose_constrained(&self, request: &ChooseRequest) -> Result<ChooseResponse> {
+        let choices = request
+            .choices
+            .iter()
+            .map(|x| format!("\"{x}\""))
+            .join(", ");
+        let prompt = format!(
+            include_str!("prompts/openai_choose.md"),
+            prompt = request.prompt,
+            choices = choices
+        );
+        let chat = ChatCompletionRequest {
+            model: self.model.clone(),
+            messages: Self::messages(prompt),
+            max_tokens: 16,
+            temperature: 0.0,
+            ..Default::default()
+        };
+        let response = self.chat_completions(&chat).await?;
+        let text = response
+            .choices
+            .first()
+            .map(|choice| choice.message.content.clone())
+            .unwrap_or_default();
+        match Self::rank_by_answer(&request.choices, &text) {
+            Some(response) => Ok(response),
+            None => Err(LlmError::Parse(format!("no choice in the answer {text:?}")).into()),
+        }
+    }
+
+    /// Rank choices by where the answer names them, as whole words, or `None` if it names none.
+    /// An answer that is exactly a choice names only that one, e.g., "10" does not name "1".
+    pub fn rank_by_answer(choices: &[String], answer: &str) -> Option<ChooseResponse> {
+        let normalize = |x: &str| {
+            x.trim_matches(|c: char| c.is_whitespace() || c.is_ascii_punctuation())
+                .to_lowercase()
+        };
+        let answer = normalize(answer);
+        let exact = choices
+            .iter()
+            .position(|choice| normalize(choice) == answer);
+
+        let position = |index: usize| {
+            let choice = normalize(&choices[index]);
+            match exact {
+                Some(exact) => (exact == index).then_some(0),
+                None if choice.is_empty() => None,
+                None => answer.match_indices(&choice).map(|(x, _)| x).find(|&x| {
+                    let before = answer[..x].chars().next_back();
+                    let after = answer[x + choice.len()..].chars().next();
+                    [before, after]
+                        .into_iter()
+                        .flatten()
+                        .all(|c| !c.is_alphanumeric())
+                }),
+            }
+        };
+        let positions = (0..choices.len()).map(position).collect_vec();
+        if positions.iter().all(Option::is_none) {
+            return None;
+        }
+
+        // at the same position, the longer choice is the one named, e.g., "I would like to" over "I"
+        let order = (0..choices.len())
+            .sorted_by_key(|&index| {
+                let length = std::cmp::Reverse(choices[index].trim().len());
+                (positions[index].unwrap_or(usize::MAX), length)
+            })
+            .collect_vec();
+        let data = order
+            .into_iter()
+            .enumerate()
+            .map(|(rank, index)| ChooseItem {
+                choice: choices[index].clone(),
+                index,
+                rank,
+                perplexity: match positions[index] {
+                    Some(_) => 1.0,
+                    None => f32::INFINITY,
+                },
+            })
+            .collect();
+        Some(ChooseResponse { data })
+    }
+}
+
+impl LlmBackend for OpenAiBackend {
+    fn complete<'a>(
+        &'a self,
+        request: &'a CompletionRequest,
+    ) -> BoxedFuture<'a, Result<CompletionResponse>> {
+        Box::pin(self.complete(request))
+    }
+
+    fn choose<'a>(&'a self, request: &'a ChooseRequest) -> BoxedFuture<'a, Result<ChooseResponse>> {
+        Box::pin(self.choose(request))
+    }
+}
+
+/// Cut the text at the first stop sequence, in case the server ignores some of them.
+fn truncate_at_stop<'a>(text: &'a str, stop: &[String]) -> &'a str {
+    let end = stop
+        .iter()
+        .filter(|x| !x.is_empty())
+        .filter_map(|x| text.find(x.as_str()))
+        .min()
+        .unwrap_or(text.len());
+    &text[..end]
+}
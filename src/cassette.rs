@@ -0,0 +1,204 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+use async_std::{fs::File, io::WriteExt, sync::Mutex};
+use bevy::{prelude::*, utils::BoxedFuture};
+use clap::ValueEnum;
+use itertools::Itertools;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    backend::LlmBackend,
+    llm::{ChooseRequest, ChooseResponse, CompletionRequest, CompletionResponse},
+};
+
+/// Whether to record the LLM traffic into the cassette, or to replay it from there.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Reflect, Serialize, Deserialize,
+)]
+pub enum CassetteMode {
+    /// Forward requests to the LLM server and record the responses.
+    #[default]
+    Record,
+    /// Answer requests with the recorded responses, without any server.
+    Replay,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TapeKind {
+    Complete,
+    Choose,
+}
+
+impl Display for TapeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TapeKind::Complete => write!(f, "complete"),
+            TapeKind::Choose => write!(f, "choose"),
+        }
+    }
+}
+
+/// A request and its response, one JSON line in the cassette file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tape {
+    pub hash: String,
+    pub kind: TapeKind,
+    pub request: Value,
+    pub response: Value,
+}
+
+/// Hash of the request, independent of the order of fields and map entries.
+pub fn request_hash(kind: TapeKind, request: &impl Serialize) -> Result<String> {
+    fn canonical(value: &Value) -> String {
+        match value {
+            Value::Array(items) => format!("[{}]", items.iter().map(canonical).join(",")),
+            Value::Object(map) => {
+                let entries = map
+                    .iter()
+                    .sorted_by(|x, y| x.0.cmp(y.0))
+                    .map(|(key, value)| {
+                        format!("{}:{}", Value::from(key.as_str()), canonical(value))
+                    })
+                    .join(",");
+                format!("{{{entries}}}")
+            }
+            value => value.to_string(),
+        }
+    }
+
+    // 64-bit FNV-1a, stable across platforms and compiler versions
+    let text = format!("{kind}{}", canonical(&serde_json::to_value(request)?));
+    let hash = text.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    Ok(format!("{hash:016x}"))
+}
+
+/// A backend that records the traffic of another backend into a cassette file, or replays it from there.
+///
+/// Identical requests are answered in the order they are recorded.
+#[derive(Debug)]
+pub struct CassetteBackend {
+    /// The backend to record, or `None` to replay.
+    inner: Option<Arc<dyn LlmBackend>>,
+    file: Option<Mutex<File>>,
+    tapes: Mutex<HashMap<String, VecDeque<Value>>>,
+}
+
+impl CassetteBackend {
+    /// Record the traffic of `inner`, overwriting the cassette file.
+    pub fn record(inner: Arc<dyn LlmBackend>, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("cannot create cassette {}", path.display()))?;
+        Ok(Self {
+            inner: Some(inner),
+            file: Some(Mutex::new(file.into())),
+            tapes: Default::default(),
+        })
+    }
+
+    /// Load the cassette file to replay.
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read cassette {}", path.display()))?;
+        let mut tapes: HashMap<_, VecDeque<_>> = HashMap::new();
+        for line in text.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let tape: Tape = serde_json::from_str(line)?;
+            tapes.entry(tape.hash).or_default().push_back(tape.response);
+        }
+        Ok(Self {
+            inner: None,
+            file: None,
+            tapes: Mutex::new(tapes),
+        })
+    }
+
+    async fn record_tape(
+        &self,
+        kind: TapeKind,
+        hash: String,
+        request: &impl Serialize,
+        response: &impl Serialize,
+    ) -> Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let tape = Tape {
+            hash,
+            kind,
+            request: serde_json::to_value(request)?,
+            response: serde_json::to_value(response)?,
+        };
+        let mut line = serde_json::to_vec(&tape)?;
+        line.push(b'\n');
+
+        let mut file = file.lock().await;
+        file.write_all(&line).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    async fn replay_tape<T: DeserializeOwned>(&self, kind: TapeKind, hash: String) -> Result<T> {
+        let response = self
+            .tapes
+            .lock()
+            .await
+            .get_mut(&hash)
+            .and_then(|responses| responses.pop_front());
+        match response {
+            Some(response) => Ok(serde_json::from_value(response)?),
+            None => bail!("no recorded response for {kind} request {hash}"),
+        }
+    }
+}
+
+impl LlmBackend for CassetteBackend {
+    fn complete<'a>(
+        &'a self,
+        request: &'a CompletionRequest,
+    ) -> BoxedFuture<'a, Result<CompletionResponse>> {
+        Box::pin(async move {
+            let kind = TapeKind::Complete;
+            let hash = request_hash(kind, request)?;
+            match &self.inner {
+                Some(inner) => {
+                    let response = inner.complete(request).await?;
+                    self.record_tape(kind, hash, request, &response).await?;
+                    Ok(response)
+                }
+                None => self.replay_tape(kind, hash).await,
+            }
+        })
+    }
+
+    fn choose<'a>(&'a self, request: &'a ChooseRequest) -> BoxedFuture<'a, Result<ChooseResponse>> {
+        Box::pin(async move {
+            let kind = TapeKind::Choose;
+            let hash = request_hash(kind, request)?;
+            match &self.inner {
+                Some(inner) => {
+                    let response = inner.choose(request).await?;
+                    self.record_tape(kind, hash, request, &response).await?;
+                    Ok(response)
+                }
+                None => self.replay_tape(kind, hash).await,
+            }
+        })
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::{
+    bevy_egui::{EguiContexts, EguiPlugin},
+    egui::{self, Color32, RichText},
+};
+use itertools::Itertools;
+
+use crate::{
+    event::GameEvent,
+    game::{
+        Cards, DuelProgress, DuelResult, GameSet, Inventory, PlayerDead, PlayerSafe, PlayerTimer,
+        PublicState, Table,
+    },
+    replay::{describe_stake, describe_trade, ReplayTable},
+};
+
+/// Most lines kept in the feed.
+pub const FEED_CAPACITY: usize = 200;
+
+/// An egui dashboard of the game: players as tokens, tables with their live chat and duels,
+/// a feed of what happens, and the hologram showing cards left on the ship.
+#[derive(Debug, Default)]
+pub struct DashboardPlugin;
+
+impl Plugin for DashboardPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(EguiPlugin);
+        }
+        app.add_plugins(DashboardFeedPlugin)
+            .add_systems(Update, draw_dashboard.after(update_feed));
+    }
+}
+
+/// Collects game events into the [`DashboardFeed`], without drawing anything.
+#[derive(Debug, Default)]
+pub struct DashboardFeedPlugin;
+
+impl Plugin for DashboardFeedPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DashboardFeed>()
+            .add_event::<GameEvent>()
+            // read the events of the frame the game ends in, before the app exits
+            .add_systems(Update, update_feed.after(GameSet::GameOver));
+    }
+}
+
+/// Recent game events as lines of text, oldest first.
+#[derive(Debug, Default, Clone, Deref, DerefMut, Resource)]
+pub struct DashboardFeed(pub VecDeque<String>);
+
+impl DashboardFeed {
+    pub fn push(&mut self, line: String) {
+        if self.0.len() >= FEED_CAPACITY {
+            self.0.pop_front();
+        }
+        self.0.push_back(line);
+    }
+}
+
+pub(crate) fn update_feed(
+    mut feed: ResMut<DashboardFeed>,
+    mut events: EventReader<GameEvent>,
+    names: Query<&Name>,
+) {
+    let name = |entity: Entity| {
+        names
+            .get(entity)
+            .map(|x| x.to_string())
+            .unwrap_or_else(|_| format!("{entity}"))
+    };
+    for event in events.read() {
+        let line = match event {
+            GameEvent::DuelFinished {
+                players, result, ..
+            } => {
+                let [p0, p1] = players.map(name);
+                match result {
+                    DuelResult::Win(x, y) => format!("{p0} ({x}) beats {p1} ({y})"),
+                    DuelResult::Lose(x, y) => format!("{p1} ({y}) beats {p0} ({x})"),
+                    DuelResult::Tie(x) => format!("{p0} and {p1} tie with {x}"),
+                }
+            }
+            GameEvent::TableFailed { players, error, .. } => {
+                let [p0, p1] = players.map(name);
+                format!("table ({p0}, {p1}) fails: {error}")
+            }
+            GameEvent::PlayerDied { player } => format!("{} is eliminated", name(*player)),
+            GameEvent::PlayerSafe { player } => format!("{} is safe", name(*player)),
+            GameEvent::StarPurchased {
+                buyer,
+                seller,
+                price,
+            } => format!(
+                "{} buys a star from {} for {price} coins",
+                name(*buyer),
+                name(*seller)
+            ),
+            GameEvent::GameOver => "game over".into(),
+            _ => continue,
+        };
+        feed.push(line);
+    }
+}
+
+pub(crate) fn describe_cards(cards: &Cards) -> String {
+    cards
+        .iter()
+        .map(|(card, count)| format!("{card} {count}"))
+        .join("  ")
+}
+
+/// A player shown as a token.
+fn player_token(
+    ui: &mut egui::Ui,
+    name: &str,
+    inventory: &Inventory,
+    timer: Option<&PlayerTimer>,
+    status: &str,
+    fill: Color32,
+) {
+    egui::Frame::group(ui.style()).fill(fill).show(ui, |ui| {
+        ui.set_width(150.0);
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                ui.strong(name);
+                ui.label(RichText::new(status).small());
+            });
+            ui.label(format!(
+                "{} stars  {} coins",
+                inventory.star, inventory.coin
+            ));
+            ui.label(RichText::new(describe_cards(&inventory.cards)).small());
+            if let Some(timer) = timer {
+                ui.label(RichText::new(format!("{} rounds left", timer.0)).small());
+            }
+        });
+    });
+}
+
+#[allow(clippy::type_complexity)]
+fn draw_dashboard(
+    mut contexts: EguiContexts,
+    state: Option<Res<PublicState>>,
+    feed: Res<DashboardFeed>,
+    players: Query<(
+        Entity,
+        &Name,
+        &Inventory,
+        Option<&PlayerTimer>,
+        Has<PlayerDead>,
+        Has<PlayerSafe>,
+    )>,
+    tables: Query<(Entity, &Table, Option<&DuelProgress>, Option<&ReplayTable>)>,
+) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+    let name = |entity: Entity| {
+        players
+            .get(entity)
+            .map(|x| x.1.to_string())
+            .unwrap_or_else(|_| format!("{entity}"))
+    };
+    let seated = tables
+        .iter()
+        .flat_map(|(_, table, ..)| table.0)
+        .collect_vec();
+
+    // the hologram shows the cards left among all players, as players see in `PublicState`
+    let state = match state {
+        Some(state) => state.clone(),
+        None => {
+            let mut state = PublicState {
+                player: players.iter().len(),
+                ..default()
+            };
+            for (_, _, inventory, ..) in &players {
+                for (card, &count) in &inventory.cards {
+                    *state.cards.entry(card.clone()).or_default() += count;
+                }
+            }
+            state
+        }
+    };
+    egui::SidePanel::right("hologram").show(ctx, |ui| {
+        ui.heading("Hologram");
+        ui.label(format!("{} players", state.player));
+        let total = state.total_cards();
+        ui.label(format!("{total} cards left"));
+        for (card, &count) in &state.cards {
+            let fraction = match total {
+                0 => 0.0,
+                total => count as f32 / total as f32,
+            };
+            ui.add(egui::ProgressBar::new(fraction).text(format!("{card} {count}")));
+        }
+    });
+
+    egui::TopBottomPanel::bottom("feed")
+        .resizable(true)
+        .default_height(120.0)
+        .show(ctx, |ui| {
+            ui.heading("Feed");
+            egui::ScrollArea::vertical()
+                .stick_to_bottom(true)
+                .auto_shrink([false, false])
+                .show(ui, |ui| {
+                    for line in feed.iter() {
+                        ui.label(line);
+                    }
+                });
+        });
+
+    egui::SidePanel::left("players")
+        .default_width(340.0)
+        .show(ctx, |ui| {
+            ui.heading("Players");
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for (entity, name, inventory, timer, dead, safe) in
+                        players.iter().sorted_by_key(|x| x.0)
+                    {
+                        let (status, fill) = match (dead, safe, seated.contains(&entity)) {
+                            (true, _, _) => ("dead", Color32::from_rgb(90, 30, 30)),
+                            (_, true, _) => ("safe", Color32::from_rgb(30, 80, 40)),
+                            (_, _, true) => ("at table", Color32::from_rgb(30, 50, 90)),
+                            _ => ("", ui.style().visuals.faint_bg_color),
+                        };
+                        player_token(ui, name, inventory, timer, status, fill);
+                    }
+                });
+            });
+        });
+
+    egui::CentralPanel::default().show(ctx, |ui| {
+        ui.heading("Tables");
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (entity, table, progress, replay) in tables.iter().sorted_by_key(|x| x.0) {
+                let [p0, p1] = table.0.map(name);
+                egui::CollapsingHeader::new(format!("{p0} vs {p1}"))
+                    .id_salt(entity)
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        if let Some(replay) = replay {
+                            ui.label(replay.0.trim_end());
+                        }
+                        let Some(progress) = progress else {
+                            return;
+                        };
+                        let report = progress.report();
+                        let players = [&p0, &p1];
+                        for line in &report.chat {
+                            ui.label(format!("{}: {}", players[line.player], line.content));
+                        }
+                        if report
+                            .trades
+                            .iter()
+                            .any(|x| x.star + x.coin > 0 || !x.cards.is_empty())
+                            || report.accepted.iter().any(|&x| x)
+                        {
+                            let [t0, t1] = &report.trades;
+                            ui.label(format!(
+                                "offers: {p0} {} | {p1} {}",
+                                describe_trade(t0),
+                                describe_trade(t1)
+                            ));
+                        }
+                        for counter in &report.counters {
+                            let [t0, t1] = &counter.trades;
+                            ui.label(format!(
+                                "counter by {}: {p0} {} | {p1} {}",
+                                players[counter.player],
+                                describe_trade(t0),
+                                describe_trade(t1)
+                            ));
+                        }
+                        for line in &report.duel_chat {
+                            ui.label(format!("{}: {}", players[line.player], line.content));
+                        }
+                        if let Some([s0, s1]) = &report.stakes {
+                            ui.label(format!(
+                                "stakes: {p0} {} | {p1} {}",
+                                describe_stake(s0),
+                                describe_stake(s1)
+                            ));
+                        }
+                        if let Some(result) = &report.result {
+                            let card = |x: &Option<_>| {
+                                x.as_ref().map_or("refuses".into(), ToString::to_string)
+                            };
+                            let [c0, c1] = &report.cards;
+                            let winner = match result {
+                                DuelResult::Win(..) => format!("{p0} wins"),
+                                DuelResult::Lose(..) => format!("{p1} wins"),
+                                DuelResult::Tie(..) => "tie".into(),
+                            };
+                            ui.label(
+                                RichText::new(format!(
+                                    "{p0} draws {} | {p1} draws {}: {winner}",
+                                    card(c0),
+                                    card(c1)
+                                ))
+                                .strong(),
+                            );
+                        } else if report.stakes.is_some() {
+                            ui.label("cards face down...");
+                        }
+                    });
+            }
+        });
+    });
+}
@@ -0,0 +1,126 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    event::GameEvent,
+    game::{Cards, Player},
+    rules::Rules,
+};
+
+/// The public record of a player, built from the game events so that actors can model opponents.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Component, Reflect, Serialize, Deserialize)]
+#[reflect(Component, Default)]
+#[serde(default)]
+pub struct Dossier {
+    /// Duels played to the end.
+    pub duels: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub ties: usize,
+    /// Cards revealed in past duels.
+    pub cards: Cards,
+    /// Contracts signed.
+    pub signed: usize,
+    /// Contracts refused.
+    pub refused: usize,
+    /// Number of stakes placed.
+    pub stakes: usize,
+    /// Stars staked in total.
+    pub staked_star: usize,
+    /// Coins staked in total.
+    pub staked_coin: usize,
+}
+
+impl Dossier {
+    /// Mean stars and coins staked, if the player has ever placed a stake.
+    pub fn mean_stake(&self) -> Option<(f32, f32)> {
+        match self.stakes {
+            0 => None,
+            n => Some((
+                self.staked_star as f32 / n as f32,
+                self.staked_coin as f32 / n as f32,
+            )),
+        }
+    }
+
+    /// The record in words, unless there is nothing on it.
+    pub fn describe(&self, name: &str, rules: &Rules) -> Option<String> {
+        if self.duels + self.signed + self.refused == 0 {
+            return None;
+        }
+        let (star, coin) = self.mean_stake().unwrap_or_default();
+        Some(format!(
+            include_str!("prompts/dossier.md"),
+            name = name,
+            duels = self.duels,
+            wins = self.wins,
+            losses = self.losses,
+            ties = self.ties,
+            cards = rules.describe_cards(&self.cards),
+            signed = self.signed,
+            contracts = self.signed + self.refused,
+            star = star,
+            coin = coin,
+        ))
+    }
+}
+
+pub(crate) fn update_dossiers(
+    mut events: EventReader<GameEvent>,
+    mut dossiers: Query<&mut Dossier, With<Player>>,
+) {
+    for event in events.read() {
+        match event {
+            GameEvent::TradeAccepted {
+                players, accepted, ..
+            } => {
+                for (&player, &accepted) in players.iter().zip(accepted) {
+                    if let Ok(mut dossier) = dossiers.get_mut(player) {
+                        match accepted {
+                            true => dossier.signed += 1,
+                            false => dossier.refused += 1,
+                        }
+                    }
+                }
+            }
+            GameEvent::StakesPlaced {
+                players, stakes, ..
+            } => {
+                for (&player, stake) in players.iter().zip(stakes) {
+                    if let Ok(mut dossier) = dossiers.get_mut(player) {
+                        dossier.stakes += 1;
+                        dossier.staked_star += stake.star;
+                        dossier.staked_coin += stake.coin;
+                    }
+                }
+            }
+            // a card is only revealed if both players draw, otherwise it stays in hand
+            GameEvent::CardsDrawn {
+                players,
+                cards: [Some(lhs), Some(rhs)],
+                ..
+            } => {
+                for (&player, card) in players.iter().zip([lhs, rhs]) {
+                    if let Ok(mut dossier) = dossiers.get_mut(player) {
+                        *dossier.cards.entry(card.clone()).or_default() += 1;
+                    }
+                }
+            }
+            GameEvent::DuelFinished {
+                players, winner, ..
+            } => {
+                for &player in players {
+                    if let Ok(mut dossier) = dossiers.get_mut(player) {
+                        dossier.duels += 1;
+                        match winner {
+                            Some(winner) if *winner == player => dossier.wins += 1,
+                            Some(_) => dossier.losses += 1,
+                            None => dossier.ties += 1,
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use anyhow::{Context, Result};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::{ActorKind, Card, DuelResult, GameRound, Inventory, OutputDir, Stake, Trade};
+
+/// File name of the event log in the output directory.
+pub const GAME_EVENTS_FILE: &str = "events.jsonl";
+
+/// A fact of the game, for analysis tools to consume without scraping logs.
+///
+/// Players and tables are identified by their entity IDs; see [`GameEvent::PlayerJoined`] for names.
+#[derive(Debug, Clone, Event, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum GameEvent {
+    /// A player enters the game.
+    PlayerJoined {
+        player: Entity,
+        name: String,
+        actor: ActorKind,
+        inventory: Inventory,
+    },
+    /// Two players are put onto a table.
+    TableCreated { table: Entity, players: [Entity; 2] },
+    /// A player says something in the public chat before the trade, or before the duel.
+    ChatSent {
+        table: Entity,
+        players: [Entity; 2],
+        player: Entity,
+        content: String,
+        /// Whether the chat is before the duel rather than the trade.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        duel: bool,
+    },
+    /// Players propose what to give each other.
+    TradeProposed {
+        table: Entity,
+        players: [Entity; 2],
+        trades: [Trade; 2],
+    },
+    /// A player amends the contract; `trades` are what each player gives under the new one.
+    TradeCountered {
+        table: Entity,
+        players: [Entity; 2],
+        player: Entity,
+        trades: [Trade; 2],
+    },
+    /// Players sign or refuse the contract; the trade only happens if both sign.
+    TradeAccepted {
+        table: Entity,
+        players: [Entity; 2],
+        accepted: [bool; 2],
+    },
+    /// Players bet on the duel.
+    StakesPlaced {
+        table: Entity,
+        players: [Entity; 2],
+        stakes: [Stake; 2],
+    },
+    /// Players draw cards, or refuse the duel with `None`.
+    CardsDrawn {
+        table: Entity,
+        players: [Entity; 2],
+        cards: [Option<Card>; 2],
+    },
+    /// The duel ends, with the result for the first player.
+    DuelFinished {
+        table: Entity,
+        players: [Entity; 2],
+        winner: Option<Entity>,
+        result: DuelResult,
+        inventories: [Inventory; 2],
+    },
+    /// The duel fails, and the table is retried or forfeited depending on `--on-failure`.
+    TableFailed {
+        table: Entity,
+        players: [Entity; 2],
+        error: String,
+        retry: bool,
+    },
+    /// A player loses all stars.
+    PlayerDied { player: Entity },
+    /// A player has enough stars and no cards left.
+    PlayerSafe { player: Entity },
+    /// A player without cards buys a star from a survivor in the final trade.
+    StarPurchased {
+        buyer: Entity,
+        seller: Entity,
+        price: usize,
+    },
+    /// No more duels can take place.
+    GameOver,
+}
+
+impl GameEvent {
+    /// The table the event happens at, if any.
+    pub fn table(&self) -> Option<Entity> {
+        match self {
+            GameEvent::TableCreated { table, .. }
+            | GameEvent::ChatSent { table, .. }
+            | GameEvent::TradeProposed { table, .. }
+            | GameEvent::TradeCountered { table, .. }
+            | GameEvent::TradeAccepted { table, .. }
+            | GameEvent::StakesPlaced { table, .. }
+            | GameEvent::CardsDrawn { table, .. }
+            | GameEvent::DuelFinished { table, .. }
+            | GameEvent::TableFailed { table, .. } => Some(*table),
+            _ => None,
+        }
+    }
+}
+
+/// A [`GameEvent`] as written to the event log, one JSON line each.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameEventRecord {
+    /// Local time in RFC 3339, as of the frame the event is sent in.
+    pub time: String,
+    /// The round of the table, or the latest round for events outside tables.
+    pub round: usize,
+    #[serde(flatten)]
+    pub event: GameEvent,
+}
+
+/// Append game events to the event log in the output directory.
+pub(crate) fn write_game_events(
+    output: Option<Res<OutputDir>>,
+    round: Res<GameRound>,
+    mut events: EventReader<GameEvent>,
+    mut file: Local<Option<BufWriter<File>>>,
+    mut failed: Local<bool>,
+    mut tables: Local<HashMap<Entity, usize>>,
+) {
+    let Some(output) = output else {
+        return;
+    };
+    if events.is_empty() || *failed {
+        events.clear();
+        return;
+    }
+
+    fn open(output: &OutputDir) -> Result<BufWriter<File>> {
+        std::fs::create_dir_all(&output.0)?;
+        let path = output.join(GAME_EVENTS_FILE);
+        let file = File::create(&path)
+            .with_context(|| format!("cannot create event log {}", path.display()))?;
+        Ok(BufWriter::new(file))
+    }
+
+    let writer = match &mut *file {
+        Some(writer) => writer,
+        None => match open(&output) {
+            Ok(writer) => file.insert(writer),
+            Err(err) => {
+                bevy::log::error!("{err:#}");
+                *failed = true;
+                return;
+            }
+        },
+    };
+
+    let time = chrono::Local::now().to_rfc3339();
+    let result = events.read().try_for_each(|event| -> Result<()> {
+        // tables are set up in the same frame as the round starts
+        if let GameEvent::TableCreated { table, .. } = event {
+            tables.insert(*table, round.latest());
+        }
+        let round = event
+            .table()
+            .and_then(|table| tables.get(&table).copied())
+            .unwrap_or_else(|| round.latest());
+        let record = GameEventRecord {
+            time: time.clone(),
+            round,
+            event: event.clone(),
+        };
+        serde_json::to_writer(&mut *writer, &record)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    });
+    if let Err(err) = result.and_then(|_| Ok(writer.flush()?)) {
+        bevy::log::error!("failed to write game events: {err}");
+    }
+}
+
+/// Read the event log back.
+pub fn read_game_events(path: impl AsRef<std::path::Path>) -> Result<Vec<GameEventRecord>> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("cannot read event log {}", path.display()))?;
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
@@ -1,28 +1,42 @@
 use std::{
     borrow::Cow,
+    collections::BTreeMap,
     fmt::Display,
     ops::{Add, Not},
+    path::PathBuf,
     sync::Arc,
+    time::Duration,
 };
 
-use anyhow::{bail, Result};
+use anyhow::Result;
 use async_std::{fs::File, io::WriteExt, path::Path, sync::Mutex, task::block_on};
 use bevy::{
     ecs::{query::QueryData, system::SystemId},
     prelude::*,
     tasks::{futures_lite::future, IoTaskPool, Task},
+    time::common_conditions::on_timer,
     utils::{BoxedFuture, ConditionalSend},
 };
+use clap::ValueEnum;
 use derivative::Derivative;
 use futures::{future::join_all, join};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::{llm::LlmActor, Settings};
-
-pub const NUM_CHAT_ROUNDS: usize = 6;
-pub const MAX_TRAIL_ROUNDS: usize = 3;
+use crate::{
+    backend::{LlmBackend, LlmError, LlmLimiter, LlmTraffic},
+    cassette::{CassetteBackend, CassetteMode},
+    dossier::{update_dossiers, Dossier},
+    event::{write_game_events, GameEvent},
+    human::HumanActor,
+    llm::LlmActor,
+    memory::Memory,
+    remote::RemoteActor,
+    rules::Rules,
+    script::{ConstantActor, CounterActor, HoarderActor, NashActor, SellerActor},
+    Settings,
+};
 
 pub const SYSTEM_NAME: &str = "System";
 pub const ASSISTANT_NAME: &str = "Stellaris";
@@ -35,19 +49,44 @@ impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<Inventory>()
             .register_type::<PlayerTimer>()
+            .register_type::<Dossier>()
             .register_type::<Table>()
+            .register_type::<TableRetries>()
+            .register_type::<TableRound>()
             .register_type::<PublicState>()
+            .register_type::<Rules>()
             .init_resource::<PublicState>()
+            .init_resource::<Rules>()
+            .init_resource::<GameLog>()
+            .init_resource::<GameRound>()
+            .add_event::<GameEvent>()
             .add_systems(Startup, setup_scene)
             .add_systems(
                 Update,
                 (update_public_state, match_players, update_players).in_set(GameSet::Player),
             )
-            .add_systems(Update, (start_duel, poll_duel).in_set(GameSet::Duel))
+            .add_systems(
+                Update,
+                (
+                    start_duel,
+                    send_duel_events.before(poll_duel),
+                    poll_duel,
+                    update_dossiers.after(poll_duel),
+                    save_game_log
+                        .after(poll_duel)
+                        .run_if(resource_changed::<GameLog>),
+                )
+                    .in_set(GameSet::Duel),
+            )
+            .add_systems(
+                Update,
+                log_llm_traffic.run_if(on_timer(LLM_TRAFFIC_LOG_INTERVAL)),
+            )
             .add_systems(
                 Update,
                 final_trade.run_if(is_game_over).in_set(GameSet::GameOver),
             )
+            .add_systems(Update, write_game_events.after(GameSet::GameOver))
             .configure_sets(
                 Update,
                 (GameSet::Player, GameSet::Duel, GameSet::GameOver).chain(),
@@ -55,6 +94,55 @@ impl Plugin for GamePlugin {
     }
 }
 
+/// The RNG driving all random choices made by the game itself, e.g., matchmaking.
+#[derive(Debug, Clone, Deref, DerefMut, Resource)]
+pub struct GameRng(pub fastrand::Rng);
+
+/// The LLM backend and limiter shared by all LLM players in the game.
+#[derive(Debug, Clone, Resource)]
+pub struct SharedLlm {
+    pub backend: Arc<dyn LlmBackend>,
+    pub limiter: Arc<LlmLimiter>,
+}
+
+impl SharedLlm {
+    pub fn new(settings: &Settings) -> Result<Self> {
+        let backend = settings.backend.build(settings);
+        let backend: Arc<dyn LlmBackend> = match (&settings.cassette, settings.cassette_mode) {
+            (None, _) => backend,
+            (Some(path), CassetteMode::Record) => Arc::new(CassetteBackend::record(backend, path)?),
+            (Some(path), CassetteMode::Replay) => Arc::new(CassetteBackend::replay(path)?),
+        };
+        let limiter = Arc::new(LlmLimiter::new(settings.max_in_flight, settings.rate_limit));
+        Ok(Self { backend, limiter })
+    }
+}
+
+/// The directory where the outputs of this game are dumped.
+#[derive(Debug, Clone, Deref, Resource)]
+pub struct OutputDir(pub PathBuf);
+
+impl OutputDir {
+    pub fn new(settings: &Settings) -> Self {
+        let time = chrono::Local::now().format("%Y-%m-%d-%H-%M").to_string();
+        Self(settings.output.join(format!("output-{}", time)))
+    }
+}
+
+/// Number of rounds of tables set up so far.
+#[derive(Debug, Default, Clone, Copy, Deref, Resource)]
+pub struct GameRound(pub usize);
+
+impl GameRound {
+    /// The round of the latest tables.
+    pub fn latest(&self) -> usize {
+        self.0.saturating_sub(1)
+    }
+}
+
+/// How often to log the LLM traffic.
+pub const LLM_TRAFFIC_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
 #[derive(Debug, Clone, Copy, Resource)]
 pub struct DumpPlayersSystem(pub SystemId);
 
@@ -62,108 +150,131 @@ pub struct DumpPlayersSystem(pub SystemId);
 pub struct ExitSystem(pub SystemId);
 
 #[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone, Copy)]
-enum GameSet {
+pub enum GameSet {
     Player,
     Duel,
     GameOver,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-pub enum Card {
-    Rock,
-    Paper,
-    Scissors,
-}
+/// A card, identified by the name of its kind in [`Rules::cards`].
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Deref,
+    Reflect,
+    Serialize,
+    Deserialize,
+)]
+#[serde(transparent)]
+pub struct Card(pub Cow<'static, str>);
 
 impl Card {
-    pub fn compare(self: Card, rhs: Card) -> Option<usize> {
-        match (self, rhs) {
-            (Card::Rock, Card::Rock) => None,
-            (Card::Rock, Card::Paper) => Some(1),
-            (Card::Rock, Card::Scissors) => Some(0),
-            (Card::Paper, Card::Rock) => Some(0),
-            (Card::Paper, Card::Paper) => None,
-            (Card::Paper, Card::Scissors) => Some(1),
-            (Card::Scissors, Card::Rock) => Some(1),
-            (Card::Scissors, Card::Paper) => Some(0),
-            (Card::Scissors, Card::Scissors) => None,
-        }
+    pub const fn from_static(name: &'static str) -> Self {
+        Self(Cow::Borrowed(name))
+    }
+
+    pub fn new(name: impl AsRef<str>) -> Self {
+        let name = name.as_ref().trim();
+        Self(Cow::Owned(name.to_owned()))
     }
 }
 
 impl Display for Card {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Card::Rock => write!(f, "Rock"),
-            Card::Paper => write!(f, "Paper"),
-            Card::Scissors => write!(f, "Scissors"),
-        }
+        write!(f, "{}", self.0)
     }
 }
 
+pub const ROCK: Card = Card::from_static("Rock");
+pub const PAPER: Card = Card::from_static("Paper");
+pub const SCISSORS: Card = Card::from_static("Scissors");
+
+/// Number of cards of each kind.
+pub type Cards = BTreeMap<Card, usize>;
+
 #[derive(Debug, Derivative, Clone, Component, Reflect, Serialize, Deserialize)]
 #[derivative(Default)]
 #[reflect(Component, Default)]
+#[serde(default)]
 pub struct Inventory {
     #[derivative(Default(value = "3"))]
     pub star: usize,
     #[derivative(Default(value = "10"))]
     pub coin: usize,
-    #[derivative(Default(value = "4"))]
-    pub rock: usize,
-    #[derivative(Default(value = "4"))]
-    pub paper: usize,
-    #[derivative(Default(value = "4"))]
-    pub scissors: usize,
+    #[derivative(Default(value = "[(ROCK, 4), (PAPER, 4), (SCISSORS, 4)].into()"))]
+    pub cards: Cards,
 }
 
 impl Inventory {
     pub fn num_cards(&self) -> usize {
-        self.rock + self.paper + self.scissors
+        self.cards.values().sum()
+    }
+
+    /// Number of cards of some kind.
+    pub fn count(&self, card: &Card) -> usize {
+        self.cards.get(card).copied().unwrap_or_default()
+    }
+
+    /// All cards in the inventory, one entry per card.
+    pub fn deck(&self) -> Vec<Card> {
+        self.cards
+            .iter()
+            .flat_map(|(card, &count)| std::iter::repeat_n(card.clone(), count))
+            .collect()
     }
 
     pub fn is_alive(&self) -> bool {
         self.star > 0
     }
 
-    pub fn is_safe(&self) -> bool {
-        self.star >= 3 && self.num_cards() == 0
+    pub fn is_safe(&self, rules: &Rules) -> bool {
+        self.star >= rules.safe_stars && self.num_cards() == 0
     }
 
     pub fn can_duel(&self) -> bool {
         self.num_cards() > 0
     }
 
-    pub fn star_price(&self) -> Option<usize> {
+    pub fn star_price(&self, rules: &Rules) -> Option<usize> {
         match self.star {
-            x if x >= 3 => None,
-            x => Some(self.coin / (3 - x)),
+            x if x >= rules.safe_stars => None,
+            x => Some(self.coin / (rules.safe_stars - x)),
         }
     }
 
     pub fn split_trade(&self, trade: &Trade) -> Result<Self, TradeError> {
         match (self, trade) {
-            (x, y) if x.star < y.star => Err(TradeError::Star(x.star, y.star)),
-            (x, y) if x.coin < y.coin => Err(TradeError::Coin(x.coin, y.coin)),
-            (x, y) if x.rock < y.rock => Err(TradeError::Rock(x.rock, y.rock)),
-            (x, y) if x.paper < y.paper => Err(TradeError::Paper(x.paper, y.paper)),
-            (x, y) if x.scissors < y.scissors => Err(TradeError::Scissors(x.scissors, y.scissors)),
-            (x, y) => Ok(Self {
-                star: x.star - y.star,
-                coin: x.coin - y.coin,
-                rock: x.rock - y.rock,
-                paper: x.paper - y.paper,
-                scissors: x.scissors - y.scissors,
-            }),
+            (x, y) if x.star < y.star => return Err(TradeError::Star(x.star, y.star)),
+            (x, y) if x.coin < y.coin => return Err(TradeError::Coin(x.coin, y.coin)),
+            _ => {}
+        }
+
+        let mut inventory = Self {
+            star: self.star - trade.star,
+            coin: self.coin - trade.coin,
+            ..self.clone()
+        };
+        for (card, &count) in &trade.cards {
+            match self.count(card) {
+                x if x < count => return Err(TradeError::Card(card.clone(), x, count)),
+                x => inventory.cards.insert(card.clone(), x - count),
+            };
         }
+        Ok(inventory)
     }
 
     pub fn apply_trade(&mut self, trade: &Trade) {
         self.star += trade.star;
         self.coin += trade.coin;
-        self.rock += trade.rock;
-        self.paper += trade.paper;
-        self.scissors += trade.scissors;
+        for (card, &count) in &trade.cards {
+            *self.cards.entry(card.clone()).or_default() += count;
+        }
     }
 
     pub fn split_stake(&self, stake: &Stake) -> Result<Self, StakeError> {
@@ -183,23 +294,14 @@ impl Inventory {
         self.coin += stake.coin;
     }
 
-    pub fn split_duel(&self, card: Card) -> Result<Self, DuelError> {
-        match (self, card) {
-            (x, Card::Rock) if x.rock == 0 => Err(DuelError::Rock),
-            (x, Card::Paper) if x.paper == 0 => Err(DuelError::Paper),
-            (x, Card::Scissors) if x.scissors == 0 => Err(DuelError::Scissors),
-            (x, Card::Rock) => Ok(Self {
-                rock: x.rock - 1,
-                ..x.clone()
-            }),
-            (x, Card::Paper) => Ok(Self {
-                paper: x.paper - 1,
-                ..x.clone()
-            }),
-            (x, Card::Scissors) => Ok(Self {
-                scissors: x.scissors - 1,
-                ..x.clone()
-            }),
+    pub fn split_duel(&self, card: &Card) -> Result<Self, DuelError> {
+        match self.count(card) {
+            0 => Err(DuelError::Card(card.clone())),
+            x => {
+                let mut inventory = self.clone();
+                inventory.cards.insert(card.clone(), x - 1);
+                Ok(inventory)
+            }
         }
     }
 }
@@ -230,39 +332,55 @@ pub struct PlayerSafe;
 pub struct PlayerDead;
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Trade {
     pub star: usize,
     pub coin: usize,
-    pub rock: usize,
-    pub paper: usize,
-    pub scissors: usize,
+    pub cards: Cards,
 }
 
 impl Trade {
+    /// A trade offering a single card, or nothing.
+    pub fn card(card: Option<Card>) -> Self {
+        match card {
+            Some(card) => Self {
+                cards: [(card, 1)].into(),
+                ..Default::default()
+            },
+            None => Self::default(),
+        }
+    }
+
+    /// Number of cards of some kind in the trade.
+    pub fn count(&self, card: &Card) -> usize {
+        self.cards.get(card).copied().unwrap_or_default()
+    }
+
     pub fn normalize(self, inventory: &Inventory) -> Self {
         assert!(inventory.star > 0);
         Self {
             star: self.star.min(inventory.star - 1),
             coin: self.coin.min(inventory.coin),
-            rock: self.rock.min(inventory.rock),
-            paper: self.paper.min(inventory.paper),
-            scissors: self.scissors.min(inventory.scissors),
+            cards: self
+                .cards
+                .into_iter()
+                .map(|(card, count)| {
+                    let count = count.min(inventory.count(&card));
+                    (card, count)
+                })
+                .collect(),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, Error)]
+#[derive(Debug, Clone, Error)]
 pub enum TradeError {
     #[error("cannot take out {1} star(s) since you only have {0}")]
     Star(usize, usize),
     #[error("cannot take out {1} coin(s) since you only have {0}")]
     Coin(usize, usize),
-    #[error("cannot take out {1} rock card(s) since you only have {0}")]
-    Rock(usize, usize),
-    #[error("cannot take out {1} paper card(s) since you only have {0}")]
-    Paper(usize, usize),
-    #[error("cannot take out {1} scissors card(s) since you only have {0}")]
-    Scissors(usize, usize),
+    #[error("cannot take out {2} {0} card(s) since you only have {1}")]
+    Card(Card, usize, usize),
 }
 
 #[derive(Debug, Derivative, Clone, Serialize, Deserialize)]
@@ -274,10 +392,10 @@ pub struct Stake {
 }
 
 impl Stake {
-    pub fn normalize(self) -> Self {
+    pub fn normalize(self, rules: &Rules, inventory: &Inventory) -> Self {
         let Self { star, coin } = self;
         Self {
-            star: star.max(1),
+            star: star.max(rules.min_stake).min(inventory.star),
             coin,
         }
     }
@@ -302,14 +420,49 @@ pub enum StakeError {
     Coin(usize, usize),
 }
 
-#[derive(Debug, Clone, Copy, Error)]
+#[derive(Debug, Clone, Error)]
 pub enum DuelError {
-    #[error("cannot draw rock since you do not have such card")]
-    Rock,
-    #[error("cannot draw paper since you do not have such card")]
-    Paper,
-    #[error("cannot draw scissors since you do not have such card")]
-    Scissors,
+    #[error("cannot draw {0} since you do not have such card")]
+    Card(Card),
+}
+
+/// An actor fails to act at all, as opposed to acting against the rules.
+#[derive(Debug, Clone, Error)]
+pub enum ActorError {
+    #[error(transparent)]
+    Llm(#[from] LlmError),
+}
+
+/// A duel cannot finish because of one of the players at the table.
+#[derive(Debug, Clone, Error)]
+pub enum TableError {
+    #[error("player {0} fails: {1}")]
+    Actor(usize, ActorError),
+    #[error("player {0} makes invalid {1} too many times")]
+    Invalid(usize, &'static str),
+}
+
+impl TableError {
+    /// Index of the player at the table to blame.
+    pub fn index(&self) -> usize {
+        match self {
+            TableError::Actor(index, _) | TableError::Invalid(index, _) => *index,
+        }
+    }
+}
+
+/// What to do with a table whose duel fails.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Reflect, Serialize, Deserialize,
+)]
+pub enum FailurePolicy {
+    /// The failing player forfeits the minimum stake to the opponent.
+    #[default]
+    Forfeit,
+    /// Run the duel again, and forfeit if it keeps failing.
+    Retry,
+    /// Dump the players and abort the game.
+    Abort,
 }
 
 #[derive(Derivative, Component)]
@@ -326,6 +479,61 @@ impl Player {
     }
 }
 
+/// Kinds of actors that can be assigned to players.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Reflect, Serialize, Deserialize,
+)]
+pub enum ActorKind {
+    /// Played by the LLM.
+    #[default]
+    Llm,
+    /// Draws cards uniformly at random.
+    Random,
+    /// Always draws the same kind of card.
+    Constant,
+    /// Counters the most common card on the stage, or the card the opponent has revealed the least.
+    Counter,
+    /// Draws cards following the Nash equilibrium.
+    Nash,
+    /// Hoards coins and never gives them away.
+    Hoarder,
+    /// Sells stars above the safe threshold for coins.
+    Seller,
+    /// Played by a human over the terminal.
+    Human,
+    /// Played by an external process over a socket, see `--remote`.
+    Remote,
+}
+
+impl ActorKind {
+    pub fn player(self, settings: &Settings, llm: &SharedLlm, rng: fastrand::Rng) -> Player {
+        match self {
+            ActorKind::Llm => {
+                let actor = LlmActor {
+                    retry: settings.retry,
+                    limiter: llm.limiter.clone(),
+                    trade_mode: settings.trade_mode,
+                    memory: Memory::new(settings.memory),
+                    context_budget: settings.context_budget,
+                    ..LlmActor::new(llm.backend.clone(), settings.output.clone(), rng)
+                };
+                Player::new(actor)
+            }
+            ActorKind::Random => Player::new(DummyActor::new(rng)),
+            ActorKind::Constant => Player::new(ConstantActor::new(rng)),
+            ActorKind::Counter => Player::new(CounterActor::new(rng)),
+            ActorKind::Nash => Player::new(NashActor::new(rng)),
+            ActorKind::Hoarder => Player::new(HoarderActor::new(rng)),
+            ActorKind::Seller => Player::new(SellerActor::new(rng)),
+            ActorKind::Human => Player::new(HumanActor::new()),
+            ActorKind::Remote => {
+                let actor = RemoteActor::new(settings.remote.clone(), settings.remote_timeout, rng);
+                Player::new(actor)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deref, DerefMut, Component, Reflect)]
 #[reflect(Component)]
 pub struct Table(pub [Entity; 2]);
@@ -340,14 +548,12 @@ impl Table {
 #[reflect(Resource, Default)]
 pub struct PublicState {
     pub player: usize,
-    pub rock: usize,
-    pub paper: usize,
-    pub scissors: usize,
+    pub cards: Cards,
 }
 
 impl PublicState {
     pub fn total_cards(&self) -> usize {
-        self.rock + self.paper + self.scissors
+        self.cards.values().sum()
     }
 }
 
@@ -359,50 +565,106 @@ struct PlayerQuery {
     name: &'static Name,
     inventory: &'static Inventory,
     timer: &'static PlayerTimer,
+    dossier: &'static Dossier,
 }
 
-fn setup_scene(mut commands: Commands, settings: Res<Settings>) {
+fn setup_scene(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    rules: Res<Rules>,
+    mut exit: EventWriter<AppExit>,
+    mut events: EventWriter<GameEvent>,
+    mut log: ResMut<GameLog>,
+) {
     let names = NAMES.split("\n").map(|x| x.trim()).collect_vec();
     let Settings {
-        url,
-        output,
         num_players,
         max_rounds,
-    } = settings.clone();
-    commands.spawn_batch((0..num_players).map(move |index| {
-        (
-            Name::new(names[index]),
-            Player::new(LlmActor::new(url.clone(), output.clone())),
-            // Player::new(DummyActor),
-            Inventory::default(),
-            PlayerTimer(max_rounds),
-        )
-    }));
+        seed,
+        ref actors,
+        ..
+    } = *settings;
+    let inventory = rules.inventory.clone();
 
     let system = DumpPlayersSystem(commands.register_system(dump_players));
     commands.insert_resource(system);
 
     let system = ExitSystem(commands.register_system(exit_system));
     commands.insert_resource(system);
+
+    commands.insert_resource(OutputDir::new(&settings));
+
+    let mut rng = match seed {
+        Some(seed) => fastrand::Rng::with_seed(seed),
+        None => fastrand::Rng::new(),
+    };
+    let rngs = (0..num_players).map(|_| rng.fork()).collect_vec();
+    commands.insert_resource(GameRng(rng));
+
+    let llm = match SharedLlm::new(&settings) {
+        Ok(llm) => llm,
+        Err(err) => {
+            bevy::log::error!("failed to set up the LLM backend: {err:#}");
+            exit.send(AppExit::from_code(1));
+            return;
+        }
+    };
+    commands.insert_resource(llm.clone());
+
+    for (index, (rng, kind)) in rngs.into_iter().zip(actors.iter().cycle()).enumerate() {
+        let player = commands
+            .spawn((
+                Name::new(names[index]),
+                kind.player(&settings, &llm, rng),
+                inventory.clone(),
+                PlayerTimer(max_rounds),
+                Dossier::default(),
+            ))
+            .id();
+        log.players.push(PlayerRecord {
+            name: names[index].into(),
+            actor: *kind,
+            inventory: inventory.clone(),
+        });
+        events.send(GameEvent::PlayerJoined {
+            player,
+            name: names[index].into(),
+            actor: *kind,
+            inventory: inventory.clone(),
+        });
+    }
 }
 
 fn update_public_state(mut state: ResMut<PublicState>, players: Query<&Inventory, With<Player>>) {
     *state = Default::default();
     for inventory in &players {
-        state.rock += inventory.rock;
-        state.paper += inventory.paper;
-        state.scissors += inventory.scissors;
+        for (card, &count) in &inventory.cards {
+            *state.cards.entry(card.clone()).or_default() += count;
+        }
     }
     state.player = players.iter().len();
 }
 
 /// Find players that are not currently in match, and put them onto a table.
-fn match_players(mut commands: Commands, players: Query<PlayerQuery>, tables: Query<&Table>) {
+#[allow(clippy::too_many_arguments)]
+fn match_players(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    rules: Res<Rules>,
+    mut rng: ResMut<GameRng>,
+    mut round: ResMut<GameRound>,
+    mut events: EventWriter<GameEvent>,
+    players: Query<PlayerQuery>,
+    tables: Query<&Table>,
+) {
+    // seeded games wait for all tables to finish, so that matching doesn't depend on timing
+    if settings.seed.is_some() && !tables.is_empty() {
+        return;
+    }
+
     let mut total_cards = 0;
     for PlayerQueryItem { inventory, .. } in &players {
-        total_cards += inventory.rock;
-        total_cards += inventory.paper;
-        total_cards += inventory.scissors;
+        total_cards += inventory.num_cards();
     }
     if total_cards < 2 {
         // there is only one card, cannot proceed
@@ -421,22 +683,31 @@ fn match_players(mut commands: Commands, players: Query<PlayerQuery>, tables: Qu
                 .not()
         })
         .filter(|PlayerQueryItem { inventory, .. }| inventory.is_alive())
-        .filter(|PlayerQueryItem { inventory, .. }| !inventory.is_safe())
+        .filter(|PlayerQueryItem { inventory, .. }| !inventory.is_safe(&rules))
         .filter(|PlayerQueryItem { timer, .. }| !timer.time_up())
+        .sorted_by_key(|PlayerQueryItem { entity, .. }| *entity)
         .collect_vec();
 
-    fastrand::shuffle(&mut players);
+    rng.shuffle(&mut players);
+    if players.len() < 2 {
+        return;
+    }
 
     for (x, y) in players.into_iter().tuples() {
         let table = Table::new(x.entity, y.entity);
         let name = Name::new(format!("Table ({}, {})", x.name, y.name));
-        commands.spawn((table, name));
+        let players = table.0;
+        let table = commands.spawn((table, name, TableRound(round.0))).id();
+        events.send(GameEvent::TableCreated { table, players });
     }
+    round.0 += 1;
 }
 
 #[allow(clippy::type_complexity)]
 fn update_players(
     mut commands: Commands,
+    rules: Res<Rules>,
+    mut events: EventWriter<GameEvent>,
     players: Query<
         (Entity, &Name, &Inventory),
         (With<Player>, Without<PlayerDead>, Without<PlayerSafe>),
@@ -446,35 +717,42 @@ fn update_players(
         if !inventory.is_alive() {
             bevy::log::info!("player dead: {name}");
             commands.entity(entity).insert(PlayerDead);
+            events.send(GameEvent::PlayerDied { player: entity });
         }
-        if inventory.is_safe() {
+        if inventory.is_safe(&rules) {
             bevy::log::info!("player safe: {name}");
             commands.entity(entity).insert(PlayerSafe);
+            events.send(GameEvent::PlayerSafe { player: entity });
         }
     }
 }
 
-fn is_game_over(players: Query<(&Inventory, &PlayerTimer), With<Player>>) -> bool {
+fn is_game_over(
+    rules: Res<Rules>,
+    players: Query<(&Inventory, &PlayerTimer), With<Player>>,
+) -> bool {
     players
         .iter()
         .filter(|(inventory, _)| inventory.is_alive())
-        .filter(|(inventory, _)| !inventory.is_safe())
+        .filter(|(inventory, _)| !inventory.is_safe(&rules))
         .filter(|(_, timer)| !timer.time_up())
         .count()
         < 2
 }
 
-#[allow(clippy::type_complexity)]
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn final_trade(
     mut commands: Commands,
     mut processed: Local<bool>,
-    mut survivors: Query<(&Name, &mut Inventory), (With<Player>, With<PlayerSafe>)>,
+    rules: Res<Rules>,
+    mut survivors: Query<(Entity, &Name, &mut Inventory), (With<Player>, With<PlayerSafe>)>,
     mut players: Query<
-        (&Name, &mut Inventory),
+        (Entity, &Name, &mut Inventory),
         (With<Player>, Without<PlayerSafe>, Without<PlayerDead>),
     >,
     dump_players_system: Res<DumpPlayersSystem>,
     exit_system: Res<ExitSystem>,
+    mut events: EventWriter<GameEvent>,
 ) {
     *processed = match *processed {
         true => return,
@@ -485,44 +763,93 @@ fn final_trade(
 
     for mut player in players
         .iter_mut()
-        .filter(|x| x.1.num_cards() == 0)
-        .sorted_by(|x, y| y.1.star_price().cmp(&x.1.star_price()))
+        .filter(|x| x.2.num_cards() == 0)
+        .sorted_by(|x, y| {
+            let ordering = y.2.star_price(&rules).cmp(&x.2.star_price(&rules));
+            ordering.then(x.0.cmp(&y.0))
+        })
     {
-        let price = player.1.star_price().unwrap_or_default();
-        while player.1.star < 3 {
+        let price = player.2.star_price(&rules).unwrap_or_default();
+        while player.2.star < rules.safe_stars {
             let seller = survivors
                 .iter_mut()
-                .filter(|x| x.1.star > 3)
-                .sorted_by(|x, y| y.1.star.cmp(&x.1.star))
+                .filter(|x| x.2.star > rules.safe_stars)
+                .sorted_by(|x, y| y.2.star.cmp(&x.2.star).then(x.0.cmp(&y.0)))
                 .next();
             let Some(mut seller) = seller else {
                 break;
             };
 
-            player.1.star += 1;
-            seller.1.star -= 1;
+            player.2.star += 1;
+            seller.2.star -= 1;
 
-            player.1.coin -= price;
-            seller.1.coin += price;
+            player.2.coin -= price;
+            seller.2.coin += price;
 
             bevy::log::info!(
                 "{} buys from {} 1 star for {} coins",
-                player.0,
-                seller.0,
+                player.1,
+                seller.1,
                 price
             );
+            events.send(GameEvent::StarPurchased {
+                buyer: player.0,
+                seller: seller.0,
+                price,
+            });
         }
     }
+    events.send(GameEvent::GameOver);
 
     commands.run_system(dump_players_system.0);
     commands.run_system(exit_system.0);
 }
 
-fn dump_players(settings: Res<Settings>, players: Query<PlayerQuery>) {
-    let time = chrono::Local::now().format("%Y-%m-%d-%H-%M").to_string();
-    let path = settings.output.join(format!("output-{}", time));
-    if let Err(err) = std::fs::create_dir_all(&path) {
-        bevy::log::error!("{err}");
+/// Write the game log, with the current inventories of players as the result.
+fn write_game_log(
+    path: &std::path::Path,
+    log: &GameLog,
+    players: &Query<PlayerQuery>,
+) -> Result<PathBuf> {
+    std::fs::create_dir_all(path)?;
+    let log = GameLog {
+        result: log
+            .players
+            .iter()
+            .map(|record| {
+                let inventory = players
+                    .iter()
+                    .find(|player| player.name.as_str() == record.name)
+                    .map(|player| player.inventory.clone())
+                    .unwrap_or_else(|| record.inventory.clone());
+                PlayerRecord {
+                    inventory,
+                    ..record.clone()
+                }
+            })
+            .collect(),
+        ..log.clone()
+    };
+    let file = path.join(GAME_LOG_FILE);
+    std::fs::write(&file, serde_json::to_vec_pretty(&log)?)?;
+    Ok(file)
+}
+
+/// Keep the game log on disk as tables finish, so that games cut short can be replayed as well.
+fn save_game_log(output: Option<Res<OutputDir>>, log: Res<GameLog>, players: Query<PlayerQuery>) {
+    let Some(output) = output else {
+        return;
+    };
+    if let Err(err) = write_game_log(&output.0, &log, &players) {
+        bevy::log::error!("failed to write game log: {err}");
+    }
+}
+
+fn dump_players(output: Res<OutputDir>, log: Res<GameLog>, players: Query<PlayerQuery>) {
+    let path = output.0.clone();
+    match write_game_log(&path, &log, &players) {
+        Ok(file) => bevy::log::info!("dumped game log to {:?}", file),
+        Err(err) => bevy::log::error!("{err}"),
     }
 
     async fn dump(
@@ -556,15 +883,89 @@ fn dump_players(settings: Res<Settings>, players: Query<PlayerQuery>) {
     block_on(join_all(tasks));
 }
 
+/// Log the queue depth of LLM requests if there is any traffic since the last time.
+fn log_llm_traffic(llm: Option<Res<SharedLlm>>, mut last: Local<LlmTraffic>) {
+    let Some(llm) = llm else {
+        return;
+    };
+    let traffic = llm.limiter.traffic();
+    if traffic != *last {
+        let LlmTraffic {
+            waiting,
+            in_flight,
+            completed,
+            ..
+        } = traffic;
+        let latency = traffic.mean_latency().unwrap_or_default();
+        bevy::log::info!(
+            "llm traffic: {waiting} waiting, {in_flight} in flight, {completed} completed, {latency:.2?} on average"
+        );
+        *last = traffic;
+    }
+}
+
 fn exit_system(mut writer: EventWriter<AppExit>) {
     writer.send(AppExit::Success);
 }
 
 #[derive(Debug, Component)]
-pub struct DuelTask(pub Task<Result<[Inventory; 2]>>);
+pub struct DuelTask(pub Task<Result<([Inventory; 2], DuelReport), TableError>>);
+
+/// The round of matching in which the table is set up.
+#[derive(Debug, Default, Clone, Copy, Deref, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct TableRound(pub usize);
+
+/// A player as recorded in the game log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerRecord {
+    pub name: String,
+    pub actor: ActorKind,
+    pub inventory: Inventory,
+}
+
+/// A finished or failed table as recorded in the game log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableRecord {
+    pub round: usize,
+    pub players: [String; 2],
+    pub before: [Inventory; 2],
+    pub after: [Inventory; 2],
+    /// What happens at the table, if the duel finishes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report: Option<DuelReport>,
+    /// Why the duel fails, if it does.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Everything needed to replay the game, written as `game.json` next to the players each time a
+/// table finishes, and once more at the end of the game.
+#[derive(Debug, Default, Clone, Resource, Serialize, Deserialize)]
+pub struct GameLog {
+    /// Players with their starting inventories.
+    pub players: Vec<PlayerRecord>,
+    /// Tables in the order they finish.
+    pub tables: Vec<TableRecord>,
+    /// Players with their inventories at the end of the game, after the final trade.
+    #[serde(default)]
+    pub result: Vec<PlayerRecord>,
+}
+
+/// File name of the game log in the output directory.
+pub const GAME_LOG_FILE: &str = "game.json";
+
+/// Number of times the duel at the table has been run again after failures.
+#[derive(Debug, Default, Clone, Copy, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct TableRetries(pub usize);
+
+/// Maximum times to run a failed duel again under [`FailurePolicy::Retry`] before forfeiting.
+pub const MAX_TABLE_RETRIES: usize = 3;
 
 fn start_duel(
     mut commands: Commands,
+    rules: Res<Rules>,
     state: Res<PublicState>,
     players: Query<PlayerQuery>,
     tables: Query<(Entity, &Table), Without<DuelTask>>,
@@ -580,23 +981,65 @@ fn start_duel(
         assert!(!x.timer.time_up());
         assert!(!y.timer.time_up());
 
+        let rules = rules.clone();
         let state = state.clone();
         let actors = [x.player.actor.clone(), y.player.actor.clone()];
         let data = [x.into(), y.into()];
-        let task = thread_pool.spawn(duel(state, actors, data));
-        commands.entity(entity).insert(DuelTask(task));
+        let progress = DuelProgress::new(entity, table.0);
+        let task = thread_pool.spawn(duel_with_progress(
+            rules,
+            state,
+            actors,
+            data,
+            progress.clone(),
+        ));
+        commands.entity(entity).insert((DuelTask(task), progress));
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn poll_duel(
     mut commands: Commands,
+    settings: Res<Settings>,
+    rules: Res<Rules>,
+    dump_players_system: Res<DumpPlayersSystem>,
+    mut exit: EventWriter<AppExit>,
+    mut aborted: Local<bool>,
+    mut log: ResMut<GameLog>,
+    mut events: EventWriter<GameEvent>,
+    names: Query<&Name, With<Player>>,
     mut players: Query<(&mut Inventory, &mut PlayerTimer), With<Player>>,
-    mut tables: Query<(Entity, &Table, &mut DuelTask), Without<Player>>,
+    mut tables: Query<
+        (
+            Entity,
+            &Table,
+            &mut DuelTask,
+            Option<&DuelProgress>,
+            Option<&TableRetries>,
+            Option<&TableRound>,
+        ),
+        Without<Player>,
+    >,
 ) {
-    for (entity, table, mut task) in &mut tables {
+    let inventories = |players: &Query<(&mut Inventory, &mut PlayerTimer), With<Player>>,
+                       table: &Table| {
+        table
+            .0
+            .map(|entity| players.get(entity).map(|x| x.0.clone()).unwrap_or_default())
+    };
+
+    for (entity, table, mut task, progress, retries, round) in &mut tables {
         if let Some(result) = block_on(future::poll_once(&mut task.0)) {
-            match result {
-                Ok([m, n]) => {
+            // steps taken since the last frame
+            if let Some(progress) = progress {
+                events.send_batch(progress.drain());
+            }
+            let before = inventories(&players, table);
+            let retries = retries.map(|x| x.0).unwrap_or_default();
+            let mut retry = false;
+
+            let (report, error) = match result {
+                Ok(([m, n], report)) => {
                     if let Ok(mut x) = players.get_mut(table[0]) {
                         *x.0 = m;
                         x.1.decrease();
@@ -605,20 +1048,95 @@ fn poll_duel(
                         *y.0 = n;
                         y.1.decrease();
                     }
+                    (Some(report), None)
+                }
+                Err(err) => {
+                    bevy::log::warn!("duel error: {err}");
+                    match settings.on_failure {
+                        FailurePolicy::Retry if retries < MAX_TABLE_RETRIES => {
+                            bevy::log::info!("retry duel, attempt {}", retries + 1);
+                            retry = true;
+                        }
+                        FailurePolicy::Forfeit | FailurePolicy::Retry => {
+                            forfeit(&rules, &mut players, table, err.index())
+                        }
+                        FailurePolicy::Abort => {
+                            if !*aborted {
+                                *aborted = true;
+                                bevy::log::error!("game aborted");
+                                commands.run_system(dump_players_system.0);
+                                exit.send(AppExit::from_code(1));
+                            }
+                        }
+                    }
+                    (None, Some(err.to_string()))
                 }
-                Err(err) => bevy::log::warn!("duel error: {err}"),
+            };
+
+            let after = inventories(&players, table);
+            if let Some(error) = &error {
+                events.send(GameEvent::TableFailed {
+                    table: entity,
+                    players: table.0,
+                    error: error.clone(),
+                    retry,
+                });
+            }
+
+            log.tables.push(TableRecord {
+                round: round.map(|x| x.0).unwrap_or_default(),
+                players: table.0.map(|entity| {
+                    names
+                        .get(entity)
+                        .map(|name| name.to_string())
+                        .unwrap_or_default()
+                }),
+                before,
+                after,
+                report,
+                error,
+            });
+
+            match retry {
+                true => {
+                    commands
+                        .entity(entity)
+                        .remove::<(DuelTask, DuelProgress)>()
+                        .insert(TableRetries(retries + 1));
+                }
+                false => commands.entity(entity).despawn_recursive(),
             }
-            commands.entity(entity).despawn_recursive();
         }
     }
 }
 
+/// The player at `index` of the table pays the minimum stake to the opponent, and both spend the round.
+fn forfeit(
+    rules: &Rules,
+    players: &mut Query<(&mut Inventory, &mut PlayerTimer), With<Player>>,
+    table: &Table,
+    index: usize,
+) {
+    let [loser, winner] = [table[index], table[1 - index]];
+    let Ok([mut x, mut y]) = players.get_many_mut([loser, winner]) else {
+        return;
+    };
+    let star = rules.min_stake.min(x.0.star);
+    x.0.star -= star;
+    y.0.star += star;
+    x.1.decrease();
+    y.1.decrease();
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlayerData {
     pub entity: Entity,
     pub name: Name,
     pub inventory: Inventory,
     pub timer: PlayerTimer,
+    /// The public record of the player.
+    #[serde(default)]
+    pub dossier: Dossier,
 }
 
 impl<'a> From<PlayerQueryItem<'a>> for PlayerData {
@@ -628,6 +1146,7 @@ impl<'a> From<PlayerQueryItem<'a>> for PlayerData {
             name,
             inventory,
             timer,
+            dossier,
             ..
         }: PlayerQueryItem<'a>,
     ) -> Self {
@@ -636,6 +1155,7 @@ impl<'a> From<PlayerQueryItem<'a>> for PlayerData {
             name: name.to_owned(),
             inventory: inventory.to_owned(),
             timer: *timer,
+            dossier: dossier.to_owned(),
         }
     }
 }
@@ -645,6 +1165,9 @@ pub struct OpponentData {
     pub name: Name,
     pub star: usize,
     pub card: usize,
+    /// The public record of the opponent in previous rounds.
+    #[serde(default)]
+    pub dossier: Dossier,
 }
 
 impl From<PlayerData> for OpponentData {
@@ -653,6 +1176,7 @@ impl From<PlayerData> for OpponentData {
             name: value.name,
             star: value.inventory.star,
             card: value.inventory.num_cards(),
+            dossier: value.dossier,
         }
     }
 }
@@ -695,7 +1219,7 @@ pub enum ChatKind {
     Duel(usize),
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ChatRecordId;
 
 #[derive(Debug, Derivative, Clone, Serialize, Deserialize)]
@@ -729,28 +1253,82 @@ pub struct TradeState<'a> {
     pub that: &'a Trade,
 }
 
+/// An answer to the contract on the table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradeResponse {
+    /// Sign the contract.
+    Accept,
+    /// Refuse the contract, which voids the trade.
+    Reject,
+    /// Propose an amended contract instead: what this player and the other give.
+    Counter { this: Trade, that: Trade },
+}
+
+/// A counter-offer made at a table.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CounterOffer {
+    /// Index of the player making the counter-offer.
+    pub player: usize,
+    /// What each player gives under the amended contract, in the order of the players at the table.
+    pub trades: [Trade; 2],
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct StakeState<'a> {
     pub this: &'a Stake,
     pub that: &'a Stake,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DuelResult {
     Tie(Card),
     Win(Card, Card),
     Lose(Card, Card),
 }
 
+/// A line said in the public chat at a table.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ChatLine {
+    /// Index of the speaker at the table.
+    pub player: usize,
+    pub content: String,
+}
+
+/// What happens at a table, in the order of the players at the table.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DuelReport {
+    /// The public chat before the trade.
+    #[serde(default)]
+    pub chat: Vec<ChatLine>,
+    /// What each player offers in the trade.
+    pub trades: [Trade; 2],
+    /// Counter-offers in the order they are made; the last one is the contract signed or refused.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub counters: Vec<CounterOffer>,
+    /// Whether each player signs the contract.
+    pub accepted: [bool; 2],
+    /// The public chat before the duel.
+    #[serde(default)]
+    pub duel_chat: Vec<ChatLine>,
+    /// What each player bets, if they proceed to the duel.
+    pub stakes: Option<[Stake; 2]>,
+    /// The card each player draws, or `None` if refusing the duel.
+    pub cards: [Option<Card>; 2],
+    /// The result of the duel for the first player.
+    pub result: Option<DuelResult>,
+}
+
 #[allow(unused_variables)]
 pub trait Actor: ConditionalSend + Sync + 'static {
-    /// Notify the actor about how many cards are there on the stage.
+    /// Notify the actor about the rules and how many cards are there on the stage.
     fn notify<'a>(
         &'a mut self,
         player: &'a PlayerData,
+        rules: &'a Rules,
         state: &'a PublicState,
-    ) -> BoxedFuture<'a, ()> {
-        Box::pin(async move {})
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move { Ok(()) })
     }
 
     /// Provide feedback to the actor (due to erroneous actions).
@@ -758,8 +1336,8 @@ pub trait Actor: ConditionalSend + Sync + 'static {
         &'a mut self,
         player: &'a PlayerData,
         text: String,
-    ) -> BoxedFuture<'a, ()> {
-        Box::pin(async move { panic!("{text}") })
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move { Ok(()) })
     }
 
     /// Chat with the actor.
@@ -769,8 +1347,8 @@ pub trait Actor: ConditionalSend + Sync + 'static {
         opponent: &'a OpponentData,
         history: &'a [ChatRecord],
         kind: ChatKind,
-    ) -> BoxedFuture<'a, Vec<ChatRecord>> {
-        Box::pin(async move { vec![] })
+    ) -> BoxedFuture<'a, Result<Vec<ChatRecord>, ActorError>> {
+        Box::pin(async move { Ok(vec![]) })
     }
 
     /// Trade with another actor.
@@ -779,32 +1357,7 @@ pub trait Actor: ConditionalSend + Sync + 'static {
         player: &'a PlayerData,
         opponent: &'a OpponentData,
         history: &'a [ChatRecord],
-    ) -> BoxedFuture<'a, Trade> {
-        Box::pin(async move {
-            let deck = [
-                vec![Card::Rock; player.inventory.rock],
-                vec![Card::Paper; player.inventory.paper],
-                vec![Card::Scissors; player.inventory.scissors],
-            ]
-            .concat();
-            let card = fastrand::choice(&deck).cloned();
-            match card {
-                Some(Card::Rock) => Trade {
-                    rock: 1,
-                    ..Default::default()
-                },
-                Some(Card::Paper) => Trade {
-                    paper: 1,
-                    ..Default::default()
-                },
-                Some(Card::Scissors) => Trade {
-                    scissors: 1,
-                    ..Default::default()
-                },
-                None => Trade::default(),
-            }
-        })
-    }
+    ) -> BoxedFuture<'a, Result<Trade, ActorError>>;
 
     /// Accept the trade or not.
     fn accept_trade<'a>(
@@ -813,8 +1366,28 @@ pub trait Actor: ConditionalSend + Sync + 'static {
         opponent: &'a OpponentData,
         history: &'a [ChatRecord],
         state: TradeState<'a>,
-    ) -> BoxedFuture<'a, bool> {
-        Box::pin(async move { true })
+    ) -> BoxedFuture<'a, Result<bool, ActorError>> {
+        Box::pin(async move { Ok(true) })
+    }
+
+    /// Respond to the contract on the table: accept, reject, or counter with an amended one.
+    /// `counters` is how many counter-offers are left at the table; a counter-offer beyond that is a rejection.
+    ///
+    /// By default, accepts or rejects the contract by [`Actor::accept_trade`].
+    fn respond_trade<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+        state: TradeState<'a>,
+        counters: usize,
+    ) -> BoxedFuture<'a, Result<TradeResponse, ActorError>> {
+        Box::pin(async move {
+            match self.accept_trade(player, opponent, history, state).await? {
+                true => Ok(TradeResponse::Accept),
+                false => Ok(TradeResponse::Reject),
+            }
+        })
     }
 
     /// Feedback on accepting the trade or not.
@@ -822,8 +1395,8 @@ pub trait Actor: ConditionalSend + Sync + 'static {
         &'a mut self,
         player: &'a PlayerData,
         state: [bool; 2],
-    ) -> BoxedFuture<'a, ()> {
-        Box::pin(async move {})
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move { Ok(()) })
     }
 
     /// Bet for the duel.
@@ -832,8 +1405,8 @@ pub trait Actor: ConditionalSend + Sync + 'static {
         player: &'a PlayerData,
         opponent: &'a OpponentData,
         history: &'a [ChatRecord],
-    ) -> BoxedFuture<'a, Stake> {
-        Box::pin(async move { Default::default() })
+    ) -> BoxedFuture<'a, Result<Stake, ActorError>> {
+        Box::pin(async move { Ok(Default::default()) })
     }
 
     /// Accept the duel or not. If accepts, draw a card from the inventory.
@@ -843,25 +1416,15 @@ pub trait Actor: ConditionalSend + Sync + 'static {
         opponent: &'a OpponentData,
         history: &'a [ChatRecord],
         state: StakeState<'a>,
-    ) -> BoxedFuture<'a, Option<Card>> {
-        Box::pin(async move {
-            let deck = [
-                vec![Card::Rock; player.inventory.rock],
-                vec![Card::Paper; player.inventory.paper],
-                vec![Card::Scissors; player.inventory.scissors],
-            ]
-            .concat();
-            fastrand::choice(&deck).cloned()
-        })
-    }
+    ) -> BoxedFuture<'a, Result<Option<Card>, ActorError>>;
 
     /// Feedback on the duel result.
     fn feedback_duel<'a>(
         &'a mut self,
         player: &'a PlayerData,
         result: DuelResult,
-    ) -> BoxedFuture<'a, ()> {
-        Box::pin(async move {})
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move { Ok(()) })
     }
 
     fn dump<'a>(&'a self, player: &'a PlayerData) -> BoxedFuture<'a, Result<Vec<u8>>> {
@@ -869,20 +1432,128 @@ pub trait Actor: ConditionalSend + Sync + 'static {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
-pub struct DummyActor;
+/// An actor that plays uniformly at random.
+#[derive(Debug, Default, Clone)]
+pub struct DummyActor {
+    pub rng: fastrand::Rng,
+}
+
+impl DummyActor {
+    pub fn new(rng: fastrand::Rng) -> Self {
+        Self { rng }
+    }
+}
+
+impl Actor for DummyActor {
+    fn trade<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+    ) -> BoxedFuture<'a, Result<Trade, ActorError>> {
+        Box::pin(async move {
+            let card = self.rng.choice(&player.inventory.deck()).cloned();
+            Ok(Trade::card(card))
+        })
+    }
+
+    fn accept_duel<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+        _state: StakeState<'a>,
+    ) -> BoxedFuture<'a, Result<Option<Card>, ActorError>> {
+        Box::pin(async move { Ok(self.rng.choice(&player.inventory.deck()).cloned()) })
+    }
+}
+
+/// What happens at a table so far, shared with the running duel to watch it live.
+#[derive(Debug, Derivative, Clone, Component)]
+#[derivative(Default)]
+pub struct DuelProgress {
+    #[derivative(Default(value = "Entity::PLACEHOLDER"))]
+    table: Entity,
+    #[derivative(Default(value = "[Entity::PLACEHOLDER; 2]"))]
+    players: [Entity; 2],
+    report: Arc<std::sync::Mutex<DuelReport>>,
+    /// Events of the steps taken, until they are sent to the game.
+    events: Arc<std::sync::Mutex<Vec<GameEvent>>>,
+}
+
+impl DuelProgress {
+    pub fn new(table: Entity, players: [Entity; 2]) -> Self {
+        Self {
+            table,
+            players,
+            ..Default::default()
+        }
+    }
+
+    pub fn report(&self) -> DuelReport {
+        self.report.lock().unwrap().clone()
+    }
+
+    /// Take the events of the steps taken since the last call.
+    pub fn drain(&self) -> Vec<GameEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    fn publish(&self, report: &DuelReport) {
+        *self.report.lock().unwrap() = report.clone();
+    }
+
+    /// Record the event of a step of the table as it happens.
+    fn send(&self, event: impl FnOnce(Entity, [Entity; 2]) -> GameEvent) {
+        let event = event(self.table, self.players);
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn send_chat(&self, lines: &[ChatLine], duel: bool) {
+        for line in lines {
+            self.send(|table, players| GameEvent::ChatSent {
+                table,
+                players,
+                player: players[line.player],
+                content: line.content.clone(),
+                duel,
+            });
+        }
+    }
+}
 
-impl Actor for DummyActor {}
+/// Send the events of running duels as their steps are taken.
+fn send_duel_events(mut events: EventWriter<GameEvent>, tables: Query<&DuelProgress>) {
+    for progress in &tables {
+        events.send_batch(progress.drain());
+    }
+}
 
 pub async fn duel(
+    rules: Rules,
+    state: PublicState,
+    actors: [Arc<Mutex<dyn Actor>>; 2],
+    data: [PlayerData; 2],
+) -> Result<([Inventory; 2], DuelReport), TableError> {
+    duel_with_progress(rules, state, actors, data, Default::default()).await
+}
+
+/// Run the duel, publishing each step to `progress` as it happens.
+pub async fn duel_with_progress(
+    rules: Rules,
     state: PublicState,
     [a0, a1]: [Arc<Mutex<dyn Actor>>; 2],
     [mut p0, mut p1]: [PlayerData; 2],
-) -> Result<[Inventory; 2]> {
+    progress: DuelProgress,
+) -> Result<([Inventory; 2], DuelReport), TableError> {
     let (mut a0, mut a1) = join!(a0.lock(), a1.lock());
+    let mut report = DuelReport::default();
 
     // step 1: notify both players about public state
-    join!(a0.notify(&p0, &state), a1.notify(&p1, &state));
+    both(join!(
+        a0.notify(&p0, &rules, &state),
+        a1.notify(&p1, &rules, &state)
+    ))?;
 
     // step 2: players chat before trade
     let mut history: Vec<ChatRecord> = vec![];
@@ -899,212 +1570,360 @@ pub async fn duel(
             .collect_vec()
     };
 
-    for round in 0..NUM_CHAT_ROUNDS {
+    for round in 0..rules.num_chat_rounds {
         let h0 = observe(&p0, &history);
         let q0 = p1.clone().into();
         let r0 = round * 2;
-        let mut records = a0.chat(&p0, &q0, &h0, ChatKind::Trade(r0)).await;
+        let mut records = a0
+            .chat(&p0, &q0, &h0, ChatKind::Trade(r0))
+            .await
+            .map_err(blame(0))?;
+        let lines = records
+            .iter()
+            .map(|x| ChatLine {
+                player: 0,
+                content: x.content.trim().to_string(),
+            })
+            .collect_vec();
+        progress.send_chat(&lines, false);
+        report.chat.extend(lines);
+        progress.publish(&report);
         history.append(&mut records);
 
         let h1 = observe(&p1, &history);
         let q1 = p0.clone().into();
         let r1 = r0 + 1;
-        let mut records = a1.chat(&p1, &q1, &h1, ChatKind::Trade(r1)).await;
+        let mut records = a1
+            .chat(&p1, &q1, &h1, ChatKind::Trade(r1))
+            .await
+            .map_err(blame(1))?;
+        let lines = records
+            .iter()
+            .map(|x| ChatLine {
+                player: 1,
+                content: x.content.trim().to_string(),
+            })
+            .collect_vec();
+        progress.send_chat(&lines, false);
+        report.chat.extend(lines);
+        progress.publish(&report);
         history.append(&mut records);
     }
 
     // step 3: players trade
     let (t0, t1) = {
-        let (Some((t0, x0)), Some((t1, x1))) = join!(
+        let (r0, r1) = both(join!(
             async {
                 let mut round = 0;
                 loop {
-                    if round > MAX_TRAIL_ROUNDS {
-                        break None;
+                    if round > rules.max_trail_rounds {
+                        break Ok(None);
                     }
                     round += 1;
 
                     let h0 = observe(&p0, &history);
                     let q0 = p1.clone().into();
-                    let trade = a0.trade(&p0, &q0, &h0).await;
+                    let trade = a0.trade(&p0, &q0, &h0).await?;
                     let inventory = match p0.inventory.split_trade(&trade) {
                         Ok(inventory) => inventory,
                         Err(err) => {
-                            a0.feedback_error(&p0, format!("Error: {err}")).await;
+                            a0.feedback_error(&p0, format!("Error: {err}")).await?;
                             continue;
                         }
                     };
 
-                    break Some((trade, inventory));
+                    break Ok(Some((trade, inventory)));
                 }
             },
             async {
                 let mut round = 0;
                 loop {
-                    if round > MAX_TRAIL_ROUNDS {
-                        break None;
+                    if round > rules.max_trail_rounds {
+                        break Ok(None);
                     }
                     round += 1;
 
                     let h1 = observe(&p1, &history);
                     let q1 = p0.clone().into();
-                    let trade = a1.trade(&p1, &q1, &h1).await;
+                    let trade = a1.trade(&p1, &q1, &h1).await?;
                     let inventory = match p1.inventory.split_trade(&trade) {
                         Ok(inventory) => inventory,
                         Err(err) => {
-                            a1.feedback_error(&p1, format!("Error: {err}")).await;
+                            a1.feedback_error(&p1, format!("Error: {err}")).await?;
                             continue;
                         }
                     };
 
-                    break Some((trade, inventory));
+                    break Ok(Some((trade, inventory)));
                 }
             }
-        ) else {
-            bail!("trade failed too many times");
-        };
+        ))?;
+        let (t0, x0) = r0.ok_or(TableError::Invalid(0, "trades"))?;
+        let (t1, x1) = r1.ok_or(TableError::Invalid(1, "trades"))?;
 
         // success, update inventories
         p0.inventory = x0;
         p1.inventory = x1;
         (t0, t1)
     };
+    report.trades = [t0.clone(), t1.clone()];
+    progress.publish(&report);
+    progress.send(|table, players| GameEvent::TradeProposed {
+        table,
+        players,
+        trades: report.trades.clone(),
+    });
 
-    // step 4: players agree on the trade
+    // step 4: players agree on the trade, or counter each other's offers
     {
-        let q0 = p1.clone().into();
-        let q1 = p0.clone().into();
-        match join!(
-            a0.accept_trade(
-                &p0,
-                &q0,
-                &history,
-                TradeState {
-                    this: &t0,
-                    that: &t1
+        // what each player gives under the contract on the table, held out of their inventories
+        let mut trades = [t0, t1];
+        // players yet to answer the contract; one making a counter-offer signs it already
+        let mut pending = [true, true];
+        let mut counters = rules.max_counter_offers;
+        let accepted = loop {
+            let q0 = p1.clone().into();
+            let q1 = p0.clone().into();
+            let [t0, t1] = &trades;
+            let responses = both(join!(
+                async {
+                    if !pending[0] {
+                        return Ok(None);
+                    }
+                    let state = TradeState { this: t0, that: t1 };
+                    let response = a0.respond_trade(&p0, &q0, &history, state, counters);
+                    response.await.map(Some)
+                },
+                async {
+                    if !pending[1] {
+                        return Ok(None);
+                    }
+                    let state = TradeState { this: t1, that: t0 };
+                    let response = a1.respond_trade(&p1, &q1, &history, state, counters);
+                    response.await.map(Some)
                 }
-            ),
-            a1.accept_trade(
-                &p1,
-                &q1,
-                &history,
-                TradeState {
-                    this: &t1,
-                    that: &t0
+            ))?;
+            let responses = [responses.0, responses.1];
+
+            // if both counter at once, the first player's counter-offer stands on even turns
+            let turn = (rules.max_counter_offers - counters) % 2;
+            let counter = [turn, 1 - turn]
+                .into_iter()
+                .find_map(|index| match &responses[index] {
+                    Some(TradeResponse::Counter { this, that }) => Some((index, this, that)),
+                    _ => None,
+                })
+                .filter(|_| counters > 0);
+            if let Some((index, this, that)) = counter {
+                let offer = match index {
+                    0 => [this.clone(), that.clone()],
+                    _ => [that.clone(), this.clone()],
+                };
+                // take the amended items out of what players hold before the contract
+                let split = |data: &PlayerData, escrow: &Trade, trade: &Trade| {
+                    let mut inventory = data.inventory.clone();
+                    inventory.apply_trade(escrow);
+                    inventory.split_trade(trade)
+                };
+                let x0 = split(&p0, &trades[0], &offer[0]);
+                let x1 = split(&p1, &trades[1], &offer[1]);
+                match (x0, x1) {
+                    (Ok(x0), Ok(x1)) => {
+                        p0.inventory = x0;
+                        p1.inventory = x1;
+                        report.counters.push(CounterOffer {
+                            player: index,
+                            trades: offer.clone(),
+                        });
+                        progress.publish(&report);
+                        progress.send(|table, players| GameEvent::TradeCountered {
+                            table,
+                            players,
+                            player: players[index],
+                            trades: offer.clone(),
+                        });
+                        trades = offer;
+                        pending = [index != 0, index != 1];
+                        counters -= 1;
+                        continue;
+                    }
+                    // a counter-offer the other player cannot afford is a rejection
+                    (x0, x1) => {
+                        let errors = [x0.err(), x1.err()];
+                        if let Some(err) = &errors[index] {
+                            let (actor, data) = match index {
+                                0 => (&mut a0, &p0),
+                                _ => (&mut a1, &p1),
+                            };
+                            actor
+                                .feedback_error(data, format!("Error: {err}"))
+                                .await
+                                .map_err(blame(index))?;
+                        }
+                    }
                 }
-            )
-        ) {
-            (true, true) => {
+            }
+
+            // counter-offers that do not stand are rejections
+            break responses.map(|response| matches!(response, None | Some(TradeResponse::Accept)));
+        };
+        let [t0, t1] = trades;
+        progress.send(|table, players| GameEvent::TradeAccepted {
+            table,
+            players,
+            accepted,
+        });
+
+        match accepted {
+            [true, true] => {
                 // players do reach an agreement, perform the trade
+                report.accepted = [true, true];
+                progress.publish(&report);
                 p0.inventory.apply_trade(&t1);
                 p1.inventory.apply_trade(&t0);
-                join!(
+                both(join!(
                     a0.feedback_trade(&p0, [true, true]),
                     a1.feedback_trade(&p1, [true, true])
-                );
+                ))?;
             }
-            (u0, u1) => {
+            [u0, u1] => {
                 // players do not reach an agreement, rewind
+                report.accepted = [u0, u1];
+                progress.publish(&report);
                 p0.inventory.apply_trade(&t0);
                 p1.inventory.apply_trade(&t1);
-                join!(
+                both(join!(
                     a0.feedback_trade(&p0, [u0, u1]),
                     a1.feedback_trade(&p1, [u1, u0])
-                );
+                ))?;
             }
         }
     }
 
     // check if we can proceed to duel
     if [&p0, &p1].iter().any(|x| !x.inventory.can_duel()) {
-        return Ok([p0, p1].map(|x| x.inventory));
+        return Ok(([p0, p1].map(|x| x.inventory), report));
     }
 
     // step 5: player chat before duel
-    // let mut history: Vec<ChatRecord> = vec![];
-
-    for round in 0..NUM_CHAT_ROUNDS {
+    for round in 0..rules.num_chat_rounds {
         let h0 = observe(&p0, &history);
         let q0 = p1.clone().into();
         let r0 = round * 2;
-        let mut records = a0.chat(&p0, &q0, &h0, ChatKind::Duel(r0)).await;
+        let mut records = a0
+            .chat(&p0, &q0, &h0, ChatKind::Duel(r0))
+            .await
+            .map_err(blame(0))?;
+        let lines = records
+            .iter()
+            .map(|x| ChatLine {
+                player: 0,
+                content: x.content.trim().to_string(),
+            })
+            .collect_vec();
+        progress.send_chat(&lines, true);
+        report.duel_chat.extend(lines);
+        progress.publish(&report);
         history.append(&mut records);
 
         let h1 = observe(&p1, &history);
         let q1 = p0.clone().into();
         let r1 = r0 + 1;
-        let mut records = a1.chat(&p1, &q1, &h1, ChatKind::Duel(r1)).await;
+        let mut records = a1
+            .chat(&p1, &q1, &h1, ChatKind::Duel(r1))
+            .await
+            .map_err(blame(1))?;
+        let lines = records
+            .iter()
+            .map(|x| ChatLine {
+                player: 1,
+                content: x.content.trim().to_string(),
+            })
+            .collect_vec();
+        progress.send_chat(&lines, true);
+        report.duel_chat.extend(lines);
+        progress.publish(&report);
         history.append(&mut records);
     }
 
     // step 6: players bet
     let (s0, s1) = {
-        let (Some((s0, x0)), Some((s1, x1))) = join!(
+        let (r0, r1) = both(join!(
             async {
                 let mut round = 0;
                 loop {
-                    if round > MAX_TRAIL_ROUNDS {
-                        break None;
+                    if round > rules.max_trail_rounds {
+                        break Ok(None);
                     }
                     round += 1;
 
                     let h0 = observe(&p0, &history);
                     let q0 = p1.clone().into();
-                    let stake = a0.bet(&p0, &q0, &h0).await;
+                    let stake = a0.bet(&p0, &q0, &h0).await?;
+                    let stake = stake.normalize(&rules, &p0.inventory);
                     let inventory = match p0.inventory.split_stake(&stake) {
                         Ok(inventory) => inventory,
                         Err(err) => {
-                            a0.feedback_error(&p0, format!("Error: {err}")).await;
+                            a0.feedback_error(&p0, format!("Error: {err}")).await?;
                             continue;
                         }
                     };
 
-                    break Some((stake, inventory));
+                    break Ok(Some((stake, inventory)));
                 }
             },
             async {
                 let mut round = 0;
                 loop {
-                    if round > MAX_TRAIL_ROUNDS {
-                        break None;
+                    if round > rules.max_trail_rounds {
+                        break Ok(None);
                     }
                     round += 1;
 
-                    let h1 = observe(&p0, &history);
+                    let h1 = observe(&p1, &history);
                     let q1 = p0.clone().into();
-                    let stake = a1.bet(&p1, &q1, &h1).await;
+                    let stake = a1.bet(&p1, &q1, &h1).await?;
+                    let stake = stake.normalize(&rules, &p1.inventory);
                     let inventory = match p1.inventory.split_stake(&stake) {
                         Ok(inventory) => inventory,
                         Err(err) => {
-                            a1.feedback_error(&p1, format!("Error: {err}")).await;
+                            a1.feedback_error(&p1, format!("Error: {err}")).await?;
                             continue;
                         }
                     };
 
-                    break Some((stake, inventory));
+                    break Ok(Some((stake, inventory)));
                 }
             }
-        ) else {
-            bail!("bet failed too many times");
-        };
+        ))?;
+        let (s0, x0) = r0.ok_or(TableError::Invalid(0, "bets"))?;
+        let (s1, x1) = r1.ok_or(TableError::Invalid(1, "bets"))?;
 
         // success, update inventories
         p0.inventory = x0;
         p1.inventory = x1;
         (s0, s1)
     };
+    report.stakes = Some([s0.clone(), s1.clone()]);
+    progress.publish(&report);
+    progress.send(|table, players| GameEvent::StakesPlaced {
+        table,
+        players,
+        stakes: [s0.clone(), s1.clone()],
+    });
 
     // step 7: players agree on the duel
     let mut round = 0;
+    let mut invalid = 0;
     let cards = loop {
-        if round > MAX_TRAIL_ROUNDS {
-            bail!("duel failed too many times");
+        if round > rules.max_trail_rounds {
+            return Err(TableError::Invalid(invalid, "draws"));
         }
         round += 1;
 
         let q0 = p1.clone().into();
         let q1 = p0.clone().into();
-        let cards = join!(
+        let cards = both(join!(
             a0.accept_duel(
                 &p0,
                 &q0,
@@ -1123,20 +1942,26 @@ pub async fn duel(
                     that: &s0
                 }
             )
-        );
+        ))?;
 
-        if let (Some(lhs), Some(rhs)) = cards {
+        if let (Some(lhs), Some(rhs)) = &cards {
             let x0 = match p0.inventory.split_duel(lhs) {
                 Ok(inventory) => inventory,
                 Err(err) => {
-                    a0.feedback_error(&p0, format!("Error: {err}")).await;
+                    a0.feedback_error(&p0, format!("Error: {err}"))
+                        .await
+                        .map_err(blame(0))?;
+                    invalid = 0;
                     continue;
                 }
             };
             let x1 = match p1.inventory.split_duel(rhs) {
                 Ok(inventory) => inventory,
                 Err(err) => {
-                    a1.feedback_error(&p1, format!("Error: {err}")).await;
+                    a1.feedback_error(&p1, format!("Error: {err}"))
+                        .await
+                        .map_err(blame(1))?;
+                    invalid = 1;
                     continue;
                 }
             };
@@ -1148,31 +1973,75 @@ pub async fn duel(
 
         break cards;
     };
+    report.cards = [cards.0.clone(), cards.1.clone()];
+    progress.publish(&report);
+    progress.send(|table, players| GameEvent::CardsDrawn {
+        table,
+        players,
+        cards: report.cards.clone(),
+    });
 
     match cards {
-        (Some(lhs), Some(rhs)) => match lhs.compare(rhs) {
+        (Some(lhs), Some(rhs)) => match rules.compare(&lhs, &rhs) {
             Some(index) => {
-                let stake = s0 + s1;
+                // a weighted winning card makes the loser pay extra stars on top of the stake
+                let card = [&lhs, &rhs][index];
+                let extra = [&s1, &s0][index].star * (rules.weight(card).max(1) - 1);
+                let extra = {
+                    let loser = &mut [&mut p1, &mut p0][index].inventory;
+                    let extra = extra.min(loser.star);
+                    loser.star -= extra;
+                    extra
+                };
+
+                let stake = s0
+                    + s1
+                    + Stake {
+                        star: extra,
+                        coin: 0,
+                    };
                 [&mut p0, &mut p1][index].inventory.apply_stake(&stake);
-                match index {
+                let result = match index {
+                    0 => DuelResult::Win(lhs.clone(), rhs.clone()),
+                    _ => DuelResult::Lose(lhs.clone(), rhs.clone()),
+                };
+                report.result = Some(result.clone());
+                progress.publish(&report);
+                progress.send(|table, players| GameEvent::DuelFinished {
+                    table,
+                    players,
+                    winner: Some(players[index]),
+                    result,
+                    inventories: [p0.inventory.clone(), p1.inventory.clone()],
+                });
+                both(match index {
                     0 => join!(
-                        a0.feedback_duel(&p0, DuelResult::Win(lhs, rhs)),
+                        a0.feedback_duel(&p0, DuelResult::Win(lhs.clone(), rhs.clone())),
                         a1.feedback_duel(&p1, DuelResult::Lose(rhs, lhs))
                     ),
                     1 => join!(
-                        a0.feedback_duel(&p0, DuelResult::Lose(lhs, rhs)),
+                        a0.feedback_duel(&p0, DuelResult::Lose(lhs.clone(), rhs.clone())),
                         a1.feedback_duel(&p1, DuelResult::Win(rhs, lhs))
                     ),
                     _ => unreachable!(),
-                };
+                })?;
             }
             None => {
                 p0.inventory.apply_stake(&s0);
                 p1.inventory.apply_stake(&s1);
-                join!(
-                    a0.feedback_duel(&p0, DuelResult::Tie(lhs)),
-                    a1.feedback_duel(&p1, DuelResult::Tie(lhs))
-                );
+                report.result = Some(DuelResult::Tie(lhs.clone()));
+                progress.publish(&report);
+                progress.send(|table, players| GameEvent::DuelFinished {
+                    table,
+                    players,
+                    winner: None,
+                    result: DuelResult::Tie(lhs.clone()),
+                    inventories: [p0.inventory.clone(), p1.inventory.clone()],
+                });
+                both(join!(
+                    a0.feedback_duel(&p0, DuelResult::Tie(lhs.clone())),
+                    a1.feedback_duel(&p1, DuelResult::Tie(rhs))
+                ))?;
             }
         },
         _ => {
@@ -1181,5 +2050,17 @@ pub async fn duel(
         }
     }
 
-    Ok([p0, p1].map(|x| x.inventory))
+    Ok(([p0, p1].map(|x| x.inventory), report))
+}
+
+/// Blame the player at the table for failing to act.
+fn blame(index: usize) -> impl Fn(ActorError) -> TableError {
+    move |err| TableError::Actor(index, err)
+}
+
+/// Results of both players acting at the same time, blaming the first failing one.
+fn both<T, U>(
+    (x, y): (Result<T, ActorError>, Result<U, ActorError>),
+) -> Result<(T, U), TableError> {
+    Ok((x.map_err(blame(0))?, y.map_err(blame(1))?))
 }
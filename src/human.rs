@@ -0,0 +1,409 @@
+use std::fmt::Display;
+
+use async_std::{
+    io::{stdin, WriteExt},
+    sync::Mutex,
+};
+use bevy::utils::BoxedFuture;
+use itertools::Itertools;
+
+use crate::{
+    game::{
+        Actor, ActorError, Card, Cards, ChatKind, ChatRecord, DuelResult, OpponentData, PlayerData,
+        PublicState, Role, Stake, StakeState, Trade, TradeState,
+    },
+    rules::Rules,
+};
+
+/// Prevents human players at different tables from talking over each other.
+static TERMINAL: Mutex<()> = Mutex::new(());
+
+/// Print the prompt and read a trimmed line from stdin. Returns `None` if stdin is closed.
+async fn read_line(prompt: impl Display) -> Option<String> {
+    let mut stdout = async_std::io::stdout();
+    let _ = stdout.write_all(format!("{prompt}").as_bytes()).await;
+    let _ = stdout.flush().await;
+
+    let mut line = String::new();
+    match stdin().read_line(&mut line).await {
+        Ok(0) => None,
+        Ok(_) => Some(line.trim().to_owned()),
+        Err(err) => {
+            bevy::log::error!("{err}");
+            None
+        }
+    }
+}
+
+/// Read a number from stdin. An empty line stands for 0.
+async fn read_number(prompt: impl Display) -> Option<usize> {
+    loop {
+        let line = read_line(&prompt).await?;
+        if line.is_empty() {
+            break Some(0);
+        }
+        match line.parse() {
+            Ok(x) => break Some(x),
+            Err(_) => println!("Please enter a non-negative number."),
+        }
+    }
+}
+
+/// Read yes or no from stdin. An empty line stands for no.
+async fn read_bool(prompt: impl Display) -> Option<bool> {
+    loop {
+        match read_line(&prompt).await?.to_lowercase().as_str() {
+            "y" | "yes" => break Some(true),
+            "" | "n" | "no" => break Some(false),
+            _ => println!("Please enter \"y\" or \"n\"."),
+        }
+    }
+}
+
+/// A player controlled by a human over the terminal.
+/// Once stdin is closed, it stays silent, offers and accepts nothing, and refuses to duel.
+#[derive(Debug, Default, Clone)]
+pub struct HumanActor {
+    pub rules: Rules,
+    /// Chat records already shown to the human.
+    pub chat: Vec<ChatRecord>,
+}
+
+impl HumanActor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn print_cards(&self, cards: &Cards) -> String {
+        self.rules
+            .cards
+            .iter()
+            .map(|kind| {
+                let count = cards.get(&kind.name).copied().unwrap_or_default();
+                format!("{}: {count}", kind.name)
+            })
+            .join(", ")
+    }
+
+    fn print_player(&self, player: &PlayerData) {
+        let inventory = &player.inventory;
+        println!(
+            "[{}] stars: {}, coins: {}, cards: {} ({})",
+            player.name,
+            inventory.star,
+            inventory.coin,
+            inventory.num_cards(),
+            self.print_cards(&inventory.cards)
+        );
+    }
+
+    fn print_opponent(&self, opponent: &OpponentData) {
+        println!(
+            "[{}] stars: {}, cards: {}",
+            opponent.name, opponent.star, opponent.card
+        );
+    }
+
+    fn print_trade(&self, trade: &Trade) -> String {
+        format!(
+            "stars: {}, coins: {}, {}",
+            trade.star,
+            trade.coin,
+            self.print_cards(&trade.cards)
+        )
+    }
+
+    /// Print records in the history not yet shown.
+    fn print_history(&mut self, history: &[ChatRecord]) {
+        for record in history {
+            if !self.chat.contains(record) {
+                println!("  {record}");
+                self.chat.push(record.clone());
+            }
+        }
+    }
+
+    pub async fn notify(&mut self, player: &PlayerData, rules: &Rules, state: &PublicState) {
+        self.rules = rules.clone();
+        self.chat.clear();
+
+        let _lock = TERMINAL.lock().await;
+        println!();
+        println!("========== {}: a new round begins ==========", player.name);
+        println!("{}", rules.describe_beats());
+        println!(
+            "Players left: {}, cards on the stage: {} ({})",
+            state.player,
+            state.total_cards(),
+            self.print_cards(&state.cards)
+        );
+        println!("Rounds left: {}", player.timer.0);
+        self.print_player(player);
+    }
+
+    pub async fn feedback_error(&mut self, player: &PlayerData, text: String) {
+        let _lock = TERMINAL.lock().await;
+        println!("[{}] {text}", player.name);
+    }
+
+    pub async fn chat(
+        &mut self,
+        player: &PlayerData,
+        opponent: &OpponentData,
+        history: &[ChatRecord],
+        kind: ChatKind,
+    ) -> Vec<ChatRecord> {
+        let _lock = TERMINAL.lock().await;
+        let round = match kind {
+            ChatKind::Trade(round) => format!("trade chat {}", round / 2 + 1),
+            ChatKind::Duel(round) => format!("duel chat {}", round / 2 + 1),
+        };
+        println!("---------- {}: {round} ----------", player.name);
+        self.print_opponent(opponent);
+        self.print_history(history);
+
+        let line = read_line(format!("{} (empty to stay silent)> ", player.name)).await;
+        match line.filter(|line| !line.is_empty()) {
+            None => vec![],
+            Some(line) => {
+                let record = ChatRecord::new(Role::actor(player.entity, &player.name), line);
+                self.chat.push(record.clone());
+                vec![record]
+            }
+        }
+    }
+
+    pub async fn trade(
+        &mut self,
+        player: &PlayerData,
+        opponent: &OpponentData,
+        history: &[ChatRecord],
+    ) -> Trade {
+        let _lock = TERMINAL.lock().await;
+        println!("---------- {}: trade ----------", player.name);
+        self.print_history(history);
+        self.print_player(player);
+        println!("What would you like to offer {}?", opponent.name);
+
+        loop {
+            let Some(trade) = self.read_trade().await else {
+                break Trade::default();
+            };
+            match player.inventory.split_trade(&trade) {
+                Ok(_) => break trade,
+                Err(err) => println!("Error: {err}"),
+            }
+        }
+    }
+
+    async fn read_trade(&self) -> Option<Trade> {
+        let star = read_number("stars> ").await?;
+        let coin = read_number("coins> ").await?;
+        let mut cards = Cards::new();
+        for kind in &self.rules.cards {
+            let count = read_number(format!("{} cards> ", kind.name)).await?;
+            cards.insert(kind.name.clone(), count);
+        }
+        Some(Trade { star, coin, cards })
+    }
+
+    pub async fn accept_trade(
+        &mut self,
+        player: &PlayerData,
+        opponent: &OpponentData,
+        state: TradeState<'_>,
+    ) -> bool {
+        let _lock = TERMINAL.lock().await;
+        println!("---------- {}: contract ----------", player.name);
+        println!(
+            "You give {}: {}",
+            opponent.name,
+            self.print_trade(state.this)
+        );
+        println!(
+            "{} gives you: {}",
+            opponent.name,
+            self.print_trade(state.that)
+        );
+        read_bool("Sign the contract? [y/N]> ")
+            .await
+            .unwrap_or_default()
+    }
+
+    pub async fn feedback_trade(&mut self, player: &PlayerData, state: [bool; 2]) {
+        let _lock = TERMINAL.lock().await;
+        match state {
+            [true, true] => println!("Both parties signed, the trade is done."),
+            _ => println!("The contract is void."),
+        }
+        self.print_player(player);
+    }
+
+    pub async fn bet(
+        &mut self,
+        player: &PlayerData,
+        opponent: &OpponentData,
+        history: &[ChatRecord],
+    ) -> Stake {
+        let _lock = TERMINAL.lock().await;
+        println!("---------- {}: bet ----------", player.name);
+        self.print_history(history);
+        self.print_opponent(opponent);
+        self.print_player(player);
+
+        let min_stake = self.rules.min_stake;
+        loop {
+            let prompt = format!("stars (at least {min_stake})> ");
+            let (Some(star), Some(coin)) =
+                (read_number(prompt).await, read_number("coins> ").await)
+            else {
+                break Stake::default();
+            };
+
+            let stake = Stake { star, coin };
+            if star < min_stake {
+                println!("Error: you must bet at least {min_stake} star(s)");
+                continue;
+            }
+            match player.inventory.split_stake(&stake) {
+                Ok(_) => break stake,
+                Err(err) => println!("Error: {err}"),
+            }
+        }
+    }
+
+    pub async fn accept_duel(
+        &mut self,
+        player: &PlayerData,
+        opponent: &OpponentData,
+        state: StakeState<'_>,
+    ) -> Option<Card> {
+        let _lock = TERMINAL.lock().await;
+        println!("---------- {}: duel ----------", player.name);
+        println!(
+            "You bet {} star(s) and {} coin(s); {} bets {} star(s) and {} coin(s).",
+            state.this.star, state.this.coin, opponent.name, state.that.star, state.that.coin
+        );
+        self.print_player(player);
+
+        loop {
+            let line = read_line("Card to draw (empty to refuse the duel)> ").await?;
+            if line.is_empty() {
+                break None;
+            }
+            let Some(card) = self.rules.parse_card(&line) else {
+                println!("Error: there is no card named \"{line}\"");
+                continue;
+            };
+            match player.inventory.split_duel(&card) {
+                Ok(_) => break Some(card),
+                Err(err) => println!("Error: {err}"),
+            }
+        }
+    }
+
+    pub async fn feedback_duel(&mut self, player: &PlayerData, result: DuelResult) {
+        let _lock = TERMINAL.lock().await;
+        match result {
+            DuelResult::Tie(card) => println!("It's a tie, you both draw \"{card}\"."),
+            DuelResult::Win(this, that) => println!("\"{this}\" vs. \"{that}\". You win!"),
+            DuelResult::Lose(this, that) => println!("\"{this}\" vs. \"{that}\". You lose."),
+        }
+        self.print_player(player);
+    }
+}
+
+impl Actor for HumanActor {
+    fn notify<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        rules: &'a Rules,
+        state: &'a PublicState,
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move {
+            self.notify(player, rules, state).await;
+            Ok(())
+        })
+    }
+
+    fn feedback_error<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        text: String,
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move {
+            self.feedback_error(player, text).await;
+            Ok(())
+        })
+    }
+
+    fn chat<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+        kind: ChatKind,
+    ) -> BoxedFuture<'a, Result<Vec<ChatRecord>, ActorError>> {
+        Box::pin(async move { Ok(self.chat(player, opponent, history, kind).await) })
+    }
+
+    fn trade<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+    ) -> BoxedFuture<'a, Result<Trade, ActorError>> {
+        Box::pin(async move { Ok(self.trade(player, opponent, history).await) })
+    }
+
+    fn accept_trade<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+        state: TradeState<'a>,
+    ) -> BoxedFuture<'a, Result<bool, ActorError>> {
+        Box::pin(async move { Ok(self.accept_trade(player, opponent, state).await) })
+    }
+
+    fn feedback_trade<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        state: [bool; 2],
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move {
+            self.feedback_trade(player, state).await;
+            Ok(())
+        })
+    }
+
+    fn bet<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+    ) -> BoxedFuture<'a, Result<Stake, ActorError>> {
+        Box::pin(async move { Ok(self.bet(player, opponent, history).await) })
+    }
+
+    fn accept_duel<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+        state: StakeState<'a>,
+    ) -> BoxedFuture<'a, Result<Option<Card>, ActorError>> {
+        Box::pin(async move { Ok(self.accept_duel(player, opponent, state).await) })
+    }
+
+    fn feedback_duel<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        result: DuelResult,
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move {
+            self.feedback_duel(player, result).await;
+            Ok(())
+        })
+    }
+}
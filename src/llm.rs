@@ -1,19 +1,45 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use anyhow::Result;
 use async_std::sync::Mutex;
-use bevy::{core::Name, utils::BoxedFuture};
+use bevy::{core::Name, reflect::Reflect, utils::BoxedFuture};
+use clap::ValueEnum;
 use derivative::Derivative;
-use futures::join;
+use futures::{future::join_all, join};
 use itertools::Itertools;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
-
-use crate::game::{
-    Actor, Card, ChatKind, ChatRecord, DuelResult, DummyActor, Inventory, OpponentData, PlayerData,
-    PublicState, Role, Stake, StakeState, Trade, TradeState, ASSISTANT_NAME, NUM_CHAT_ROUNDS,
-    SYSTEM_NAME,
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::{Ai00Backend, LlmBackend, LlmError, LlmLimiter, RetryPolicy},
+    game::{
+        Actor, ActorError, Card, Cards, ChatKind, ChatRecord, ChatRecordId, DuelResult, Inventory,
+        OpponentData, PlayerData, PublicState, Role, Stake, StakeState, Trade, TradeResponse,
+        TradeState, ASSISTANT_NAME, SYSTEM_NAME,
+    },
+    memory::Memory,
+    replay::describe_trade,
+    rules::Rules,
 };
 
+/// Most recent chat records kept as they are when the story is summarized.
+pub const SUMMARY_KEEP_RECORDS: usize = 8;
+
+/// How LLM players fill in their trade offers.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Reflect, Serialize, Deserialize,
+)]
+pub enum TradeMode {
+    /// Ask about each item in turn: whether to offer it, then how many.
+    #[default]
+    Items,
+    /// Fill in the whole offer in one completion, constrained to a JSON object.
+    Json,
+}
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SamplerKind {
     #[default]
@@ -50,7 +76,7 @@ pub struct CompletionRequest {
     pub stop: Vec<String>,
     pub stream: bool,
     pub bias: HashMap<u16, f32>,
-    #[serde(skip_serializing_if = "String::is_empty")]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub bnf_schema: String,
     #[derivative(Default(value = "1024"))]
     pub max_tokens: u32,
@@ -106,8 +132,8 @@ pub struct Choice {
 pub enum LlmRecord {
     Completion {
         role: Role,
-        player: Option<PlayerData>,
-        opponent: Option<OpponentData>,
+        player: Option<Box<PlayerData>>,
+        opponent: Option<Box<OpponentData>>,
         request: Box<CompletionRequest>,
         response: Box<CompletionResponse>,
     },
@@ -118,24 +144,79 @@ pub enum LlmRecord {
     },
 }
 
-#[derive(Debug, Default, Clone)]
+/// An offer filled in by the LLM in [`TradeMode::Json`].
+/// Unknown keys are ignored, and counts that are not natural numbers are read as 0.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct JsonOffer {
+    star: JsonCount,
+    coin: JsonCount,
+    #[serde(flatten)]
+    cards: HashMap<String, JsonCount>,
+}
+
+impl JsonOffer {
+    fn parse(content: &str) -> Result<Self, LlmError> {
+        // servers without grammar may say more than the object
+        let object = content
+            .find('{')
+            .zip(content.rfind('}'))
+            .and_then(|(start, end)| content.get(start..=end))
+            .unwrap_or(content);
+        serde_json::from_str(object)
+            .map_err(|err| LlmError::Parse(format!("{err}: {}", content.trim())))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct JsonCount(usize);
+
+impl<'de> Deserialize<'de> for JsonCount {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let count = match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::Number(x) => x.as_u64().unwrap_or_default() as usize,
+            serde_json::Value::String(x) => x.trim().parse().unwrap_or_default(),
+            _ => 0,
+        };
+        Ok(Self(count))
+    }
+}
+
+#[derive(Debug, Derivative, Clone)]
+#[derivative(Default)]
 pub struct LlmActor {
-    pub url: String,
+    #[derivative(Default(value = "Arc::new(Ai00Backend::default())"))]
+    pub backend: Arc<dyn LlmBackend>,
     pub output: PathBuf,
 
+    pub rules: Rules,
     pub chat: Vec<ChatRecord>,
     pub history: Arc<Mutex<Vec<LlmRecord>>>,
 
     pub state: uuid::Uuid,
-    pub dummy: DummyActor,
+    pub rng: fastrand::Rng,
+    pub retry: RetryPolicy,
+    /// Shared with other players, so that they don't flood the LLM server together.
+    pub limiter: Arc<LlmLimiter>,
+    pub trade_mode: TradeMode,
+    /// What the player remembers of previous tables.
+    pub memory: Memory,
+    /// Estimated tokens of the story before older records are summarized, 0 for no limit.
+    pub context_budget: usize,
+    /// Number of records at the start of the chat that are never summarized.
+    pub pinned: usize,
+    /// Public records already added to the chat, remembered after they are summarized.
+    pub seen: HashSet<uid::Id<ChatRecordId>>,
+    /// Whether the game rejected the last action of the player, who is asked to take it again.
+    pub rejected: bool,
 }
 
 impl LlmActor {
-    pub fn new(url: impl ToString, output: PathBuf) -> Self {
-        let url = url.to_string();
+    pub fn new(backend: Arc<dyn LlmBackend>, output: PathBuf, rng: fastrand::Rng) -> Self {
         Self {
-            url,
+            backend,
             output,
+            rng,
             ..Default::default()
         }
     }
@@ -149,6 +230,7 @@ impl LlmActor {
 
             text = match &record.role {
                 x if x == &last => format!("{text}\n{content}"),
+                Role::Think(_) => format!("{text}\n\n{content}"),
                 Role::Assistant(_) => format!("{text}\n\n{} (AI): {content}", record.role),
                 x => format!("{text}\n\n{x}: {content}"),
             };
@@ -167,6 +249,18 @@ impl LlmActor {
         format!("{}{prefix}", Self::prompt_story(records))
     }
 
+    /// Lists cards in a contract, one line per kind of card in the rules.
+    pub fn prompt_contract_cards(&self, cards: &Cards) -> String {
+        self.rules
+            .cards
+            .iter()
+            .map(|kind| {
+                let count = cards.get(&kind.name).copied().unwrap_or_default();
+                format!("  - {}: {count}", kind.name)
+            })
+            .join("\n")
+    }
+
     pub fn prompt_compact(records: &[ChatRecord]) -> String {
         Self::prompt_story(records)
             .replace("\n\n", "\n")
@@ -174,16 +268,50 @@ impl LlmActor {
             .to_string()
     }
 
-    pub async fn call_llm<T: DeserializeOwned>(
-        &self,
-        url: impl ToString,
-        request: &impl Serialize,
-    ) -> Result<T> {
-        async_std::task::yield_now().await;
-        let response = ehttp::fetch_async(ehttp::Request::json(url, request)?)
-            .await
-            .map_err(|err| anyhow::anyhow!(err))?;
-        Ok(response.json()?)
+    /// A rough count of tokens in the text, as servers do not share their tokenizers.
+    pub fn estimate_tokens(text: &str) -> usize {
+        text.chars().count().div_ceil(4)
+    }
+
+    /// If the story exceeds the context budget, replace older records after the pinned ones
+    /// with a summary written by the LLM, keeping the latest [`SUMMARY_KEEP_RECORDS`] as they are.
+    pub async fn summarize_chat(&mut self, player: &PlayerData) -> Result<(), ActorError> {
+        if self.context_budget == 0
+            || Self::estimate_tokens(&Self::prompt_story(&self.chat)) <= self.context_budget
+        {
+            return Ok(());
+        }
+        let end = self.chat.len().saturating_sub(SUMMARY_KEEP_RECORDS);
+        let start = self.pinned.min(end);
+        if end - start < 2 {
+            return Ok(());
+        }
+
+        let role = Role::Think(player.entity);
+        let prompt = format!(
+            include_str!("prompts/summary_0.md"),
+            player = player.name,
+            cards = self.rules.describe_cards(&player.inventory.cards),
+            story = Self::prompt_compact(&self.chat[start..end]),
+        );
+        let record = self
+            .chat_llm(
+                format!("[summary][{}]", player.name),
+                &role,
+                prompt,
+                "",
+                "",
+                &["\n\n"],
+                &[],
+                Some(player),
+                None,
+                Default::default(),
+            )
+            .await?;
+        let content = format!(include_str!("prompts/summary_1.md"), record.content.trim());
+        self.chat
+            .splice(start..end, [ChatRecord::new(role, content)]);
+        Ok(())
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -199,90 +327,87 @@ impl LlmActor {
         player: Option<&PlayerData>,
         opponent: Option<&OpponentData>,
         sampler: Sampler,
-    ) -> ChatRecord {
-        loop {
-            let head = head.as_ref();
-            let prompt = prompt.as_ref();
-            let prefix = prefix.as_ref();
-            let bnf_schema = bnf_schema.as_ref().into();
-            let sampler = sampler.clone();
-
-            let mut stop = stop.iter().map(|x| x.as_ref().to_string()).collect_vec();
+    ) -> Result<ChatRecord, LlmError> {
+        let head = head.as_ref();
+        let prompt = prompt.as_ref();
+        let prefix = prefix.as_ref();
+        let bnf_schema = bnf_schema.as_ref().into();
+
+        let mut stop = stop.iter().map(|x| x.as_ref().to_string()).collect_vec();
+        stop.extend([
+            format!("{}:", ASSISTANT_NAME),
+            format!("{}:", SYSTEM_NAME),
+            "\nUser:".into(),
+            "\nQ:".into(),
+            "\nAssistant:".into(),
+            "\nAI:".into(),
+        ]);
+        if let Some(player) = player {
             stop.extend([
-                format!("{}:", ASSISTANT_NAME),
-                format!("{}:", SYSTEM_NAME),
-                "\nUser:".into(),
-                "\nQ:".into(),
-                "\nAssistant:".into(),
-                "\nAI:".into(),
+                format!("\n{}", player.name),
+                format!("\n*{}", player.name),
+                format!("\n**{}", player.name),
+                format!("{}:", player.name),
             ]);
-            if let Some(player) = player {
-                stop.extend([
-                    format!("\n{}", player.name),
-                    format!("\n*{}", player.name),
-                    format!("\n**{}", player.name),
-                    format!("{}:", player.name),
-                ]);
-            }
-            if let Some(opponent) = opponent {
-                stop.extend([
-                    format!("\n{}", opponent.name),
-                    format!("\n*{}", opponent.name),
-                    format!("\n**{}", opponent.name),
-                    format!("\n({}", opponent.name),
-                    format!("{}:", opponent.name),
-                ]);
-            }
+        }
+        if let Some(opponent) = opponent {
+            stop.extend([
+                format!("\n{}", opponent.name),
+                format!("\n*{}", opponent.name),
+                format!("\n**{}", opponent.name),
+                format!("\n({}", opponent.name),
+                format!("{}:", opponent.name),
+            ]);
+        }
 
-            let bias = bias.iter().cloned().collect();
+        let bias = bias.iter().cloned().collect();
 
-            let request = CompletionRequest {
-                prompt: format!("{prompt}{prefix}"),
-                state: self.state,
-                stop,
-                bias,
-                sampler,
-                bnf_schema,
-                ..Default::default()
-            };
-            let response: CompletionResponse = match self
-                .call_llm(format!("{}/api/oai/completions", self.url), &request)
-                .await
-            {
-                Ok(response) => response,
-                Err(err) => {
-                    bevy::log::error!("{err}");
-                    continue;
-                }
-            };
+        let request = CompletionRequest {
+            prompt: format!("{prompt}{prefix}"),
+            state: self.state,
+            stop,
+            bias,
+            sampler,
+            bnf_schema,
+            ..Default::default()
+        };
 
-            let content = format!("{prefix}{}", response.model_text());
-            if content.is_empty() {
-                bevy::log::warn!("[{role}]{head} empty response");
-                continue;
-            }
+        let (backend, request_ref) = (&self.backend, &request);
+        let response = self
+            .retry
+            .run(format!("[{role}]{head}"), &self.limiter, || async move {
+                let response = backend
+                    .complete(request_ref)
+                    .await
+                    .map_err(|err| LlmError::Request(err.to_string()))?;
+                match prefix.is_empty() && response.model_text().is_empty() {
+                    true => Err(LlmError::Empty),
+                    false => Ok(response),
+                }
+            })
+            .await?;
 
-            let record = ChatRecord::new(role.clone(), content);
-            // bevy::log::info!("{head}[prompt] {prompt}{prefix}");
-            bevy::log::info!("{head} {record}");
-
-            {
-                let role = role.clone();
-                let player = player.cloned();
-                let opponent = opponent.cloned();
-                let request = Box::new(request);
-                let response = Box::new(response);
-                self.history.lock().await.push(LlmRecord::Completion {
-                    role,
-                    player,
-                    opponent,
-                    request,
-                    response,
-                });
-            }
+        let content = format!("{prefix}{}", response.model_text());
+        let record = ChatRecord::new(role.clone(), content);
+        // bevy::log::info!("{head}[prompt] {prompt}{prefix}");
+        bevy::log::info!("{head} {record}");
 
-            break record;
+        {
+            let role = role.clone();
+            let player = player.cloned().map(Box::new);
+            let opponent = opponent.cloned().map(Box::new);
+            let request = Box::new(request);
+            let response = Box::new(response);
+            self.history.lock().await.push(LlmRecord::Completion {
+                role,
+                player,
+                opponent,
+                request,
+                response,
+            });
         }
+
+        Ok(record)
     }
 
     pub async fn choose_llm(
@@ -291,56 +416,70 @@ impl LlmActor {
         role: &Role,
         prompt: impl AsRef<str>,
         choices: &[impl AsRef<str>],
-    ) -> Vec<String> {
-        loop {
-            let head = head.as_ref();
-            let prompt = prompt.as_ref().to_string();
-            let choices = choices
-                .iter()
-                .map(|choice| choice.as_ref().to_string())
-                .collect_vec();
+    ) -> Result<Vec<String>, LlmError> {
+        let head = head.as_ref();
+        let prompt = prompt.as_ref().to_string();
+        let choices = choices
+            .iter()
+            .map(|choice| choice.as_ref().to_string())
+            .collect_vec();
+
+        let request = ChooseRequest {
+            prompt,
+            state: self.state,
+            choices,
+            calibrate: true,
+        };
 
-            let request = ChooseRequest {
-                prompt,
-                state: self.state,
-                choices,
-                calibrate: true,
-            };
-            let response: ChooseResponse = match self
-                .call_llm(format!("{}/api/oai/chooses", self.url), &request)
-                .await
-            {
-                Ok(response) => response,
-                Err(err) => {
-                    bevy::log::error!("{err}");
-                    continue;
+        let (backend, request_ref) = (&self.backend, &request);
+        let response = self
+            .retry
+            .run(format!("[{role}]{head}"), &self.limiter, || async move {
+                // backends may tell why the response cannot be used
+                let response = backend.choose(request_ref).await.map_err(|err| {
+                    err.downcast::<LlmError>()
+                        .unwrap_or_else(|err| LlmError::Request(err.to_string()))
+                })?;
+                // nothing to choose from is not the server's fault
+                match response.data.is_empty() && !request_ref.choices.is_empty() {
+                    true => Err(LlmError::Empty),
+                    false => Ok(response),
                 }
-            };
+            })
+            .await?;
 
-            let choices = response
-                .data
-                .iter()
-                .map(|item| item.choice.clone())
-                .collect_vec();
-            bevy::log::info!("{head} {role}: {:?}", choices);
-
-            {
-                let role = role.clone();
-                let request = Box::new(request);
-                let response = Box::new(response);
-                self.history.lock().await.push(LlmRecord::Choose {
-                    role,
-                    request,
-                    response,
-                });
-            }
+        let choices = response
+            .data
+            .iter()
+            .map(|item| item.choice.clone())
+            .collect_vec();
+        bevy::log::info!("{head} {role}: {:?}", choices);
 
-            break choices;
+        {
+            let role = role.clone();
+            let request = Box::new(request);
+            let response = Box::new(response);
+            self.history.lock().await.push(LlmRecord::Choose {
+                role,
+                request,
+                response,
+            });
         }
+
+        Ok(choices)
     }
 
-    pub async fn notify<'a>(&'a mut self, player: &'a PlayerData, state: &'a PublicState) {
+    pub async fn notify<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        rules: &'a Rules,
+        state: &'a PublicState,
+    ) -> Result<(), ActorError> {
+        self.rules = rules.clone();
         self.chat.clear();
+        self.seen.clear();
+        self.rejected = false;
+        self.memory.commit();
 
         self.chat.extend([
             ChatRecord::new(
@@ -373,13 +512,12 @@ impl LlmActor {
                     star = player.inventory.star,
                     coin = player.inventory.coin,
                     num_cards = player.inventory.num_cards(),
-                    rock = player.inventory.rock,
-                    paper = player.inventory.paper,
-                    scissors = player.inventory.scissors,
+                    cards = rules.describe_cards(&player.inventory.cards),
+                    beats = rules.describe_beats(),
                     num_players = state.player,
-                    total_rock = state.rock,
-                    total_paper = state.paper,
-                    total_scissors = state.scissors,
+                    total_cards = rules.describe_cards(&state.cards),
+                    safe_stars = rules.safe_stars,
+                    min_stake = rules.min_stake,
                 ),
             ),
             ChatRecord::new(
@@ -412,11 +550,18 @@ impl LlmActor {
                     ),
                 ),
             },
-            ChatRecord::new(
-                Role::actor(player.entity, &player.name),
-                format!(include_str!("prompts/notify_6_user.md"), ASSISTANT_NAME),
-            ),
         ]);
+        if let Some(memory) = self.memory.describe() {
+            self.chat.push(ChatRecord::new(
+                Role::Assistant(player.entity),
+                format!(include_str!("prompts/memory_0_ai.md"), memory),
+            ));
+        }
+        self.chat.push(ChatRecord::new(
+            Role::actor(player.entity, &player.name),
+            format!(include_str!("prompts/notify_6_user.md"), ASSISTANT_NAME),
+        ));
+        self.pinned = self.chat.len();
 
         // AI advices
         self.chat.push({
@@ -439,13 +584,25 @@ impl LlmActor {
                     ..Default::default()
                 },
             )
-            .await
+            .await?
         });
 
         self.chat.push(ChatRecord::new(
             Role::actor(player.entity, &player.name),
             format!("I see, thank you, {ASSISTANT_NAME}."),
         ));
+
+        Ok(())
+    }
+
+    /// Add public records the player has not seen yet, even if older ones were summarized.
+    pub fn update_records(&mut self, history: &[ChatRecord]) {
+        self.seen.extend(self.chat.iter().map(|x| x.id));
+        for record in history {
+            if self.seen.insert(record.id) {
+                self.chat.push(record.clone());
+            }
+        }
     }
 
     pub async fn chat_trade<'a>(
@@ -454,13 +611,10 @@ impl LlmActor {
         opponent: &'a OpponentData,
         history: &'a [ChatRecord],
         round: usize,
-    ) -> Vec<ChatRecord> {
-        // update records
-        for record in history {
-            if !self.chat.iter().any(|x| x == record) {
-                self.chat.push(record.clone());
-            }
-        }
+    ) -> Result<Vec<ChatRecord>, ActorError> {
+        self.update_records(history);
+        self.memory.meet(&opponent.name);
+        self.summarize_chat(player).await?;
 
         let mut public_records = vec![];
 
@@ -479,10 +633,24 @@ impl LlmActor {
                     ),
                 ),
             ]);
+            if let Some(dossier) = opponent.dossier.describe(&opponent.name, &self.rules) {
+                self.chat
+                    .push(ChatRecord::new(Role::Assistant(player.entity), dossier));
+            }
+            if let Some(memory) = self.memory.describe_opponent(&opponent.name) {
+                self.chat.push(ChatRecord::new(
+                    Role::Assistant(player.entity),
+                    format!(
+                        include_str!("prompts/memory_1_ai.md"),
+                        opponent.name, memory
+                    ),
+                ));
+            }
         }
 
         // system notifies last round
-        if round == (NUM_CHAT_ROUNDS - 1) * 2 || round == (NUM_CHAT_ROUNDS - 1) * 2 + 1 {
+        let last_round = self.rules.num_chat_rounds - 1;
+        if round == last_round * 2 || round == last_round * 2 + 1 {
             self.chat.push(ChatRecord::new(
                 Role::Assistant(player.entity),
                 include_str!("prompts/trade_1.md"),
@@ -510,19 +678,90 @@ impl LlmActor {
                 Some(opponent),
                 sampler,
             )
-            .await
+            .await?
         };
         public_records.push(record.clone());
         self.chat.push(record);
 
-        if round == (NUM_CHAT_ROUNDS - 1) * 2 || round == (NUM_CHAT_ROUNDS - 1) * 2 + 1 {
+        if round == last_round * 2 || round == last_round * 2 + 1 {
             self.chat.push(ChatRecord::new(
                 Role::System(player.entity),
                 format!("*{} leaves chat*", opponent.name),
             ));
         }
 
-        public_records
+        Ok(public_records)
+    }
+
+    pub async fn chat_duel<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+        round: usize,
+    ) -> Result<Vec<ChatRecord>, ActorError> {
+        self.update_records(history);
+        self.summarize_chat(player).await?;
+
+        let mut public_records = vec![];
+
+        // player starts talking before the duel
+        if round == 0 || round == 1 {
+            self.chat.extend([
+                ChatRecord::new(
+                    Role::System(player.entity),
+                    format!("*{} joins chat*", opponent.name),
+                ),
+                ChatRecord::new(
+                    Role::Assistant(player.entity),
+                    format!(include_str!("prompts/duel_chat_0.md"), opponent.name),
+                ),
+            ]);
+        }
+
+        // system notifies last round
+        let last_round = self.rules.num_chat_rounds - 1;
+        if round == last_round * 2 || round == last_round * 2 + 1 {
+            self.chat.push(ChatRecord::new(
+                Role::Assistant(player.entity),
+                include_str!("prompts/duel_chat_1.md"),
+            ));
+        }
+
+        // player public words
+        let record = {
+            let role = Role::actor(player.entity, &player.name);
+            let prompt = Self::prompt_role(&self.chat, &role);
+            let sampler = Sampler {
+                kind: SamplerKind::Typical,
+                temperature: 1.5,
+                ..Default::default()
+            };
+            self.chat_llm(
+                format!("[duel][chat][{round}]"),
+                &role,
+                prompt,
+                "",
+                "",
+                &["\n\n", "\n"],
+                &[],
+                Some(player),
+                Some(opponent),
+                sampler,
+            )
+            .await?
+        };
+        public_records.push(record.clone());
+        self.chat.push(record);
+
+        if round == last_round * 2 || round == last_round * 2 + 1 {
+            self.chat.push(ChatRecord::new(
+                Role::System(player.entity),
+                format!("*{} leaves chat*", opponent.name),
+            ));
+        }
+
+        Ok(public_records)
     }
 
     pub async fn trade_item<'a>(
@@ -532,8 +771,14 @@ impl LlmActor {
         _history: &'a [ChatRecord],
         item: impl AsRef<str> + 'a,
         choices: impl Iterator<Item = usize> + 'a,
-    ) -> usize {
+    ) -> Result<usize, ActorError> {
         let item = item.as_ref();
+        let choices = choices.map(|x| format!(" {x}")).collect_vec();
+
+        // nothing to ask about if the player has none of the item
+        if choices.is_empty() {
+            return Ok(0);
+        }
 
         let mut chat = self.chat.clone();
         chat.push(ChatRecord::new(
@@ -558,16 +803,15 @@ impl LlmActor {
                     &prompt,
                     &choices,
                 )
-                .await;
+                .await?;
             match choices[0].as_ref() {
-                " I think I wouldn't like to" | " Hmm... I wouldn't like to" => return 0,
+                " I think I wouldn't like to" | " Hmm... I wouldn't like to" => return Ok(0),
                 " I would like to" | " Hmm... I think I would like to" => {}
-                _ => unreachable!(),
+                x => return Err(LlmError::Parse(format!("unknown choice {x:?}")).into()),
             }
         }
 
         let prompt = format!("{prompt} Hmm... I would like to offer {}", opponent.name);
-        let choices = choices.map(|x| format!(" {x}")).collect_vec();
         let choices = self
             .choose_llm(
                 format!("[trade][{item}][{}][1]", player.name),
@@ -575,13 +819,16 @@ impl LlmActor {
                 prompt,
                 &choices,
             )
-            .await;
-
-        choices
-            .into_iter()
-            .map(|x| x.trim().parse::<usize>().expect("cannot parse the result"))
-            .next()
-            .unwrap_or(0)
+            .await?;
+
+        let count = match choices.first() {
+            Some(x) => x
+                .trim()
+                .parse::<usize>()
+                .map_err(|err| LlmError::Parse(format!("{err}: {x:?}")))?,
+            None => 0,
+        };
+        Ok(count)
     }
 
     pub async fn trade<'a>(
@@ -589,7 +836,10 @@ impl LlmActor {
         player: &'a PlayerData,
         opponent: &'a OpponentData,
         history: &'a [ChatRecord],
-    ) -> Trade {
+    ) -> Result<Trade, ActorError> {
+        self.rejected = false;
+        self.memory.meet(&opponent.name);
+        self.summarize_chat(player).await?;
         self.chat.push(ChatRecord::new(
             Role::Assistant(player.entity),
             format!(include_str!("prompts/trade_3_0.md"), opponent.name),
@@ -615,43 +865,179 @@ impl LlmActor {
                 Some(opponent),
                 sampler,
             )
-            .await
+            .await?
         });
 
-        let Inventory {
-            star,
-            coin,
-            rock,
-            paper,
-            scissors,
-        } = player.inventory.clone();
-
-        let (star, coin, rock, paper, scissors) = join!(
-            self.trade_item(player, opponent, history, "stars", 0..star),
-            self.trade_item(player, opponent, history, "coins", 0..coin),
-            self.trade_item(player, opponent, history, "rock cards", 0..rock),
-            self.trade_item(player, opponent, history, "paper cards", 0..paper),
-            self.trade_item(player, opponent, history, "scissors cards", 0..scissors)
+        let trade = self
+            .offer(player, opponent, history, &player.inventory)
+            .await?;
+        bevy::log::info!("[trade][{}] {:?}", player.name, trade);
+        Ok(trade)
+    }
+
+    /// Ask what to give out of `inventory`, in the way of the trade mode.
+    pub async fn offer<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+        inventory: &'a Inventory,
+    ) -> Result<Trade, ActorError> {
+        if self.trade_mode == TradeMode::Json {
+            return self.trade_json(player, opponent, inventory).await;
+        }
+
+        let kinds = self.rules.cards.iter().map(|kind| &kind.name).collect_vec();
+        let items = kinds
+            .iter()
+            .map(|&card| {
+                let item = format!("{} cards", card.to_lowercase());
+                self.trade_item(player, opponent, history, item, 0..inventory.count(card))
+            })
+            .collect_vec();
+
+        let (star, coin, cards) = join!(
+            self.trade_item(player, opponent, history, "stars", 0..inventory.star),
+            self.trade_item(player, opponent, history, "coins", 0..inventory.coin),
+            join_all(items)
         );
+        let (star, coin) = (star?, coin?);
+        let cards: Vec<_> = cards.into_iter().try_collect()?;
+        let cards = kinds.into_iter().cloned().zip(cards).collect();
+        Ok(Trade { star, coin, cards })
+    }
 
-        let trade = Trade {
-            star,
-            coin,
-            rock,
-            paper,
-            scissors,
+    /// Keys of the JSON offer: stars, coins, and each kind of cards in the rules.
+    fn trade_keys(&self) -> Vec<String> {
+        ["star".into(), "coin".into()]
+            .into_iter()
+            .chain(self.rules.cards.iter().map(|kind| kind.name.to_lowercase()))
+            .collect()
+    }
+
+    /// The grammar of `bnf_trade.txt`, with the offer generated for the card kinds in the rules.
+    /// Each item is bounded by the inventory the same way as in [`TradeMode::Items`].
+    pub fn trade_grammar(&self, inventory: &Inventory) -> String {
+        const OFFER: &str = "__json_0_0_json";
+
+        let base = include_str!("prompts/bnf_trade.txt")
+            .lines()
+            .filter(|line| !line.starts_with(OFFER) && !line.starts_with("start ::="))
+            .join("\n");
+        let bounds = [inventory.star, inventory.coin].into_iter().chain(
+            self.rules
+                .cards
+                .iter()
+                .map(|kind| inventory.count(&kind.name)),
+        );
+        let fields = self
+            .trade_keys()
+            .iter()
+            .enumerate()
+            .map(|(index, key)| format!("'\"{key}\"' colon {OFFER}_{index}"))
+            .join(" comma ");
+        let values = bounds
+            .enumerate()
+            .map(|(index, bound)| {
+                let values = (0..bound.max(1)).map(|x| format!("\"{x}\"")).join(" | ");
+                format!("{OFFER}_{index} ::= {values};")
+            })
+            .join("\n");
+        format!(
+            "{base}\n{OFFER} ::= object_begin {fields} object_end;\n{values}\n\nstart ::= {OFFER} '\\n';"
+        )
+    }
+
+    /// Fill in the whole offer out of `inventory` in one completion.
+    pub async fn trade_json<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        inventory: &'a Inventory,
+    ) -> Result<Trade, ActorError> {
+        let keys = self.trade_keys();
+        self.chat.push(ChatRecord::new(
+            Role::Assistant(player.entity),
+            format!(
+                include_str!("prompts/trade_3_2.md"),
+                opponent.name,
+                keys.iter().map(|key| format!("\"{key}\"")).join(", ")
+            ),
+        ));
+
+        // servers ignoring the grammar may answer anything, so ask again like a failed request
+        let role = Role::actor(player.entity, &player.name);
+        let prompt = Self::prompt_role(&self.chat, &role);
+        let grammar = self.trade_grammar(inventory);
+        let mut attempts = 0;
+        let mut offer = loop {
+            attempts += 1;
+            let record = self
+                .chat_llm(
+                    format!("[trade][json][{}]", player.name),
+                    &role,
+                    &prompt,
+                    " ",
+                    &grammar,
+                    &["\n\n"],
+                    &[],
+                    Some(player),
+                    Some(opponent),
+                    Default::default(),
+                )
+                .await?;
+            match JsonOffer::parse(&record.content) {
+                Ok(offer) => break offer,
+                Err(err) if attempts >= self.retry.max_attempts => {
+                    let last = Box::new(err);
+                    return Err(LlmError::Exhausted { attempts, last }.into());
+                }
+                Err(err) => bevy::log::warn!("[trade][json][{}] {err}", player.name),
+            }
         };
-        bevy::log::info!("[trade][{}] {:?}", player.name, trade);
-        trade
+
+        let cards = self
+            .rules
+            .cards
+            .iter()
+            .map(|kind| {
+                let count = offer.cards.remove(&kind.name.to_lowercase());
+                (kind.name.clone(), count.unwrap_or_default().0)
+            })
+            .collect();
+        Ok(Trade {
+            star: offer.star.0,
+            coin: offer.coin.0,
+            cards,
+        })
     }
 
     pub async fn accept_trade<'a>(
         &'a mut self,
         player: &'a PlayerData,
         opponent: &'a OpponentData,
-        _history: &'a [ChatRecord],
+        history: &'a [ChatRecord],
         state: TradeState<'a>,
-    ) -> bool {
+    ) -> Result<bool, ActorError> {
+        let response = self
+            .respond_trade(player, opponent, history, state, 0)
+            .await?;
+        Ok(matches!(response, TradeResponse::Accept))
+    }
+
+    pub async fn respond_trade<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+        state: TradeState<'a>,
+        counters: usize,
+    ) -> Result<TradeResponse, ActorError> {
+        self.rejected = false;
+        self.memory.meet(&opponent.name);
+        self.memory.record_offer(state.that);
+        self.summarize_chat(player).await?;
+
         // display contract form
         self.chat.extend([
             ChatRecord::new(
@@ -666,14 +1052,10 @@ impl LlmActor {
                     opponent.name,
                     state.this.star,
                     state.this.coin,
-                    state.this.rock,
-                    state.this.paper,
-                    state.this.scissors,
+                    self.prompt_contract_cards(&state.this.cards),
                     state.that.star,
                     state.that.coin,
-                    state.that.rock,
-                    state.that.paper,
-                    state.that.scissors,
+                    self.prompt_contract_cards(&state.that.cards),
                 ),
             ),
             ChatRecord::new(
@@ -703,12 +1085,15 @@ impl LlmActor {
                 Some(opponent),
                 sampler,
             )
-            .await
+            .await?
         });
 
         self.chat.push(ChatRecord::new(
             Role::System(player.entity),
-            include_str!("prompts/trade_7.md"),
+            match counters {
+                0 => include_str!("prompts/trade_7.md"),
+                _ => include_str!("prompts/trade_7_1.md"),
+            },
         ));
 
         let record = {
@@ -730,26 +1115,56 @@ impl LlmActor {
                 " My decision stands as \"",
                 " I give my response with a \"",
             ];
+            let prefix = self.rng.choice(prefixes).unwrap();
+            let bnf_schema = match counters {
+                0 => "start ::= \"Yes\\\".\" | \"No\\\".\";",
+                _ => "start ::= \"Yes\\\".\" | \"No\\\".\" | \"Counter\\\".\";",
+            };
             self.chat_llm(
                 "[trade][confirm]",
                 &role,
                 prompt,
-                fastrand::choice(&prefixes).unwrap(),
-                "start ::= \"Yes\\\".\" | \"No\\\".\";",
+                prefix,
+                bnf_schema,
                 &["\n\n", "\n"],
                 &[],
                 Some(player),
                 Some(opponent),
                 sampler,
             )
-            .await
+            .await?
         };
-        let ans = record.content.contains("Yes");
+        let content = record.content.clone();
         self.chat.push(record);
-        ans
+        if content.contains("Yes") {
+            return Ok(TradeResponse::Accept);
+        }
+        if counters == 0 || !content.contains("Counter") {
+            return Ok(TradeResponse::Reject);
+        }
+
+        // player amends what to give; what the opponent gives stays
+        let mut inventory = player.inventory.clone();
+        inventory.apply_trade(state.this);
+        let this = self.offer(player, opponent, history, &inventory).await?;
+
+        self.chat.push(ChatRecord::new(
+            Role::actor(player.entity, &player.name),
+            format!(" I would rather give {}.", describe_trade(&this)),
+        ));
+        bevy::log::info!("[trade][counter][{}] {:?}", player.name, this);
+        let that = state.that.clone();
+        Ok(TradeResponse::Counter { this, that })
     }
 
-    pub async fn feedback_trade<'a>(&'a mut self, player: &'a PlayerData, state: [bool; 2]) {
+    pub async fn feedback_trade<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        state: [bool; 2],
+    ) -> Result<(), ActorError> {
+        self.memory.record_trade(state);
+        self.summarize_chat(player).await?;
+
         // system reports trade result
         let record = match state {
             [true, true] => ChatRecord::new(
@@ -759,9 +1174,7 @@ impl LlmActor {
                     star = player.inventory.star,
                     coin = player.inventory.coin,
                     num_cards = player.inventory.num_cards(),
-                    rock = player.inventory.rock,
-                    paper = player.inventory.paper,
-                    scissors = player.inventory.scissors,
+                    cards = self.rules.describe_cards(&player.inventory.cards),
                 ),
             ),
             _ => ChatRecord::new(
@@ -792,8 +1205,47 @@ impl LlmActor {
                 None,
                 sampler,
             )
-            .await
+            .await?
         });
+
+        Ok(())
+    }
+
+    pub async fn bet_item<'a>(
+        &'a self,
+        player: &'a PlayerData,
+        item: impl AsRef<str> + 'a,
+        choices: impl Iterator<Item = usize> + 'a,
+    ) -> Result<usize, ActorError> {
+        let item = item.as_ref();
+
+        let mut chat = self.chat.clone();
+        chat.push(ChatRecord::new(
+            Role::Assistant(player.entity),
+            format!(include_str!("prompts/bet_1.md"), item),
+        ));
+
+        let role = Role::actor(player.entity, &player.name);
+        let prompt = Self::prompt_role(&chat, &role);
+        let prompt = format!("{prompt} I would like to stake");
+        let choices = choices.map(|x| format!(" {x}")).collect_vec();
+        let choices = self
+            .choose_llm(
+                format!("[bet][{item}][{}]", player.name),
+                &role,
+                prompt,
+                &choices,
+            )
+            .await?;
+
+        let count = match choices.first() {
+            Some(x) => x
+                .trim()
+                .parse::<usize>()
+                .map_err(|err| LlmError::Parse(format!("{err}: {x:?}")))?,
+            None => 0,
+        };
+        Ok(count)
     }
 
     pub async fn bet<'a>(
@@ -801,21 +1253,70 @@ impl LlmActor {
         player: &'a PlayerData,
         opponent: &'a OpponentData,
         history: &'a [ChatRecord],
-    ) -> Stake {
-        // system reports opponent status
-        self.chat.extend([
-            ChatRecord::new(
-                Role::Assistant(player.entity),
-                format!(
-                    include_str!("prompts/duel_0_ai.md"),
-                    opponent.name, opponent.star, opponent.card
-                ),
+    ) -> Result<Stake, ActorError> {
+        // the opponent may have the last word before the duel
+        self.update_records(history);
+        self.summarize_chat(player).await?;
+
+        // the analysis is already done if the last stake is refused
+        if !std::mem::take(&mut self.rejected) {
+            self.analyze_duel(player, opponent).await?;
+        }
+
+        self.chat.push(ChatRecord::new(
+            Role::Assistant(player.entity),
+            format!(
+                include_str!("prompts/bet_0.md"),
+                min_stake = self.rules.min_stake,
+                star = player.inventory.star,
+                coin = player.inventory.coin,
             ),
-            ChatRecord::new(
-                Role::actor(player.entity, &player.name),
-                format!(include_str!("prompts/duel_1_user.md"), ASSISTANT_NAME),
+        ));
+
+        let inventory = &player.inventory;
+        let min_stake = self.rules.min_stake.min(inventory.star);
+        let (star, coin) = join!(
+            self.bet_item(player, "stars", min_stake..=inventory.star),
+            self.bet_item(player, "coins", 0..=inventory.coin)
+        );
+        let stake = Stake {
+            star: star?,
+            coin: coin?,
+        };
+
+        self.chat.push(ChatRecord::new(
+            Role::actor(player.entity, &player.name),
+            format!(
+                " I would like to stake {} stars and {} coins.",
+                stake.star, stake.coin
             ),
-        ]);
+        ));
+        bevy::log::info!("[bet][{}] {:?}", player.name, stake);
+        Ok(stake)
+    }
+
+    /// The assistant analyzes the opponent before the bet, and the player reflects on it.
+    async fn analyze_duel(
+        &mut self,
+        player: &PlayerData,
+        opponent: &OpponentData,
+    ) -> Result<(), ActorError> {
+        // system reports opponent status
+        self.chat.push(ChatRecord::new(
+            Role::Assistant(player.entity),
+            format!(
+                include_str!("prompts/duel_0_ai.md"),
+                opponent.name, opponent.star, opponent.card
+            ),
+        ));
+        if let Some(dossier) = opponent.dossier.describe(&opponent.name, &self.rules) {
+            self.chat
+                .push(ChatRecord::new(Role::Assistant(player.entity), dossier));
+        }
+        self.chat.push(ChatRecord::new(
+            Role::actor(player.entity, &player.name),
+            format!(include_str!("prompts/duel_1_user.md"), ASSISTANT_NAME),
+        ));
 
         // AI advices
         self.chat.push({
@@ -833,7 +1334,7 @@ impl LlmActor {
                 Some(opponent),
                 Default::default(),
             )
-            .await
+            .await?
         });
 
         // player reflects
@@ -857,29 +1358,37 @@ impl LlmActor {
                 None,
                 sampler,
             )
-            .await
+            .await?
         });
 
-        self.dummy.bet(player, opponent, history).await
+        Ok(())
     }
 
     pub async fn accept_duel<'a>(
         &'a mut self,
         player: &'a PlayerData,
         opponent: &'a OpponentData,
-        _history: &'a [ChatRecord],
-        _state: StakeState<'a>,
-    ) -> Option<Card> {
+        history: &'a [ChatRecord],
+        state: StakeState<'a>,
+    ) -> Result<Option<Card>, ActorError> {
+        self.update_records(history);
+        self.summarize_chat(player).await?;
         let mut history = vec![];
 
+        self.chat.push(ChatRecord::new(
+            Role::Assistant(player.entity),
+            format!(
+                include_str!("prompts/bet_2.md"),
+                state.this.star, state.this.coin, opponent.name, state.that.star, state.that.coin,
+            ),
+        ));
+
         let record = ChatRecord::new(
             Role::Assistant(player.entity),
             format!(
                 include_str!("prompts/duel_2.md"),
                 num_cards = player.inventory.num_cards(),
-                rock = player.inventory.rock,
-                paper = player.inventory.paper,
-                scissors = player.inventory.scissors
+                cards = self.rules.describe_cards(&player.inventory.cards),
             ),
         );
         // history.push(record.clone());
@@ -906,7 +1415,7 @@ impl LlmActor {
                 None,
                 sampler,
             )
-            .await
+            .await?
         };
         history.push(record.clone());
         self.chat.push(record);
@@ -916,13 +1425,13 @@ impl LlmActor {
             include_str!("prompts/duel_4.md"),
         ));
 
-        let deck = [
-            vec![Card::Rock; player.inventory.rock.min(1)],
-            vec![Card::Paper; player.inventory.paper.min(1)],
-            vec![Card::Scissors; player.inventory.scissors.min(1)],
-        ]
-        .concat();
-        let choices = deck.into_iter().map(|card| card.to_string()).collect_vec();
+        let choices = self
+            .rules
+            .cards
+            .iter()
+            .filter(|kind| player.inventory.count(&kind.name) > 0)
+            .map(|kind| kind.name.to_string())
+            .collect_vec();
 
         if !choices.is_empty() {
             let role = Role::actor(player.entity, &player.name);
@@ -931,7 +1440,7 @@ impl LlmActor {
                 " All right, the card I'm drawing is \"",
                 " Fine, the card I draw turns out to be \"",
             ];
-            let prefix = fastrand::choice(&prefixes).unwrap();
+            let prefix = self.rng.choice(prefixes).unwrap();
             let prompt = Self::prompt_role(&self.chat, &role);
             let choices = self
                 .choose_llm(
@@ -940,29 +1449,34 @@ impl LlmActor {
                     format!("{prompt}{prefix}"),
                     &choices,
                 )
-                .await;
-            let card = match choices[0].as_ref() {
-                "Rock" => Card::Rock,
-                "Paper" => Card::Paper,
-                "Scissors" => Card::Scissors,
-                _ => unreachable!(),
-            };
+                .await?;
+            let card = self
+                .rules
+                .parse_card(&choices[0])
+                .ok_or_else(|| LlmError::Parse(format!("unknown card {:?}", choices[0])))?;
             self.chat.push({
                 let content = format!("{prefix}{card}\".");
                 ChatRecord::new(role, content)
             });
-            Some(card)
+            Ok(Some(card))
         } else {
             self.chat.push({
                 let role = Role::actor(player.entity, &player.name);
                 let content = format!(" I don't want to duel with {}.", opponent.name);
                 ChatRecord::new(role, content)
             });
-            None
+            Ok(None)
         }
     }
 
-    pub async fn feedback_duel<'a>(&'a mut self, player: &'a PlayerData, result: DuelResult) {
+    pub async fn feedback_duel<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        result: DuelResult,
+    ) -> Result<(), ActorError> {
+        self.memory.record_duel(&result);
+        self.summarize_chat(player).await?;
+
         let prompt = match result {
             DuelResult::Tie(card) => format!("It's a tie, you both draw \"{card}\" card."),
             DuelResult::Win(this, that) => format!("\"{this}\" vs. \"{that}\". You win!"),
@@ -993,8 +1507,10 @@ impl LlmActor {
                 None,
                 sampler,
             )
-            .await
+            .await?
         });
+
+        Ok(())
     }
 }
 
@@ -1002,13 +1518,22 @@ impl Actor for LlmActor {
     fn notify<'a>(
         &'a mut self,
         data: &'a PlayerData,
+        rules: &'a Rules,
         state: &'a PublicState,
-    ) -> BoxedFuture<'a, ()> {
-        Box::pin(self.notify(data, state))
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(self.notify(data, rules, state))
     }
 
-    fn feedback_error<'a>(&'a mut self, data: &'a PlayerData, text: String) -> BoxedFuture<'a, ()> {
-        self.dummy.feedback_error(data, text)
+    fn feedback_error<'a>(
+        &'a mut self,
+        data: &'a PlayerData,
+        text: String,
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        // the next prompt shows what went wrong
+        self.chat
+            .push(ChatRecord::new(Role::System(data.entity), text));
+        self.rejected = true;
+        Box::pin(async { Ok(()) })
     }
 
     fn chat<'a>(
@@ -1017,10 +1542,10 @@ impl Actor for LlmActor {
         opponent: &'a OpponentData,
         history: &'a [ChatRecord],
         kind: ChatKind,
-    ) -> BoxedFuture<'a, Vec<ChatRecord>> {
+    ) -> BoxedFuture<'a, Result<Vec<ChatRecord>, ActorError>> {
         match kind {
             ChatKind::Trade(round) => Box::pin(self.chat_trade(player, opponent, history, round)),
-            ChatKind::Duel(_) => self.dummy.chat(player, opponent, history, kind),
+            ChatKind::Duel(round) => Box::pin(self.chat_duel(player, opponent, history, round)),
         }
     }
 
@@ -1029,7 +1554,7 @@ impl Actor for LlmActor {
         player: &'a PlayerData,
         opponent: &'a OpponentData,
         history: &'a [ChatRecord],
-    ) -> BoxedFuture<'a, Trade> {
+    ) -> BoxedFuture<'a, Result<Trade, ActorError>> {
         Box::pin(self.trade(player, opponent, history))
     }
 
@@ -1039,15 +1564,26 @@ impl Actor for LlmActor {
         opponent: &'a OpponentData,
         history: &'a [ChatRecord],
         state: TradeState<'a>,
-    ) -> BoxedFuture<'a, bool> {
+    ) -> BoxedFuture<'a, Result<bool, ActorError>> {
         Box::pin(self.accept_trade(player, opponent, history, state))
     }
 
+    fn respond_trade<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+        state: TradeState<'a>,
+        counters: usize,
+    ) -> BoxedFuture<'a, Result<TradeResponse, ActorError>> {
+        Box::pin(self.respond_trade(player, opponent, history, state, counters))
+    }
+
     fn feedback_trade<'a>(
         &'a mut self,
         player: &'a PlayerData,
         state: [bool; 2],
-    ) -> BoxedFuture<'a, ()> {
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
         Box::pin(self.feedback_trade(player, state))
     }
 
@@ -1056,7 +1592,7 @@ impl Actor for LlmActor {
         player: &'a PlayerData,
         opponent: &'a OpponentData,
         history: &'a [ChatRecord],
-    ) -> BoxedFuture<'a, Stake> {
+    ) -> BoxedFuture<'a, Result<Stake, ActorError>> {
         Box::pin(self.bet(player, opponent, history))
     }
 
@@ -1066,7 +1602,7 @@ impl Actor for LlmActor {
         opponent: &'a OpponentData,
         history: &'a [ChatRecord],
         state: StakeState<'a>,
-    ) -> BoxedFuture<'a, Option<Card>> {
+    ) -> BoxedFuture<'a, Result<Option<Card>, ActorError>> {
         Box::pin(self.accept_duel(player, opponent, history, state))
     }
 
@@ -1074,7 +1610,7 @@ impl Actor for LlmActor {
         &'a mut self,
         player: &'a PlayerData,
         result: DuelResult,
-    ) -> BoxedFuture<'a, ()> {
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
         Box::pin(self.feedback_duel(player, result))
     }
 
@@ -1083,6 +1619,7 @@ impl Actor for LlmActor {
         struct DumpData<'a> {
             name: &'a Name,
             inventory: &'a Inventory,
+            memory: &'a Memory,
             history: &'a [LlmRecord],
         }
 
@@ -1091,6 +1628,7 @@ impl Actor for LlmActor {
             let data = DumpData {
                 name: &player.name,
                 inventory: &player.inventory,
+                memory: &self.memory,
                 history: &history[..],
             };
             Ok(serde_json::to_vec(&data)?)
@@ -1,60 +1,288 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
-use bevy::prelude::*;
+use anyhow::{bail, Result};
+use bevy::{
+    app::ScheduleRunnerPlugin, input::common_conditions::input_toggle_active, log::LogPlugin,
+    prelude::*,
+};
 use bevy_async_ecs::AsyncEcsPlugin;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
-use crate::game::GamePlugin;
+use crate::{
+    analyze::AnalyzeArgs,
+    backend::{BackendKind, ChooseMode, RetryPolicy},
+    cassette::CassetteMode,
+    dashboard::DashboardPlugin,
+    game::{ActorKind, FailurePolicy, GamePlugin},
+    llm::TradeMode,
+    memory::MemoryPolicy,
+    replay::ReplayArgs,
+    rules::Rules,
+    tournament::TournamentArgs,
+    tui::{TuiPlugin, TuiTerminal},
+};
 
+pub mod analyze;
+pub mod backend;
+pub mod cassette;
+pub mod dashboard;
+pub mod dossier;
+pub mod event;
 pub mod game;
+pub mod human;
 pub mod llm;
+pub mod memory;
+pub mod remote;
+pub mod replay;
+pub mod rules;
+pub mod script;
+pub mod tournament;
+pub mod tui;
+
+#[cfg(test)]
+mod tests;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(long, default_value = "http://localhost:65530")]
     url: String,
+    /// Kind of the LLM server at `--url`.
+    #[arg(long, value_enum, default_value = "ai00")]
+    backend: BackendKind,
+    /// Model name sent to OpenAI-compatible servers.
+    #[arg(long, default_value = "default")]
+    model: String,
+    /// API key for OpenAI-compatible servers.
+    #[arg(long, env = "OPENAI_API_KEY")]
+    api_key: Option<String>,
+    /// How OpenAI-compatible servers choose among options.
+    #[arg(long, value_enum, default_value = "logprobs")]
+    choose_mode: ChooseMode,
+    /// How LLM players fill in their trade offers.
+    #[arg(long, value_enum, default_value = "items")]
+    trade_mode: TradeMode,
+    /// What LLM players remember of previous rounds.
+    #[arg(long, value_enum, default_value = "summarized")]
+    memory: MemoryPolicy,
+    /// Estimated tokens of the story in each LLM prompt before older parts are summarized, 0 for no limit.
+    #[arg(long, default_value = "0")]
+    context_budget: usize,
+    /// Maximum attempts for each request to the LLM.
+    #[arg(long, default_value = "5")]
+    max_attempts: usize,
+    /// Seconds to wait before retrying a failed LLM request, doubled after each failure.
+    #[arg(long, default_value = "1")]
+    backoff: f64,
+    /// Seconds to wait for each response from the LLM.
+    #[arg(long, default_value = "120")]
+    llm_timeout: f64,
+    /// Maximum requests to the LLM in flight at the same time, 0 for no limit.
+    #[arg(long, default_value = "8")]
+    max_in_flight: usize,
+    /// Maximum requests to the LLM per second, 0 for no limit.
+    #[arg(long, default_value = "0")]
+    rate_limit: f64,
+    /// Cassette file to record the LLM traffic into, or to replay it from.
+    #[arg(long)]
+    cassette: Option<PathBuf>,
+    /// Whether to record or replay the cassette.
+    #[arg(long, value_enum, default_value = "record")]
+    cassette_mode: CassetteMode,
+    /// What to do with a table whose duel fails, e.g., because the LLM is down.
+    #[arg(long, value_enum, default_value = "forfeit")]
+    on_failure: FailurePolicy,
     #[arg(long, short, default_value = "./output")]
     output: PathBuf,
     #[arg(long, default_value = "64")]
     num_players: usize,
     #[arg(long, default_value = "16")]
     max_rounds: usize,
+    /// Run without window, renderer and inspector.
+    #[arg(long)]
+    headless: bool,
+    /// Show a terminal dashboard instead of the logs; implies `--headless`.
+    #[arg(long)]
+    tui: bool,
+    /// Seed for all random choices in the game.
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Path to the rules file (TOML or JSON).
+    #[arg(long)]
+    rules: Option<PathBuf>,
+    /// Actors assigned to players in turn, e.g., `llm,random,nash`.
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "llm")]
+    actors: Vec<ActorKind>,
+    /// Address of remote actors, `<host>:<port>` for TCP or `unix:<path>` for Unix socket.
+    #[arg(long, default_value = "127.0.0.1:65531")]
+    remote: String,
+    /// Seconds to wait for a remote actor before falling back to random play.
+    #[arg(long, default_value = "30")]
+    remote_timeout: f64,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Replay a finished game from its output directory.
+    Replay(ReplayArgs),
+    /// Play many games without window, and rate actors by their results.
+    Tournament(TournamentArgs),
+    /// Compute statistics over the outputs of games, written as CSV and JSON.
+    Analyze(AnalyzeArgs),
 }
 
-#[derive(Debug, Clone, Resource, Reflect)]
+#[derive(Debug, Default, Clone, Resource, Reflect)]
 #[reflect(Resource)]
 pub struct Settings {
     /// Base URL for the LLM API.
     pub url: String,
+    /// Kind of the LLM server.
+    pub backend: BackendKind,
+    /// Model name for OpenAI-compatible servers.
+    pub model: String,
+    /// API key for OpenAI-compatible servers.
+    #[reflect(ignore)]
+    pub api_key: Option<String>,
+    /// How OpenAI-compatible servers choose among options.
+    pub choose_mode: ChooseMode,
+    /// How LLM players fill in their trade offers.
+    pub trade_mode: TradeMode,
+    /// What LLM players remember of previous rounds.
+    pub memory: MemoryPolicy,
+    /// Estimated tokens of the story in each LLM prompt before older parts are summarized.
+    pub context_budget: usize,
+    /// How to retry failed requests to the LLM.
+    pub retry: RetryPolicy,
+    /// Maximum requests to the LLM in flight at the same time, 0 for no limit.
+    pub max_in_flight: usize,
+    /// Maximum requests to the LLM per second, 0 for no limit.
+    pub rate_limit: f64,
+    /// Cassette file to record the LLM traffic into, or to replay it from.
+    pub cassette: Option<PathBuf>,
+    /// Whether to record or replay the cassette.
+    pub cassette_mode: CassetteMode,
+    /// What to do with a table whose duel fails.
+    pub on_failure: FailurePolicy,
     /// Output directory.
     pub output: PathBuf,
     /// Number of players in the game.
     pub num_players: usize,
     /// Maximum rounds a player can play.
     pub max_rounds: usize,
+    /// Seed for the game RNG. If set, players are matched in lockstep rounds.
+    pub seed: Option<u64>,
+    /// Actors assigned to players in turn.
+    pub actors: Vec<ActorKind>,
+    /// Address of remote actors.
+    pub remote: String,
+    /// Timeout for each request to remote actors.
+    pub remote_timeout: Duration,
 }
 
-fn main() {
+fn main() -> Result<()> {
     let Args {
+        command,
         url,
+        backend,
+        model,
+        api_key,
+        choose_mode,
+        trade_mode,
+        memory,
+        context_budget,
+        max_attempts,
+        backoff,
+        llm_timeout,
+        max_in_flight,
+        rate_limit,
+        cassette,
+        cassette_mode,
+        on_failure,
         output,
         num_players,
         max_rounds,
+        headless,
+        tui,
+        seed,
+        rules,
+        actors,
+        remote,
+        remote_timeout,
     } = Args::parse();
 
     let settings = Settings {
         url,
+        backend,
+        model,
+        api_key,
+        choose_mode,
+        trade_mode,
+        memory,
+        context_budget,
+        retry: RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            backoff: Duration::from_secs_f64(backoff),
+            timeout: Duration::from_secs_f64(llm_timeout),
+            ..Default::default()
+        },
+        max_in_flight,
+        rate_limit,
+        cassette,
+        cassette_mode,
+        on_failure,
         output,
         num_players,
         max_rounds,
+        seed,
+        actors,
+        remote,
+        remote_timeout: Duration::from_secs_f64(remote_timeout),
+    };
+    let rules = match rules {
+        Some(path) => Rules::load(path)?,
+        None => Rules::default(),
     };
 
-    App::new()
-        .add_plugins((DefaultPlugins, AsyncEcsPlugin, WorldInspectorPlugin::new()))
+    match command {
+        Some(Command::Replay(args)) => return replay::run(args),
+        Some(Command::Analyze(args)) => return analyze::run(args),
+        Some(Command::Tournament(args)) => return tournament::run(args, settings, rules),
+        None => {}
+    }
+
+    if tui && settings.actors.contains(&ActorKind::Human) {
+        bail!("human players need the terminal, which `--tui` takes over");
+    }
+
+    let mut app = App::new();
+    let minimal = MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+        1.0 / 60.0,
+    )));
+    match (tui, headless) {
+        // logs would scribble over the dashboard
+        (true, _) => app
+            .add_plugins((minimal, TuiPlugin))
+            .insert_resource(TuiTerminal::new()?),
+        (false, true) => app.add_plugins((minimal, LogPlugin::default())),
+        (false, false) => app.add_plugins((
+            DefaultPlugins,
+            WorldInspectorPlugin::new().run_if(input_toggle_active(false, KeyCode::F1)),
+            DashboardPlugin,
+        )),
+    };
+    let exit = app
+        .add_plugins(AsyncEcsPlugin)
         .add_plugins(GamePlugin)
         .register_type::<Settings>()
         .insert_resource(settings)
+        .insert_resource(rules)
         .run();
+
+    match exit {
+        AppExit::Success => Ok(()),
+        AppExit::Error(code) => bail!("game aborted with exit code {code}"),
+    }
 }
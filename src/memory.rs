@@ -0,0 +1,229 @@
+use std::{collections::VecDeque, fmt::Display};
+
+use bevy::reflect::Reflect;
+use clap::ValueEnum;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::{DuelResult, Trade},
+    replay::describe_trade,
+};
+
+/// Most past tables an LLM player remembers.
+pub const MEMORY_CAPACITY: usize = 16;
+
+/// What LLM players remember of previous rounds.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Reflect, Serialize, Deserialize,
+)]
+pub enum MemoryPolicy {
+    /// Forget everything after each table.
+    None,
+    /// Remember each past table on its own.
+    Full,
+    /// Remember each past opponent, with their tables summed up.
+    #[default]
+    Summarized,
+}
+
+/// A past table, as seen by the player.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Encounter {
+    /// The number of the table among those the player has sat at, from 1.
+    pub table: usize,
+    pub opponent: String,
+    /// What the opponent offered in the last contract.
+    pub offer: Option<Trade>,
+    /// Whether the player and the opponent signed the contract.
+    pub signed: Option<[bool; 2]>,
+    /// The result of the duel for the player.
+    pub result: Option<DuelResult>,
+}
+
+impl Encounter {
+    pub fn new(table: usize, opponent: impl ToString) -> Self {
+        Self {
+            table,
+            opponent: opponent.to_string(),
+            offer: None,
+            signed: None,
+            result: None,
+        }
+    }
+}
+
+impl Display for Encounter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = &self.opponent;
+        let offer = match &self.offer {
+            Some(trade) => format!("{name} offered {}", describe_trade(trade)),
+            None => format!("{name} made no offer"),
+        };
+        let contract = match self.signed {
+            Some([_, false]) => "refused the contract",
+            Some([true, true]) => "signed the contract, and the trade went through",
+            Some([false, true]) => "signed the contract, but you refused it",
+            None => "never got to the contract",
+        };
+        let duel = match &self.result {
+            Some(DuelResult::Win(this, that)) => {
+                format!("{name} drew \"{that}\" against your \"{this}\", and you won")
+            }
+            Some(DuelResult::Lose(this, that)) => {
+                format!("{name} drew \"{that}\" against your \"{this}\", and you lost")
+            }
+            Some(DuelResult::Tie(card)) => format!("you both drew \"{card}\""),
+            None => "the duel did not take place".into(),
+        };
+        write!(f, "{offer} and {contract}; {duel}.")
+    }
+}
+
+/// A compact record of previous tables, carried by an LLM player from round to round.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Memory {
+    pub policy: MemoryPolicy,
+    /// Past tables, oldest first.
+    pub encounters: VecDeque<Encounter>,
+    /// The table being played, remembered once the next one starts.
+    pub current: Option<Encounter>,
+    /// Number of tables the player has sat at.
+    pub tables: usize,
+}
+
+impl Memory {
+    pub fn new(policy: MemoryPolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Start remembering the table with this opponent, unless already.
+    pub fn meet(&mut self, opponent: impl ToString) {
+        if self.policy == MemoryPolicy::None || self.current.is_some() {
+            return;
+        }
+        self.tables += 1;
+        self.current = Some(Encounter::new(self.tables, opponent));
+    }
+
+    /// Keep the table being played, forgetting the oldest ones beyond [`MEMORY_CAPACITY`].
+    pub fn commit(&mut self) {
+        if let Some(encounter) = self.current.take() {
+            self.encounters.push_back(encounter);
+        }
+        while self.encounters.len() > MEMORY_CAPACITY {
+            self.encounters.pop_front();
+        }
+    }
+
+    pub fn record_offer(&mut self, trade: &Trade) {
+        if let Some(encounter) = &mut self.current {
+            encounter.offer = Some(trade.clone());
+        }
+    }
+
+    pub fn record_trade(&mut self, signed: [bool; 2]) {
+        if let Some(encounter) = &mut self.current {
+            encounter.signed = Some(signed);
+        }
+    }
+
+    pub fn record_duel(&mut self, result: &DuelResult) {
+        if let Some(encounter) = &mut self.current {
+            encounter.result = Some(result.clone());
+        }
+    }
+
+    /// What the player remembers of all previous tables, if anything.
+    pub fn describe(&self) -> Option<String> {
+        self.describe_encounters(self.encounters.iter())
+    }
+
+    /// What the player remembers of previous tables with this opponent, if anything.
+    pub fn describe_opponent(&self, opponent: &str) -> Option<String> {
+        let encounters = self.encounters.iter().filter(|x| x.opponent == opponent);
+        self.describe_encounters(encounters)
+    }
+
+    fn describe_encounters<'a>(
+        &self,
+        encounters: impl Iterator<Item = &'a Encounter> + Clone,
+    ) -> Option<String> {
+        let lines = match self.policy {
+            MemoryPolicy::None => vec![],
+            MemoryPolicy::Full => encounters
+                .map(|x| format!("- Table {}: {x}", x.table))
+                .collect(),
+            MemoryPolicy::Summarized => encounters
+                .clone()
+                .map(|x| &x.opponent)
+                .unique()
+                .map(|name| {
+                    let encounters = encounters.clone().filter(|x| &x.opponent == name);
+                    summarize(name, encounters)
+                })
+                .collect(),
+        };
+        match lines.is_empty() {
+            true => None,
+            false => Some(lines.join("\n")),
+        }
+    }
+}
+
+/// One line for all tables with an opponent.
+fn summarize<'a>(name: &str, encounters: impl Iterator<Item = &'a Encounter>) -> String {
+    let encounters = encounters.collect_vec();
+    let times = match encounters.len() {
+        1 => "once".into(),
+        2 => "twice".into(),
+        n => format!("{n} times"),
+    };
+
+    let mut offer = Trade::default();
+    for trade in encounters.iter().filter_map(|x| x.offer.as_ref()) {
+        offer.star += trade.star;
+        offer.coin += trade.coin;
+        for (card, count) in &trade.cards {
+            *offer.cards.entry(card.clone()).or_default() += count;
+        }
+    }
+    let contracts = encounters.iter().filter_map(|x| x.signed).collect_vec();
+    let signed = contracts.iter().filter(|x| x[1]).count();
+
+    let [mut win, mut lose, mut tie] = [0; 3];
+    let cards = encounters
+        .iter()
+        .filter_map(|x| x.result.as_ref())
+        .map(|result| {
+            let card = match result {
+                DuelResult::Win(_, that) => {
+                    win += 1;
+                    that
+                }
+                DuelResult::Lose(_, that) => {
+                    lose += 1;
+                    that
+                }
+                DuelResult::Tie(card) => {
+                    tie += 1;
+                    card
+                }
+            };
+            format!("\"{card}\"")
+        })
+        .join(", ");
+    let duels = match cards.is_empty() {
+        true => "never dueled with you".into(),
+        false => format!("drew {cards}; you won {win}, lost {lose} and tied {tie}"),
+    };
+
+    format!(
+        "- {name}, met {times}: offered {} in total, signed {signed} of {} contracts, and {duels}.",
+        describe_trade(&offer),
+        contracts.len()
+    )
+}
@@ -0,0 +1,474 @@
+use std::time::Duration;
+
+use async_std::{
+    future::timeout,
+    io::{prelude::BufReadExt, BufReader, Read, Write, WriteExt},
+    net::TcpStream,
+};
+use bevy::utils::BoxedFuture;
+use derivative::Derivative;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    game::{
+        Actor, ActorError, Card, ChatKind, ChatRecord, DuelResult, DummyActor, OpponentData,
+        PlayerData, PublicState, Role, Stake, StakeState, Trade, TradeState,
+    },
+    rules::Rules,
+};
+
+/// A request sent to the remote peer, one JSON object per line.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Request<'a> {
+    Notify {
+        player: &'a PlayerData,
+        rules: &'a Rules,
+        state: &'a PublicState,
+    },
+    FeedbackError {
+        player: &'a PlayerData,
+        text: &'a str,
+    },
+    Chat {
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+        kind: ChatKind,
+    },
+    Trade {
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+    },
+    AcceptTrade {
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+        this: &'a Trade,
+        that: &'a Trade,
+    },
+    FeedbackTrade {
+        player: &'a PlayerData,
+        state: [bool; 2],
+    },
+    Bet {
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+    },
+    AcceptDuel {
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+        this: &'a Stake,
+        that: &'a Stake,
+    },
+    FeedbackDuel {
+        player: &'a PlayerData,
+        result: &'a DuelResult,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RequestMessage<'a> {
+    id: usize,
+    #[serde(flatten)]
+    request: Request<'a>,
+}
+
+/// A response from the remote peer. Responses with an unexpected `id` are skipped.
+#[derive(Debug, Clone, Deserialize)]
+struct ResponseMessage {
+    id: usize,
+    #[serde(default)]
+    result: serde_json::Value,
+}
+
+#[derive(Debug, Error)]
+pub enum RemoteError {
+    #[error("not connected")]
+    Disconnected,
+    #[error("timed out")]
+    Timeout,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+type Reader = BufReader<Box<dyn Read + Unpin + Send + Sync>>;
+type Writer = Box<dyn Write + Unpin + Send + Sync>;
+
+/// A connection to the remote peer over TCP or Unix socket.
+pub struct Connection {
+    reader: Reader,
+    writer: Writer,
+}
+
+impl Connection {
+    /// Connect to `unix:<path>` for a Unix socket, or to `<host>:<port>` for TCP.
+    pub async fn connect(addr: &str) -> std::io::Result<Self> {
+        match addr.strip_prefix("unix:") {
+            #[cfg(unix)]
+            Some(path) => {
+                let stream = async_std::os::unix::net::UnixStream::connect(path).await?;
+                Ok(Self::new(Box::new(stream.clone()), Box::new(stream)))
+            }
+            #[cfg(not(unix))]
+            Some(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Unix sockets are not supported on this platform",
+            )),
+            None => {
+                let stream = TcpStream::connect(addr).await?;
+                Ok(Self::new(Box::new(stream.clone()), Box::new(stream)))
+            }
+        }
+    }
+
+    fn new(reader: Box<dyn Read + Unpin + Send + Sync>, writer: Writer) -> Self {
+        let reader = BufReader::new(reader);
+        Self { reader, writer }
+    }
+
+    /// Send a request line and wait for the response line with the same `id`.
+    async fn call(
+        &mut self,
+        id: usize,
+        request: Request<'_>,
+    ) -> Result<serde_json::Value, RemoteError> {
+        let mut line = serde_json::to_string(&RequestMessage { id, request })?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes()).await?;
+        self.writer.flush().await?;
+
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line).await? == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response: ResponseMessage = serde_json::from_str(&line)?;
+            if response.id == id {
+                return Ok(response.result);
+            }
+        }
+    }
+}
+
+/// A player controlled by an external process speaking JSON lines over a socket.
+///
+/// Each [`Actor`] method is sent as a request and blocks until the peer responds or times out.
+/// The actor falls back to [`DummyActor`] if the peer misbehaves, and reconnects at the next round
+/// if it is disconnected.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct RemoteActor {
+    pub addr: String,
+    pub timeout: Duration,
+    pub rules: Rules,
+    pub dummy: DummyActor,
+    #[derivative(Debug = "ignore")]
+    connection: Option<Connection>,
+    id: usize,
+}
+
+impl RemoteActor {
+    pub fn new(addr: String, timeout: Duration, rng: fastrand::Rng) -> Self {
+        Self {
+            addr,
+            timeout,
+            rules: Default::default(),
+            dummy: DummyActor::new(rng),
+            connection: None,
+            id: 0,
+        }
+    }
+
+    async fn connect(&mut self) {
+        if self.connection.is_some() {
+            return;
+        }
+        match timeout(self.timeout, Connection::connect(&self.addr)).await {
+            Ok(Ok(connection)) => {
+                bevy::log::info!("connected to remote actor at {}", self.addr);
+                self.connection = Some(connection);
+            }
+            Ok(Err(err)) => bevy::log::warn!("failed to connect to {}: {err}", self.addr),
+            Err(_) => bevy::log::warn!("failed to connect to {}: timed out", self.addr),
+        }
+    }
+
+    async fn try_call<T: DeserializeOwned>(
+        &mut self,
+        request: Request<'_>,
+    ) -> Result<T, RemoteError> {
+        let connection = self.connection.as_mut().ok_or(RemoteError::Disconnected)?;
+        self.id += 1;
+        let result = match timeout(self.timeout, connection.call(self.id, request)).await {
+            Ok(result) => result,
+            Err(_) => Err(RemoteError::Timeout),
+        };
+        match result {
+            Ok(value) => Ok(serde_json::from_value(value)?),
+            Err(err) => {
+                // the stream may be left in the middle of a line, so start over next round
+                self.connection = None;
+                Err(err)
+            }
+        }
+    }
+
+    /// Call the peer, returning `None` if it fails to respond in time or properly.
+    async fn call<T: DeserializeOwned>(
+        &mut self,
+        player: &PlayerData,
+        request: Request<'_>,
+    ) -> Option<T> {
+        match self.try_call(request).await {
+            Ok(value) => Some(value),
+            Err(RemoteError::Disconnected) => None,
+            Err(err) => {
+                bevy::log::warn!("[{}] remote actor: {err}", player.name);
+                None
+            }
+        }
+    }
+
+    pub async fn notify(&mut self, player: &PlayerData, rules: &Rules, state: &PublicState) {
+        self.rules = rules.clone();
+        self.connect().await;
+
+        let request = Request::Notify {
+            player,
+            rules,
+            state,
+        };
+        let _: Option<serde::de::IgnoredAny> = self.call(player, request).await;
+    }
+
+    pub async fn feedback_error(&mut self, player: &PlayerData, text: String) {
+        let request = Request::FeedbackError {
+            player,
+            text: &text,
+        };
+        let _: Option<serde::de::IgnoredAny> = self.call(player, request).await;
+    }
+
+    /// The peer replies with the messages to say.
+    pub async fn chat(
+        &mut self,
+        player: &PlayerData,
+        opponent: &OpponentData,
+        history: &[ChatRecord],
+        kind: ChatKind,
+    ) -> Vec<ChatRecord> {
+        let request = Request::Chat {
+            player,
+            opponent,
+            history,
+            kind,
+        };
+        let messages: Vec<String> = self.call(player, request).await.unwrap_or_default();
+        messages
+            .into_iter()
+            .filter(|message| !message.trim().is_empty())
+            .map(|message| ChatRecord::new(Role::actor(player.entity, &player.name), message))
+            .collect()
+    }
+
+    pub async fn trade(
+        &mut self,
+        player: &PlayerData,
+        opponent: &OpponentData,
+        history: &[ChatRecord],
+    ) -> Result<Trade, ActorError> {
+        let request = Request::Trade {
+            player,
+            opponent,
+            history,
+        };
+        match self.call(player, request).await {
+            Some(trade) => Ok(trade),
+            None => self.dummy.trade(player, opponent, history).await,
+        }
+    }
+
+    pub async fn accept_trade(
+        &mut self,
+        player: &PlayerData,
+        opponent: &OpponentData,
+        history: &[ChatRecord],
+        state: TradeState<'_>,
+    ) -> Result<bool, ActorError> {
+        let request = Request::AcceptTrade {
+            player,
+            opponent,
+            history,
+            this: state.this,
+            that: state.that,
+        };
+        match self.call(player, request).await {
+            Some(accept) => Ok(accept),
+            None => {
+                self.dummy
+                    .accept_trade(player, opponent, history, state)
+                    .await
+            }
+        }
+    }
+
+    pub async fn feedback_trade(&mut self, player: &PlayerData, state: [bool; 2]) {
+        let request = Request::FeedbackTrade { player, state };
+        let _: Option<serde::de::IgnoredAny> = self.call(player, request).await;
+    }
+
+    pub async fn bet(
+        &mut self,
+        player: &PlayerData,
+        opponent: &OpponentData,
+        history: &[ChatRecord],
+    ) -> Result<Stake, ActorError> {
+        let request = Request::Bet {
+            player,
+            opponent,
+            history,
+        };
+        match self.call(player, request).await {
+            Some(stake) => Ok(stake),
+            None => self.dummy.bet(player, opponent, history).await,
+        }
+    }
+
+    /// The peer replies with the name of the card to draw, or `null` to refuse the duel.
+    pub async fn accept_duel(
+        &mut self,
+        player: &PlayerData,
+        opponent: &OpponentData,
+        history: &[ChatRecord],
+        state: StakeState<'_>,
+    ) -> Result<Option<Card>, ActorError> {
+        let request = Request::AcceptDuel {
+            player,
+            opponent,
+            history,
+            this: state.this,
+            that: state.that,
+        };
+        match self.call::<Option<Card>>(player, request).await {
+            Some(card) => Ok(card.map(|card| self.rules.parse_card(&*card).unwrap_or(card))),
+            None => {
+                self.dummy
+                    .accept_duel(player, opponent, history, state)
+                    .await
+            }
+        }
+    }
+
+    pub async fn feedback_duel(&mut self, player: &PlayerData, result: DuelResult) {
+        let request = Request::FeedbackDuel {
+            player,
+            result: &result,
+        };
+        let _: Option<serde::de::IgnoredAny> = self.call(player, request).await;
+    }
+}
+
+impl Actor for RemoteActor {
+    fn notify<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        rules: &'a Rules,
+        state: &'a PublicState,
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move {
+            self.notify(player, rules, state).await;
+            Ok(())
+        })
+    }
+
+    fn feedback_error<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        text: String,
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move {
+            self.feedback_error(player, text).await;
+            Ok(())
+        })
+    }
+
+    fn chat<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+        kind: ChatKind,
+    ) -> BoxedFuture<'a, Result<Vec<ChatRecord>, ActorError>> {
+        Box::pin(async move { Ok(self.chat(player, opponent, history, kind).await) })
+    }
+
+    fn trade<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+    ) -> BoxedFuture<'a, Result<Trade, ActorError>> {
+        Box::pin(self.trade(player, opponent, history))
+    }
+
+    fn accept_trade<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+        state: TradeState<'a>,
+    ) -> BoxedFuture<'a, Result<bool, ActorError>> {
+        Box::pin(self.accept_trade(player, opponent, history, state))
+    }
+
+    fn feedback_trade<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        state: [bool; 2],
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move {
+            self.feedback_trade(player, state).await;
+            Ok(())
+        })
+    }
+
+    fn bet<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+    ) -> BoxedFuture<'a, Result<Stake, ActorError>> {
+        Box::pin(self.bet(player, opponent, history))
+    }
+
+    fn accept_duel<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+        state: StakeState<'a>,
+    ) -> BoxedFuture<'a, Result<Option<Card>, ActorError>> {
+        Box::pin(self.accept_duel(player, opponent, history, state))
+    }
+
+    fn feedback_duel<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        result: DuelResult,
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move {
+            self.feedback_duel(player, result).await;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,388 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use bevy::{
+    app::ScheduleRunnerPlugin, input::common_conditions::input_toggle_active, log::LogPlugin,
+    prelude::*,
+};
+use bevy_inspector_egui::quick::WorldInspectorPlugin;
+use itertools::Itertools;
+
+use crate::{
+    dashboard::DashboardPlugin,
+    game::{
+        DuelResult, GameLog, Inventory, PlayerRecord, Stake, Table, TableRecord, Trade,
+        GAME_LOG_FILE,
+    },
+};
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct ReplayArgs {
+    /// Output directory of the game, e.g., `output/output-2024-01-01-12-00`.
+    pub dir: PathBuf,
+    /// Step through the game in the Bevy app instead of printing it.
+    #[arg(long)]
+    pub app: bool,
+    /// Run the app without window, renderer and inspector, logging each round.
+    #[arg(long)]
+    pub headless: bool,
+    /// Seconds between rounds in the app, 0 to step with the space key (or every frame if headless).
+    #[arg(long, default_value = "2")]
+    pub interval: f64,
+}
+
+/// A round of the game, with the inventories of all players after the round.
+#[derive(Debug, Clone)]
+pub struct ReplayRound {
+    pub index: usize,
+    pub tables: Vec<TableRecord>,
+    pub inventories: Vec<(String, Inventory)>,
+}
+
+/// A dumped game, rebuilt round by round.
+#[derive(Debug, Clone, Resource)]
+pub struct Replay {
+    /// Players with their starting inventories.
+    pub players: Vec<PlayerRecord>,
+    pub rounds: Vec<ReplayRound>,
+    /// Players with their inventories at the end of the game, after the final trade.
+    pub result: Vec<PlayerRecord>,
+}
+
+impl Replay {
+    /// Load the game log from the output directory of a game, finished or not.
+    ///
+    /// Only the game log is read: the dumps of each player do not record the tables, so outputs
+    /// from before the game log cannot be replayed.
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self> {
+        let path = dir.as_ref().join(GAME_LOG_FILE);
+        let data = std::fs::read(&path).with_context(|| {
+            format!(
+                "cannot read {}, is it the output directory of a game? \
+                 Replays need the game log; the dumps of each player are not enough",
+                path.display()
+            )
+        })?;
+        let log: GameLog = serde_json::from_slice(&data)
+            .with_context(|| format!("invalid game log {}", path.display()))?;
+        Ok(Self::new(log))
+    }
+
+    pub fn new(log: GameLog) -> Self {
+        let GameLog {
+            players,
+            tables,
+            result,
+        } = log;
+
+        // a player sits at one table at a time, so grouping by round keeps each player's tables in order
+        let mut inventories = players
+            .iter()
+            .map(|x| (x.name.clone(), x.inventory.clone()))
+            .collect_vec();
+        let rounds = tables
+            .into_iter()
+            .into_group_map_by(|table| table.round)
+            .into_iter()
+            .sorted_by_key(|(index, _)| *index)
+            .map(|(index, tables)| {
+                for table in &tables {
+                    for (name, inventory) in table.players.iter().zip(&table.after) {
+                        if let Some(x) = inventories.iter_mut().find(|x| &x.0 == name) {
+                            x.1 = inventory.clone();
+                        }
+                    }
+                }
+                let inventories = inventories.clone();
+                ReplayRound {
+                    index,
+                    tables,
+                    inventories,
+                }
+            })
+            .collect();
+
+        Self {
+            players,
+            rounds,
+            result,
+        }
+    }
+}
+
+pub fn describe_inventory(inventory: &Inventory) -> String {
+    let cards = inventory
+        .cards
+        .iter()
+        .map(|(card, count)| format!("{count} {card}"))
+        .join(", ");
+    format!(
+        "{} stars, {} coins, [{cards}]",
+        inventory.star, inventory.coin
+    )
+}
+
+pub fn describe_trade(trade: &Trade) -> String {
+    let items = [(trade.star, "stars"), (trade.coin, "coins")]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, item)| format!("{count} {item}"))
+        .chain(
+            trade
+                .cards
+                .iter()
+                .filter(|(_, &count)| count > 0)
+                .map(|(card, count)| format!("{count} {card}")),
+        )
+        .join(", ");
+    match items.is_empty() {
+        true => "nothing".into(),
+        false => items,
+    }
+}
+
+pub fn describe_stake(stake: &Stake) -> String {
+    format!("{} stars, {} coins", stake.star, stake.coin)
+}
+
+impl Display for TableRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [p0, p1] = &self.players;
+        writeln!(f, "Table ({p0}, {p1})")?;
+
+        let Some(report) = &self.report else {
+            let error = self.error.as_deref().unwrap_or("unknown error");
+            return writeln!(f, "  failed: {error}");
+        };
+
+        for line in &report.chat {
+            writeln!(f, "  {}: {}", self.players[line.player], line.content)?;
+        }
+        let [t0, t1] = &report.trades;
+        writeln!(
+            f,
+            "  offers: {p0} {} | {p1} {}",
+            describe_trade(t0),
+            describe_trade(t1)
+        )?;
+        for counter in &report.counters {
+            let [t0, t1] = &counter.trades;
+            writeln!(
+                f,
+                "  counter by {}: {p0} {} | {p1} {}",
+                self.players[counter.player],
+                describe_trade(t0),
+                describe_trade(t1)
+            )?;
+        }
+        let [a0, a1] = report.accepted;
+        match a0 && a1 {
+            true => writeln!(f, "  contract: signed")?,
+            false => writeln!(f, "  contract: void ({p0} {a0}, {p1} {a1})")?,
+        }
+
+        for line in &report.duel_chat {
+            writeln!(f, "  {}: {}", self.players[line.player], line.content)?;
+        }
+
+        match &report.stakes {
+            Some([s0, s1]) => writeln!(
+                f,
+                "  stakes: {p0} {} | {p1} {}",
+                describe_stake(s0),
+                describe_stake(s1)
+            )?,
+            None => return writeln!(f, "  no duel: a player has no cards"),
+        }
+
+        let card = |x: &Option<_>| x.as_ref().map_or("refuses".into(), ToString::to_string);
+        let [c0, c1] = &report.cards;
+        writeln!(f, "  cards: {p0} {} | {p1} {}", card(c0), card(c1))?;
+        match &report.result {
+            Some(DuelResult::Win(..)) => writeln!(f, "  result: {p0} wins"),
+            Some(DuelResult::Lose(..)) => writeln!(f, "  result: {p1} wins"),
+            Some(DuelResult::Tie(..)) => writeln!(f, "  result: tie"),
+            None => writeln!(f, "  result: no duel"),
+        }
+    }
+}
+
+fn write_inventories<'a>(
+    f: &mut std::fmt::Formatter<'_>,
+    inventories: impl IntoIterator<Item = (&'a String, &'a Inventory)>,
+) -> std::fmt::Result {
+    for (name, inventory) in inventories {
+        writeln!(f, "  {name}: {}", describe_inventory(inventory))?;
+    }
+    Ok(())
+}
+
+impl Display for Replay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "== Start ==")?;
+        write_inventories(f, self.players.iter().map(|x| (&x.name, &x.inventory)))?;
+
+        for round in &self.rounds {
+            writeln!(f)?;
+            writeln!(f, "== Round {} ==", round.index + 1)?;
+            for table in &round.tables {
+                write!(f, "{table}")?;
+            }
+            writeln!(f, "Inventories:")?;
+            write_inventories(f, round.inventories.iter().map(|(x, y)| (x, y)))?;
+        }
+
+        if !self.result.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "== Result ==")?;
+            write_inventories(f, self.result.iter().map(|x| (&x.name, &x.inventory)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Print the replay to the terminal, or step through it in the Bevy app.
+pub fn run(args: ReplayArgs) -> Result<()> {
+    let replay = Replay::load(&args.dir)?;
+    if !args.app {
+        print!("{replay}");
+        return Ok(());
+    }
+
+    let mut app = App::new();
+    match args.headless {
+        true => app.add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+                1.0 / 60.0,
+            ))),
+            LogPlugin::default(),
+        )),
+        false => app.add_plugins((
+            DefaultPlugins,
+            WorldInspectorPlugin::new().run_if(input_toggle_active(false, KeyCode::F1)),
+            DashboardPlugin,
+        )),
+    };
+    app.add_plugins(ReplayPlugin {
+        interval: Duration::from_secs_f64(args.interval),
+    })
+    .insert_resource(replay)
+    .run();
+    Ok(())
+}
+
+/// Steps through the [`Replay`] resource, one round at a time.
+#[derive(Debug, Default)]
+pub struct ReplayPlugin {
+    /// Time between rounds, or zero to step with the space key.
+    pub interval: Duration,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let timer = match self.interval.is_zero() {
+            true => None,
+            false => Some(Timer::new(self.interval, TimerMode::Repeating)),
+        };
+        app.register_type::<Inventory>()
+            .register_type::<Table>()
+            .register_type::<ReplayTable>()
+            .insert_resource(ReplayCursor { timer, round: 0 })
+            .add_systems(Startup, setup_replay)
+            .add_systems(Update, step_replay);
+    }
+}
+
+#[derive(Debug, Resource)]
+struct ReplayCursor {
+    timer: Option<Timer>,
+    /// The next round to show.
+    round: usize,
+}
+
+/// A table of the round on display, with what happens there.
+#[derive(Debug, Default, Clone, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct ReplayTable(pub String);
+
+fn setup_replay(mut commands: Commands, replay: Res<Replay>) {
+    for player in &replay.players {
+        commands.spawn((Name::new(player.name.clone()), player.inventory.clone()));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn step_replay(
+    mut commands: Commands,
+    time: Res<Time>,
+    keys: Option<Res<ButtonInput<KeyCode>>>,
+    replay: Res<Replay>,
+    mut cursor: ResMut<ReplayCursor>,
+    mut exit: EventWriter<AppExit>,
+    mut players: Query<(Entity, &Name, &mut Inventory)>,
+    tables: Query<Entity, With<ReplayTable>>,
+) {
+    let step = match &mut cursor.timer {
+        Some(timer) => timer.tick(time.delta()).just_finished(),
+        None => keys
+            .as_ref()
+            .is_none_or(|keys| keys.just_pressed(KeyCode::Space)),
+    };
+    if !step {
+        return;
+    }
+
+    for entity in &tables {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let Some(round) = replay.rounds.get(cursor.round) else {
+        if cursor.round == replay.rounds.len() {
+            bevy::log::info!("replay finished");
+            for (_, name, mut inventory) in &mut players {
+                if let Some(x) = replay.result.iter().find(|x| x.name == name.as_str()) {
+                    *inventory = x.inventory.clone();
+                }
+            }
+            // keep the window open to inspect the result
+            if keys.is_none() {
+                exit.send(AppExit::Success);
+            }
+        }
+        cursor.round = replay.rounds.len() + 1;
+        return;
+    };
+    cursor.round += 1;
+
+    bevy::log::info!("round {}", round.index + 1);
+    let entity = |name: &str| {
+        players
+            .iter()
+            .find(|(_, x, _)| x.as_str() == name)
+            .map(|(entity, ..)| entity)
+    };
+    for table in &round.tables {
+        bevy::log::info!("{}", table.to_string().trim_end());
+        let [Some(e0), Some(e1)] = table.players.each_ref().map(|x| entity(x)) else {
+            continue;
+        };
+        let [p0, p1] = &table.players;
+        commands.spawn((
+            Name::new(format!("Table ({p0}, {p1})")),
+            Table::new(e0, e1),
+            ReplayTable(table.to_string()),
+        ));
+    }
+
+    let snapshot: BTreeMap<_, _> = round.inventories.iter().cloned().collect();
+    for (_, name, mut inventory) in &mut players {
+        if let Some(x) = snapshot.get(name.as_str()) {
+            *inventory = x.clone();
+        }
+    }
+}
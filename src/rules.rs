@@ -0,0 +1,188 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use bevy::prelude::*;
+use derivative::Derivative;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::game::{Card, Cards, Inventory, PAPER, ROCK, SCISSORS};
+
+/// A kind of card and the kinds it beats.
+#[derive(Debug, Derivative, Clone, Reflect, Serialize, Deserialize)]
+#[derivative(Default)]
+#[serde(default)]
+pub struct CardKind {
+    pub name: Card,
+    /// Kinds of cards this card beats.
+    pub beats: Vec<Card>,
+    /// Winning with this card makes the loser pay this many times the stake.
+    #[derivative(Default(value = "1"))]
+    pub weight: usize,
+}
+
+impl CardKind {
+    pub fn new(name: Card, beats: impl IntoIterator<Item = Card>) -> Self {
+        let beats = beats.into_iter().collect();
+        Self {
+            name,
+            beats,
+            ..Default::default()
+        }
+    }
+}
+
+/// Rules of the game, loaded from a TOML or JSON file.
+#[derive(Debug, Derivative, Clone, Resource, Reflect, Serialize, Deserialize)]
+#[derivative(Default)]
+#[reflect(Resource, Default)]
+#[serde(default)]
+pub struct Rules {
+    /// Items each player starts with.
+    pub inventory: Inventory,
+    /// Kinds of cards in the game, and who beats whom.
+    #[derivative(Default(value = "Rules::rock_paper_scissors()"))]
+    pub cards: Vec<CardKind>,
+    /// Rounds of chat before trade and before duel.
+    #[derivative(Default(value = "6"))]
+    pub num_chat_rounds: usize,
+    /// Times an actor can retry after an erroneous action.
+    #[derivative(Default(value = "3"))]
+    pub max_trail_rounds: usize,
+    /// Counter-offers players can make in turn before the trade is void.
+    #[derivative(Default(value = "2"))]
+    pub max_counter_offers: usize,
+    /// Stars a player must keep to be safe.
+    #[derivative(Default(value = "3"))]
+    pub safe_stars: usize,
+    /// Minimum stars a player must bet in a duel.
+    #[derivative(Default(value = "1"))]
+    pub min_stake: usize,
+}
+
+impl Rules {
+    /// Load rules from a file. Files with `.json` extension are parsed as JSON, others as TOML.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        let rules: Self = match path.extension().and_then(|x| x.to_str()) {
+            Some("json") => serde_json::from_str(&text)?,
+            _ => toml::from_str(&text)?,
+        };
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    pub fn rock_paper_scissors() -> Vec<CardKind> {
+        vec![
+            CardKind::new(ROCK, [SCISSORS]),
+            CardKind::new(PAPER, [ROCK]),
+            CardKind::new(SCISSORS, [PAPER]),
+        ]
+    }
+
+    /// Check that all cards mentioned are defined in [`Rules::cards`], and that stars add up.
+    pub fn validate(&self) -> Result<()> {
+        if self.safe_stars == 0 {
+            bail!("safe_stars must be at least 1");
+        }
+        if self.min_stake > self.inventory.star {
+            bail!(
+                "min_stake ({}) is more than the {} stars players start with",
+                self.min_stake,
+                self.inventory.star
+            );
+        }
+        if self.cards.is_empty() {
+            bail!("no card is defined");
+        }
+        if !self.cards.iter().map(|kind| &kind.name).all_unique() {
+            bail!("card names are not unique");
+        }
+        let cards = self
+            .cards
+            .iter()
+            .flat_map(|kind| &kind.beats)
+            .chain(self.inventory.cards.keys());
+        for card in cards {
+            if self.card_kind(card).is_none() {
+                bail!("card {card} is not defined");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn card_kind(&self, card: &Card) -> Option<&CardKind> {
+        self.cards.iter().find(|kind| &kind.name == card)
+    }
+
+    /// Find the card kind with the given name, ignoring cases.
+    pub fn parse_card(&self, name: impl AsRef<str>) -> Option<Card> {
+        let name = name.as_ref().trim();
+        self.cards
+            .iter()
+            .find(|kind| kind.name.eq_ignore_ascii_case(name))
+            .map(|kind| kind.name.clone())
+    }
+
+    pub fn beats(&self, lhs: &Card, rhs: &Card) -> bool {
+        self.card_kind(lhs)
+            .map(|kind| kind.beats.contains(rhs))
+            .unwrap_or_default()
+    }
+
+    /// Returns the index of the winner, or `None` if it's a tie.
+    pub fn compare(&self, lhs: &Card, rhs: &Card) -> Option<usize> {
+        match (self.beats(lhs, rhs), self.beats(rhs, lhs)) {
+            (true, false) => Some(0),
+            (false, true) => Some(1),
+            _ => None,
+        }
+    }
+
+    pub fn weight(&self, card: &Card) -> usize {
+        self.card_kind(card).map(|kind| kind.weight).unwrap_or(1)
+    }
+
+    /// Describe cards in the order of [`Rules::cards`], e.g., "4 rock cards, 4 paper cards, and 4 scissors cards".
+    pub fn describe_cards(&self, cards: &Cards) -> String {
+        let items = self
+            .cards
+            .iter()
+            .map(|kind| {
+                let count = cards.get(&kind.name).copied().unwrap_or_default();
+                format!("{count} {} cards", kind.name.to_lowercase())
+            })
+            .collect_vec();
+        match items.as_slice() {
+            [] => String::new(),
+            [x] => x.clone(),
+            [x, y] => format!("{x} and {y}"),
+            [xs @ .., y] => format!("{}, and {y}", xs.join(", ")),
+        }
+    }
+
+    /// Describe who beats whom, e.g., "Rock beats scissors; paper beats rock; scissors beats paper."
+    pub fn describe_beats(&self) -> String {
+        let text = self
+            .cards
+            .iter()
+            .filter(|kind| !kind.beats.is_empty())
+            .map(|kind| {
+                let beats = kind.beats.iter().map(|x| x.to_lowercase()).join(" and ");
+                match kind.weight {
+                    0 | 1 => format!("{} beats {beats}", kind.name.to_lowercase()),
+                    x => format!(
+                        "{} beats {beats} and takes {x} times the stake",
+                        kind.name.to_lowercase()
+                    ),
+                }
+            })
+            .join("; ");
+        let mut chars = text.chars();
+        match chars.next() {
+            Some(x) => format!("{}{}.", x.to_uppercase(), chars.as_str()),
+            None => text,
+        }
+    }
+}
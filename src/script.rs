@@ -0,0 +1,418 @@
+use bevy::utils::BoxedFuture;
+use itertools::Itertools;
+
+use crate::{
+    game::{
+        Actor, ActorError, Card, ChatRecord, Inventory, OpponentData, PlayerData, PublicState,
+        StakeState, Trade, TradeResponse, TradeState,
+    },
+    rules::Rules,
+};
+
+/// Draw a random card from the inventory.
+fn random_card(rng: &mut fastrand::Rng, inventory: &Inventory) -> Option<Card> {
+    rng.choice(&inventory.deck()).cloned()
+}
+
+/// Draw a card of some kind from the inventory with the given weights.
+fn weighted_card(
+    rng: &mut fastrand::Rng,
+    inventory: &Inventory,
+    weights: impl IntoIterator<Item = (Card, f64)>,
+) -> Option<Card> {
+    let weights = weights
+        .into_iter()
+        .filter(|(card, weight)| inventory.count(card) > 0 && *weight > 0.0)
+        .collect_vec();
+    let total: f64 = weights.iter().map(|(_, weight)| weight).sum();
+
+    let mut x = rng.f64() * total;
+    for (card, weight) in weights {
+        if x < weight {
+            return Some(card);
+        }
+        x -= weight;
+    }
+    random_card(rng, inventory)
+}
+
+/// Always draws the same kind of card as long as it has one.
+#[derive(Debug, Default, Clone)]
+pub struct ConstantActor {
+    pub rng: fastrand::Rng,
+    pub card: Option<Card>,
+}
+
+impl ConstantActor {
+    pub fn new(rng: fastrand::Rng) -> Self {
+        Self {
+            rng,
+            ..Default::default()
+        }
+    }
+}
+
+impl Actor for ConstantActor {
+    fn notify<'a>(
+        &'a mut self,
+        _player: &'a PlayerData,
+        rules: &'a Rules,
+        _state: &'a PublicState,
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move {
+            if self.card.is_none() {
+                self.card = self.rng.choice(&rules.cards).map(|kind| kind.name.clone());
+            }
+            Ok(())
+        })
+    }
+
+    fn trade<'a>(
+        &'a mut self,
+        _player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+    ) -> BoxedFuture<'a, Result<Trade, ActorError>> {
+        Box::pin(async move { Ok(Trade::default()) })
+    }
+
+    fn accept_duel<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+        _state: StakeState<'a>,
+    ) -> BoxedFuture<'a, Result<Option<Card>, ActorError>> {
+        Box::pin(async move {
+            let card = match &self.card {
+                Some(card) if player.inventory.count(card) > 0 => Some(card.clone()),
+                _ => random_card(&mut self.rng, &player.inventory),
+            };
+            Ok(card)
+        })
+    }
+}
+
+/// Draws a card that beats the most common card on the stage.
+#[derive(Debug, Default, Clone)]
+pub struct CounterActor {
+    pub rng: fastrand::Rng,
+    pub rules: Rules,
+    pub state: PublicState,
+}
+
+impl CounterActor {
+    pub fn new(rng: fastrand::Rng) -> Self {
+        Self {
+            rng,
+            ..Default::default()
+        }
+    }
+}
+
+impl Actor for CounterActor {
+    fn notify<'a>(
+        &'a mut self,
+        _player: &'a PlayerData,
+        rules: &'a Rules,
+        state: &'a PublicState,
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move {
+            self.rules = rules.clone();
+            self.state = state.clone();
+            Ok(())
+        })
+    }
+
+    fn trade<'a>(
+        &'a mut self,
+        _player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+    ) -> BoxedFuture<'a, Result<Trade, ActorError>> {
+        Box::pin(async move { Ok(Trade::default()) })
+    }
+
+    fn accept_duel<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+        _state: StakeState<'a>,
+    ) -> BoxedFuture<'a, Result<Option<Card>, ActorError>> {
+        Box::pin(async move {
+            // the opponent is most likely to draw the most common card, excluding ours,
+            // or if they have revealed any, the one they have drawn the least from their hand
+            let common = match opponent.dossier.cards.is_empty() {
+                true => self
+                    .state
+                    .cards
+                    .iter()
+                    .map(|(card, count)| (card, count.saturating_sub(player.inventory.count(card))))
+                    .max_by_key(|(_, count)| *count)
+                    .map(|(card, _)| card.clone()),
+                false => self
+                    .rules
+                    .inventory
+                    .cards
+                    .iter()
+                    .map(|(card, count)| {
+                        let revealed = opponent.dossier.cards.get(card).copied();
+                        (card, count.saturating_sub(revealed.unwrap_or_default()))
+                    })
+                    .max_by_key(|(_, count)| *count)
+                    .map(|(card, _)| card.clone()),
+            };
+            let counters = match common {
+                Some(common) => self
+                    .rules
+                    .cards
+                    .iter()
+                    .filter(|kind| self.rules.compare(&kind.name, &common) == Some(0))
+                    .map(|kind| (kind.name.clone(), 1.0))
+                    .collect_vec(),
+                None => vec![],
+            };
+            Ok(weighted_card(&mut self.rng, &player.inventory, counters))
+        })
+    }
+}
+
+/// Draws cards following the Nash equilibrium of the payoff matrix.
+#[derive(Debug, Default, Clone)]
+pub struct NashActor {
+    pub rng: fastrand::Rng,
+    pub strategy: Vec<(Card, f64)>,
+}
+
+impl NashActor {
+    /// Iterations of fictitious play to approximate the equilibrium.
+    pub const NUM_ITERATIONS: usize = 10000;
+
+    pub fn new(rng: fastrand::Rng) -> Self {
+        Self {
+            rng,
+            ..Default::default()
+        }
+    }
+
+    /// Approximate the mixed equilibrium of the symmetric zero-sum duel with fictitious play.
+    pub fn solve(rules: &Rules) -> Vec<(Card, f64)> {
+        let cards = rules.cards.iter().map(|kind| &kind.name).collect_vec();
+        let payoff = |x: usize, y: usize| match rules.compare(cards[x], cards[y]) {
+            Some(0) => rules.weight(cards[x]) as f64,
+            Some(_) => -(rules.weight(cards[y]) as f64),
+            None => 0.0,
+        };
+
+        let n = cards.len();
+        let mut counts = vec![0usize; n];
+        let mut value = vec![0.0; n];
+        let mut choice = 0;
+        for _ in 0..Self::NUM_ITERATIONS {
+            counts[choice] += 1;
+            for (x, value) in value.iter_mut().enumerate() {
+                *value += payoff(x, choice);
+            }
+            choice = (0..n)
+                .max_by(|&x, &y| value[x].total_cmp(&value[y]))
+                .unwrap_or_default();
+        }
+
+        cards
+            .into_iter()
+            .zip(counts)
+            .map(|(card, count)| (card.clone(), count as f64 / Self::NUM_ITERATIONS as f64))
+            .collect()
+    }
+}
+
+impl Actor for NashActor {
+    fn notify<'a>(
+        &'a mut self,
+        _player: &'a PlayerData,
+        rules: &'a Rules,
+        _state: &'a PublicState,
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move {
+            if self.strategy.is_empty() {
+                self.strategy = Self::solve(rules);
+            }
+            Ok(())
+        })
+    }
+
+    fn trade<'a>(
+        &'a mut self,
+        _player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+    ) -> BoxedFuture<'a, Result<Trade, ActorError>> {
+        Box::pin(async move { Ok(Trade::default()) })
+    }
+
+    fn accept_duel<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+        _state: StakeState<'a>,
+    ) -> BoxedFuture<'a, Result<Option<Card>, ActorError>> {
+        Box::pin(async move {
+            let strategy = self.strategy.clone();
+            Ok(weighted_card(&mut self.rng, &player.inventory, strategy))
+        })
+    }
+}
+
+/// Never gives away coins or stars, and only accepts trades that bring in coins.
+#[derive(Debug, Default, Clone)]
+pub struct HoarderActor {
+    pub rng: fastrand::Rng,
+}
+
+impl HoarderActor {
+    pub fn new(rng: fastrand::Rng) -> Self {
+        Self { rng }
+    }
+}
+
+impl Actor for HoarderActor {
+    fn trade<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+    ) -> BoxedFuture<'a, Result<Trade, ActorError>> {
+        Box::pin(async move {
+            let card = random_card(&mut self.rng, &player.inventory);
+            Ok(Trade::card(card))
+        })
+    }
+
+    fn accept_trade<'a>(
+        &'a mut self,
+        _player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+        state: TradeState<'a>,
+    ) -> BoxedFuture<'a, Result<bool, ActorError>> {
+        Box::pin(async move {
+            let TradeState { this, that } = state;
+            Ok(this.star == 0 && this.coin == 0 && that.coin > 0)
+        })
+    }
+
+    fn accept_duel<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+        _state: StakeState<'a>,
+    ) -> BoxedFuture<'a, Result<Option<Card>, ActorError>> {
+        Box::pin(async move { Ok(random_card(&mut self.rng, &player.inventory)) })
+    }
+}
+
+/// Offers all stars above the safe threshold, and accepts trades paying enough coins for them.
+/// Trades paying too little are countered with the asking price.
+#[derive(Debug, Default, Clone)]
+pub struct SellerActor {
+    pub rng: fastrand::Rng,
+    pub rules: Rules,
+}
+
+impl SellerActor {
+    pub fn new(rng: fastrand::Rng) -> Self {
+        Self {
+            rng,
+            ..Default::default()
+        }
+    }
+
+    /// Coins asked for each star, i.e., the starting coins spread over the safe stars.
+    pub fn price(&self) -> usize {
+        self.rules.inventory.coin / self.rules.safe_stars.max(1)
+    }
+}
+
+impl Actor for SellerActor {
+    fn notify<'a>(
+        &'a mut self,
+        _player: &'a PlayerData,
+        rules: &'a Rules,
+        _state: &'a PublicState,
+    ) -> BoxedFuture<'a, Result<(), ActorError>> {
+        Box::pin(async move {
+            self.rules = rules.clone();
+            Ok(())
+        })
+    }
+
+    fn trade<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+    ) -> BoxedFuture<'a, Result<Trade, ActorError>> {
+        Box::pin(async move {
+            let star = player.inventory.star.saturating_sub(self.rules.safe_stars);
+            let trade = Trade {
+                star,
+                ..Default::default()
+            };
+            Ok(trade.normalize(&player.inventory))
+        })
+    }
+
+    fn accept_trade<'a>(
+        &'a mut self,
+        _player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+        state: TradeState<'a>,
+    ) -> BoxedFuture<'a, Result<bool, ActorError>> {
+        Box::pin(async move {
+            let TradeState { this, that } = state;
+            Ok(this.coin == 0 && that.coin >= this.star * self.price())
+        })
+    }
+
+    fn respond_trade<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        opponent: &'a OpponentData,
+        history: &'a [ChatRecord],
+        state: TradeState<'a>,
+        counters: usize,
+    ) -> BoxedFuture<'a, Result<TradeResponse, ActorError>> {
+        Box::pin(async move {
+            if self.accept_trade(player, opponent, history, state).await? {
+                return Ok(TradeResponse::Accept);
+            }
+            let TradeState { this, that } = state;
+            if counters == 0 || this.star == 0 {
+                return Ok(TradeResponse::Reject);
+            }
+            let this = Trade {
+                star: this.star,
+                ..Default::default()
+            };
+            let that = Trade {
+                coin: this.star * self.price(),
+                ..that.clone()
+            };
+            Ok(TradeResponse::Counter { this, that })
+        })
+    }
+
+    fn accept_duel<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+        _state: StakeState<'a>,
+    ) -> BoxedFuture<'a, Result<Option<Card>, ActorError>> {
+        Box::pin(async move { Ok(random_card(&mut self.rng, &player.inventory)) })
+    }
+}
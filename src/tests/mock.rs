@@ -0,0 +1,267 @@
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::llm::{
+    Choice, ChooseItem, ChooseRequest, ChooseResponse, CompletionRequest, CompletionResponse,
+};
+
+pub type CompleteFn = dyn Fn(&CompletionRequest) -> String + Send + Sync;
+pub type ChooseFn = dyn Fn(&ChooseRequest) -> usize + Send + Sync;
+
+/// A request received by the mock server.
+#[derive(Debug, Clone)]
+pub enum MockRequest {
+    Completion(CompletionRequest),
+    Choose(ChooseRequest),
+}
+
+/// An in-process stand-in for Ai00 server, answering `/api/oai/completions` and `/api/oai/chooses`.
+///
+/// Completions are taken from the script in order, then from the rule.
+/// Chooses rank the choice picked by the rule first, and the others in order.
+/// The first few requests can be made to fail with `500 Internal Server Error`.
+/// Each request can be held for a while, to observe how many requests are served at the same time.
+pub struct MockLlm {
+    pub failures: Mutex<usize>,
+    pub delay: Duration,
+    pub active: AtomicUsize,
+    pub peak: AtomicUsize,
+    pub script: Mutex<VecDeque<String>>,
+    pub complete: Box<CompleteFn>,
+    pub choose: Box<ChooseFn>,
+    pub requests: Mutex<Vec<MockRequest>>,
+}
+
+impl Default for MockLlm {
+    fn default() -> Self {
+        Self {
+            failures: Default::default(),
+            delay: Default::default(),
+            active: Default::default(),
+            peak: Default::default(),
+            script: Default::default(),
+            complete: Box::new(Self::default_complete),
+            choose: Box::new(|_| 0),
+            requests: Default::default(),
+        }
+    }
+}
+
+impl MockLlm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Says "Yes" to yes-or-no questions, and "Fine." to everything else.
+    pub fn default_complete(request: &CompletionRequest) -> String {
+        match request.bnf_schema.contains("Yes") {
+            true => "Yes\".".into(),
+            false => "Fine.".into(),
+        }
+    }
+
+    /// Fail the next `count` requests.
+    pub fn fail(self, count: usize) -> Self {
+        let failures = Mutex::new(count);
+        Self { failures, ..self }
+    }
+
+    /// Hold each request for the duration before responding.
+    pub fn delay(self, delay: Duration) -> Self {
+        Self { delay, ..self }
+    }
+
+    pub fn script(self, texts: impl IntoIterator<Item = impl ToString>) -> Self {
+        let script = texts.into_iter().map(|x| x.to_string()).collect();
+        let script = Mutex::new(script);
+        Self { script, ..self }
+    }
+
+    pub fn complete(
+        self,
+        f: impl Fn(&CompletionRequest) -> String + Send + Sync + 'static,
+    ) -> Self {
+        let complete = Box::new(f);
+        Self { complete, ..self }
+    }
+
+    pub fn choose(self, f: impl Fn(&ChooseRequest) -> usize + Send + Sync + 'static) -> Self {
+        let choose = Box::new(f);
+        Self { choose, ..self }
+    }
+
+    /// Start serving on a random local port.
+    pub fn serve(self) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind mock server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let llm = Arc::new(self);
+
+        {
+            let llm = llm.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let llm = llm.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = llm.handle(stream) {
+                            bevy::log::error!("mock server: {err}");
+                        }
+                    });
+                }
+            });
+        }
+
+        MockServer { url, llm }
+    }
+
+    fn handle(&self, stream: TcpStream) -> Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let Some(path) = line.split_whitespace().nth(1).map(str::to_owned) else {
+            bail!("invalid request line: {line}");
+        };
+
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                if key.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse()?;
+                }
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+
+        let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(active, Ordering::SeqCst);
+        thread::sleep(self.delay);
+        let result = self.respond(stream, &path, &body);
+        self.active.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+
+    fn respond(&self, stream: TcpStream, path: &str, body: &[u8]) -> Result<()> {
+        {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return respond(stream, "500 Internal Server Error", &"scheduled failure");
+            }
+        }
+
+        let response = match path {
+            "/api/oai/completions" => serde_json::from_slice(body).map(|request| {
+                let response = self.respond_completion(&request);
+                self.push(MockRequest::Completion(request));
+                serde_json::to_value(response)
+            }),
+            "/api/oai/chooses" => serde_json::from_slice(body).map(|request| {
+                let response = self.respond_choose(&request);
+                self.push(MockRequest::Choose(request));
+                serde_json::to_value(response)
+            }),
+            _ => return respond(stream, "404 Not Found", &path),
+        };
+        match response {
+            Ok(response) => respond(stream, "200 OK", &response?),
+            Err(err) => respond(stream, "400 Bad Request", &err.to_string()),
+        }
+    }
+
+    fn push(&self, request: MockRequest) {
+        self.requests.lock().unwrap().push(request);
+    }
+
+    fn respond_completion(&self, request: &CompletionRequest) -> CompletionResponse {
+        let scripted = self.script.lock().unwrap().pop_front();
+        let text = scripted.unwrap_or_else(|| (self.complete)(request));
+        CompletionResponse {
+            choices: vec![Choice { index: 0, text }],
+            model: "mock".into(),
+        }
+    }
+
+    fn respond_choose(&self, request: &ChooseRequest) -> ChooseResponse {
+        let pick = (self.choose)(request).min(request.choices.len().saturating_sub(1));
+        let order = (0..request.choices.len()).sorted_by_key(|&index| index != pick);
+        let data = order
+            .enumerate()
+            .map(|(rank, index)| ChooseItem {
+                choice: request.choices[index].clone(),
+                index,
+                rank,
+                perplexity: 1.0 + rank as f32,
+            })
+            .collect();
+        ChooseResponse { data }
+    }
+}
+
+fn respond(mut stream: TcpStream, status: &str, body: &impl Serialize) -> Result<()> {
+    let body = serde_json::to_vec(body)?;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// A running mock server. The server keeps running in the background until the test ends.
+pub struct MockServer {
+    pub url: String,
+    pub llm: Arc<MockLlm>,
+}
+
+impl MockServer {
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.llm.requests.lock().unwrap().clone()
+    }
+
+    /// The most requests served at the same time so far.
+    pub fn peak(&self) -> usize {
+        self.llm.peak.load(Ordering::SeqCst)
+    }
+}
+
+/// The name of the role the prompt asks to speak, i.e., the name before the last colon.
+pub fn speaker(prompt: &str) -> &str {
+    let paragraph = prompt.rsplit("\n\n").next().unwrap_or_default();
+    paragraph.split(':').next().unwrap_or_default().trim()
+}
+
+/// The item the prompt asks the player to offer in the trade, e.g., "coins".
+pub fn trade_item(prompt: &str) -> Option<&str> {
+    let (_, rest) = prompt.rsplit_once("exactly how many ")?;
+    let (item, _) = rest.split_once(" you would like to offer")?;
+    Some(item)
+}
+
+/// The item the prompt asks the player to stake in the duel, e.g., "stars".
+pub fn stake_item(prompt: &str) -> Option<&str> {
+    let (_, rest) = prompt.rsplit_once("exactly how many ")?;
+    let (item, _) = rest.split_once(" you would like to stake")?;
+    Some(item)
+}
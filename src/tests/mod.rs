@@ -0,0 +1,2145 @@
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_std::{sync::Mutex, task::block_on};
+use bevy::{prelude::*, utils::BoxedFuture};
+use itertools::Itertools;
+use serde::Deserialize;
+
+use crate::{
+    analyze::{analyze_game, find_games, gini, stated_offer, Analysis},
+    backend::{
+        Ai00Backend, LlmBackend, LlmError, LlmLimiter, LlmTraffic, OpenAiBackend, RetryPolicy,
+        TokenLogprob,
+    },
+    cassette::CassetteBackend,
+    dashboard::{DashboardFeed, DashboardFeedPlugin},
+    dossier::Dossier,
+    event::{read_game_events, GameEvent, GAME_EVENTS_FILE},
+    game::{
+        duel, duel_with_progress, Actor, ActorError, ActorKind, Card, ChatRecord, DuelProgress,
+        DuelReport, DuelResult, DummyActor, FailurePolicy, GameLog, GamePlugin, Inventory,
+        OpponentData, Player, PlayerData, PlayerTimer, PublicState, Role, Stake, StakeState,
+        TableError, Trade, GAME_LOG_FILE, PAPER, ROCK, SCISSORS,
+    },
+    llm::{
+        ChooseItem, ChooseRequest, ChooseResponse, CompletionRequest, CompletionResponse, LlmActor,
+        LlmRecord, TradeMode,
+    },
+    memory::{Memory, MemoryPolicy},
+    remote::RemoteActor,
+    replay::Replay,
+    rules::Rules,
+    script::{CounterActor, NashActor, SellerActor},
+    tournament::{
+        play_tournament, rank_players, GameOutcome, PlayerOutcome, Summary, TournamentArgs,
+    },
+    tui::{draw, TuiPlugin, TuiView},
+    Settings,
+};
+
+use mock::{speaker, stake_item, trade_item, MockLlm, MockRequest, MockServer};
+
+pub mod mock;
+
+fn llm_actor(server: &MockServer, seed: u64) -> LlmActor {
+    let backend = Arc::new(Ai00Backend::new(&server.url));
+    LlmActor::new(backend, Default::default(), fastrand::Rng::with_seed(seed))
+}
+
+/// Retries quickly, so that tests against failing servers finish in time.
+fn impatient() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(10),
+        timeout: Duration::from_secs(10),
+    }
+}
+
+fn player_data(index: u32, name: &str, rules: &Rules) -> PlayerData {
+    PlayerData {
+        entity: Entity::from_raw(index),
+        name: Name::new(name.to_owned()),
+        inventory: rules.inventory.clone(),
+        timer: PlayerTimer(16),
+        dossier: default(),
+    }
+}
+
+/// Plays a single duel between Alice and Bob, with the initial inventories of the rules.
+fn duel_alice_bob(
+    alice: impl Actor + 'static,
+    bob: impl Actor + 'static,
+    rules: &Rules,
+) -> Result<([Inventory; 2], DuelReport), TableError> {
+    let actors: [Arc<Mutex<dyn Actor>>; 2] =
+        [Arc::new(Mutex::new(alice)), Arc::new(Mutex::new(bob))];
+    let data = [player_data(0, "Alice", rules), player_data(1, "Bob", rules)];
+    block_on(duel(rules.clone(), PublicState::default(), actors, data))
+}
+
+fn position(request: &ChooseRequest, choice: &str) -> usize {
+    request
+        .choices
+        .iter()
+        .position(|x| x.trim() == choice)
+        .unwrap_or_default()
+}
+
+/// Alice offers 2 coins and draws paper; Bob offers nothing and draws rock.
+fn alice_and_bob(request: &ChooseRequest) -> usize {
+    let speaker = speaker(&request.prompt);
+    let item = trade_item(&request.prompt);
+    match (speaker, item) {
+        ("Alice", _) if request.choices.iter().any(|x| x == PAPER.as_ref()) => {
+            position(request, PAPER.as_ref())
+        }
+        ("Bob", _) if request.choices.iter().any(|x| x == ROCK.as_ref()) => {
+            position(request, ROCK.as_ref())
+        }
+        ("Alice", Some("coins")) if request.prompt.ends_with("would like to offer Bob") => {
+            position(request, "2")
+        }
+        ("Alice", Some("coins")) => position(request, "I would like to"),
+        _ => 0,
+    }
+}
+
+/// Bob tells his plan in public; everyone else behaves as default.
+fn bob_tells_plan(request: &CompletionRequest) -> String {
+    match speaker(&request.prompt) {
+        "Bob" if request.bnf_schema.is_empty() => "I am going to draw rock.".into(),
+        _ => MockLlm::default_complete(request),
+    }
+}
+
+#[test]
+fn scripted_completions() {
+    let server = MockLlm::new().script(["I see."]).serve();
+    let rules = Rules::default();
+    let state = PublicState::default();
+    let alice = player_data(0, "Alice", &rules);
+    let bob = player_data(1, "Bob", &rules);
+
+    let mut actor = llm_actor(&server, 0);
+    let records = block_on(async {
+        actor.notify(&alice, &rules, &state).await.unwrap();
+        actor.chat_trade(&alice, &bob.into(), &[], 0).await.unwrap()
+    });
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].content, "Fine.");
+
+    let history = block_on(actor.history.lock());
+    let texts = history
+        .iter()
+        .map(|record| match record {
+            LlmRecord::Completion { response, .. } => response.model_text(),
+            LlmRecord::Choose { .. } => unreachable!(),
+        })
+        .collect_vec();
+    assert_eq!(texts, ["I see.", "Fine."]);
+}
+
+#[test]
+fn retries_recover_from_failures() {
+    let server = MockLlm::new().fail(2).script(["I see."]).serve();
+    let rules = Rules::default();
+    let alice = player_data(0, "Alice", &rules);
+
+    let mut actor = LlmActor {
+        retry: impatient(),
+        ..llm_actor(&server, 0)
+    };
+    block_on(actor.notify(&alice, &rules, &PublicState::default())).unwrap();
+
+    // the failed requests are not recorded
+    assert_eq!(server.requests().len(), 1);
+    assert_eq!(block_on(actor.history.lock()).len(), 1);
+    assert_eq!(
+        actor.chat.last().unwrap().role,
+        Role::actor(alice.entity, "Alice")
+    );
+}
+
+#[test]
+fn retries_go_through_limiter() {
+    let server = MockLlm::new().fail(2).script(["I see."]).serve();
+    let rules = Rules::default();
+    let alice = player_data(0, "Alice", &rules);
+
+    // every attempt waits for its own slot, and backoff is not counted as latency
+    let limiter = Arc::new(LlmLimiter::new(1, 20.0));
+    let mut actor = LlmActor {
+        retry: RetryPolicy {
+            backoff: Duration::from_millis(200),
+            ..impatient()
+        },
+        limiter: limiter.clone(),
+        ..llm_actor(&server, 0)
+    };
+    let start = Instant::now();
+    block_on(actor.notify(&alice, &rules, &PublicState::default())).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    let traffic = limiter.traffic();
+    assert_eq!(traffic.completed, 3);
+    assert!(traffic.mean_latency().unwrap() < Duration::from_millis(100));
+}
+
+#[test]
+fn limiter_bounds_requests_in_flight() {
+    let server = MockLlm::new().delay(Duration::from_millis(50)).serve();
+    let rules = Rules::default();
+    let state = PublicState::default();
+    let alice = player_data(0, "Alice", &rules);
+    let bob = player_data(1, "Bob", &rules);
+
+    let limiter = Arc::new(LlmLimiter::new(2, 0.0));
+    let mut actor = LlmActor {
+        limiter: limiter.clone(),
+        ..llm_actor(&server, 0)
+    };
+    block_on(async {
+        actor.notify(&alice, &rules, &state).await.unwrap();
+        // asks about stars, coins and each kind of cards at the same time
+        actor.trade(&alice, &bob.into(), &[]).await.unwrap();
+    });
+
+    assert_eq!(server.peak(), 2);
+    let traffic = limiter.traffic();
+    assert_eq!(
+        traffic,
+        LlmTraffic {
+            waiting: 0,
+            in_flight: 0,
+            completed: server.requests().len(),
+            latency: traffic.latency,
+        }
+    );
+    assert!(traffic.mean_latency().unwrap() >= Duration::from_millis(50));
+}
+
+#[test]
+fn limiter_spaces_requests() {
+    let limiter = LlmLimiter::new(0, 50.0);
+    let start = Instant::now();
+    block_on(async {
+        for _ in 0..5 {
+            let _permit = limiter.acquire().await;
+        }
+    });
+    assert!(start.elapsed() >= Duration::from_millis(80));
+    assert_eq!(limiter.traffic().completed, 5);
+}
+
+#[test]
+fn duel_fails_when_server_is_down() {
+    let server = MockLlm::new().fail(usize::MAX).serve();
+    let rules = Rules::default();
+
+    let [alice, bob] = [0, 1].map(|seed| LlmActor {
+        retry: impatient(),
+        ..llm_actor(&server, seed)
+    });
+
+    let result = duel_alice_bob(alice, bob, &rules);
+    let Err(TableError::Actor(0, ActorError::Llm(LlmError::Exhausted { attempts, .. }))) = result
+    else {
+        panic!("unexpected result: {result:?}");
+    };
+    assert_eq!(attempts, impatient().max_attempts);
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn duel_with_llm_actors() {
+    let server = MockLlm::new()
+        .complete(bob_tells_plan)
+        .choose(alice_and_bob)
+        .serve();
+    let rules = Rules::default();
+
+    let alice = llm_actor(&server, 0);
+    let bob = llm_actor(&server, 1);
+    let histories = [alice.history.clone(), bob.history.clone()];
+    let actors: [Arc<Mutex<dyn Actor>>; 2] =
+        [Arc::new(Mutex::new(alice)), Arc::new(Mutex::new(bob))];
+
+    let data = [
+        player_data(0, "Alice", &rules),
+        player_data(1, "Bob", &rules),
+    ];
+    let state = PublicState {
+        player: 2,
+        cards: rules
+            .cards
+            .iter()
+            .map(|kind| (kind.name.clone(), 2 * rules.inventory.count(&kind.name)))
+            .collect(),
+    };
+
+    let ([alice, bob], report) = block_on(duel(rules.clone(), state, actors, data)).unwrap();
+
+    // alice pays 2 coins for nothing, then wins a star with paper against rock
+    assert_eq!(alice.star, 4);
+    assert_eq!(alice.coin, 8);
+    assert_eq!(alice.count(&PAPER), 3);
+    assert_eq!(alice.count(&ROCK), 4);
+    assert_eq!(bob.star, 2);
+    assert_eq!(bob.coin, 12);
+    assert_eq!(bob.count(&PAPER), 4);
+    assert_eq!(bob.count(&ROCK), 3);
+    assert_eq!(report.trades[0].coin, 2);
+    assert_eq!(report.accepted, [true, true]);
+    assert_eq!(report.cards, [Some(PAPER), Some(ROCK)]);
+    assert!(matches!(report.result, Some(DuelResult::Win(..))));
+
+    let [alice, bob] = histories.map(|history| block_on(history.lock()).clone());
+    assert_eq!(alice.len() + bob.len(), server.requests().len());
+
+    // records are made by the player or by the player's own assistant
+    let owner = |role: &Role| match role {
+        Role::System(entity)
+        | Role::Think(entity)
+        | Role::Help(entity)
+        | Role::Assistant(entity)
+        | Role::Actor(entity, _) => Some(*entity),
+        Role::None => None,
+    };
+    let players = [(&alice, "Alice", PAPER), (&bob, "Bob", ROCK)];
+    for (index, (history, name, card)) in players.into_iter().enumerate() {
+        let entity = Entity::from_raw(index as u32);
+        assert!(history.iter().all(|record| match record {
+            LlmRecord::Completion { role, player, .. } => {
+                owner(role) == Some(entity) && player.as_ref().unwrap().name.as_str() == name
+            }
+            LlmRecord::Choose { role, .. } => owner(role) == Some(entity),
+        }));
+
+        // the last choice is the card to draw
+        let Some(LlmRecord::Choose { response, .. }) = history
+            .iter()
+            .rev()
+            .find(|record| matches!(record, LlmRecord::Choose { .. }))
+        else {
+            panic!("{name} never chooses");
+        };
+        assert_eq!(response.data[0].choice, card.as_ref());
+    }
+
+    // alice is asked to confirm her offer of 2 coins in the contract
+    assert!(alice.iter().any(|record| match record {
+        LlmRecord::Completion { request, .. } => request.prompt.contains("- Coins: 2"),
+        _ => false,
+    }));
+
+    // alice hears what bob says in the public chat
+    assert!(server.requests().iter().any(|request| {
+        let prompt = match request {
+            MockRequest::Completion(request) => &request.prompt,
+            MockRequest::Choose(request) => &request.prompt,
+        };
+        speaker(prompt) == "Alice" && prompt.contains("Bob: I am going to draw rock.")
+    }));
+}
+
+#[test]
+fn cassette_replays_duel_without_server() {
+    let server = MockLlm::new()
+        .complete(bob_tells_plan)
+        .choose(alice_and_bob)
+        .serve();
+    let dir = TempDir::new();
+    let path = dir.join("tape.jsonl");
+    let rules = Rules::default();
+
+    let run = |backend: Arc<dyn LlmBackend>| {
+        let actors = [0, 1].map(|seed| {
+            let actor = LlmActor {
+                retry: impatient(),
+                ..LlmActor::new(
+                    backend.clone(),
+                    Default::default(),
+                    fastrand::Rng::with_seed(seed),
+                )
+            };
+            Arc::new(Mutex::new(actor))
+        });
+        let histories = actors
+            .iter()
+            .map(|actor| block_on(actor.lock()).history.clone())
+            .collect_vec();
+        let actors = actors.map(|actor| actor as Arc<Mutex<dyn Actor>>);
+        let data = [
+            player_data(0, "Alice", &rules),
+            player_data(1, "Bob", &rules),
+        ];
+        let (result, _) =
+            block_on(duel(rules.clone(), PublicState::default(), actors, data)).unwrap();
+        // concurrent requests finish in any order
+        let histories = histories
+            .iter()
+            .map(|history| {
+                let history = block_on(history.lock());
+                history
+                    .iter()
+                    .map(|record| serde_json::to_string(record).unwrap())
+                    .sorted()
+                    .collect_vec()
+            })
+            .collect_vec();
+        (result, histories)
+    };
+
+    let inner = Arc::new(Ai00Backend::new(&server.url));
+    let recorder = Arc::new(CassetteBackend::record(inner, &path).unwrap());
+    let (recorded, recorded_histories) = run(recorder);
+    let num_requests = server.requests().len();
+
+    let player = Arc::new(CassetteBackend::replay(&path).unwrap());
+    let (replayed, replayed_histories) = run(player.clone());
+
+    assert_eq!(server.requests().len(), num_requests);
+    for (x, y) in recorded.iter().zip(&replayed) {
+        assert_eq!(x.star, y.star);
+        assert_eq!(x.coin, y.coin);
+        assert_eq!(x.cards, y.cards);
+    }
+    assert_eq!(recorded_histories, replayed_histories);
+
+    // requests not on the cassette fail
+    let request = CompletionRequest::default();
+    assert!(block_on(player.complete(&request)).is_err());
+}
+
+#[derive(Debug, Deserialize)]
+struct Dump {
+    name: String,
+    inventory: Inventory,
+    history: Vec<LlmRecord>,
+}
+
+/// A fresh directory under the system temp dir, removed with everything in it when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("cruise-test-{}", uuid::Uuid::new_v4()));
+        Self(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn read_dumps(path: &Path) -> Vec<Dump> {
+    let mut dumps = vec![];
+    for entry in std::fs::read_dir(path).unwrap().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            dumps.extend(read_dumps(&path));
+        } else if path.extension().is_some_and(|x| x == "json")
+            && path.file_name().is_some_and(|x| x != GAME_LOG_FILE)
+        {
+            let data = std::fs::read(&path).unwrap();
+            dumps.push(serde_json::from_slice(&data).unwrap());
+        }
+    }
+    dumps
+}
+
+/// Run the game to the end, and return the exit code and the final inventories of players.
+fn run_game(settings: &Settings, rules: &Rules) -> (AppExit, Vec<(String, Inventory)>) {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, GamePlugin))
+        .insert_resource(settings.clone())
+        .insert_resource(rules.clone());
+    app.finish();
+    app.cleanup();
+
+    let start = Instant::now();
+    let exit = loop {
+        assert!(
+            start.elapsed() < Duration::from_secs(120),
+            "game takes too long"
+        );
+        app.update();
+        if let Some(exit) = app.should_exit() {
+            break exit;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    };
+
+    let world = app.world_mut();
+    let players = world
+        .query_filtered::<(&Name, &Inventory), With<Player>>()
+        .iter(world)
+        .map(|(name, inventory)| (name.to_string(), inventory.clone()))
+        .collect_vec();
+    (exit, players)
+}
+
+#[test]
+fn small_game_with_llm_actors() {
+    // each player draws a kind of card depending on the length of the name
+    let server = MockLlm::new()
+        .choose(|request| speaker(&request.prompt).len() % request.choices.len())
+        .serve();
+    let output = TempDir::new();
+
+    let rules = Rules::default();
+    let settings = Settings {
+        url: server.url.clone(),
+        output: output.to_path_buf(),
+        num_players: 4,
+        max_rounds: 2,
+        seed: Some(42),
+        actors: vec![ActorKind::Llm],
+        ..Default::default()
+    };
+
+    let (exit, players) = run_game(&settings, &rules);
+    assert_eq!(exit, AppExit::Success);
+    assert_eq!(players.len(), settings.num_players);
+
+    // stars and coins only change hands
+    let num_players = settings.num_players;
+    let star: usize = players.iter().map(|(_, x)| x.star).sum();
+    let coin: usize = players.iter().map(|(_, x)| x.coin).sum();
+    let cards: usize = players.iter().map(|(_, x)| x.num_cards()).sum();
+    assert_eq!(star, num_players * rules.inventory.star);
+    assert_eq!(coin, num_players * rules.inventory.coin);
+    assert!(cards < num_players * rules.inventory.num_cards());
+
+    let dumps = read_dumps(&output);
+    assert_eq!(dumps.len(), num_players);
+
+    for dump in &dumps {
+        let (_, inventory) = players.iter().find(|(name, _)| name == &dump.name).unwrap();
+        assert_eq!(dump.inventory.star, inventory.star);
+        assert_eq!(dump.inventory.coin, inventory.coin);
+        assert_eq!(dump.inventory.cards, inventory.cards);
+        assert!(!dump.history.is_empty());
+    }
+
+    let requests = server.requests();
+    let choose = |x: &&MockRequest| matches!(x, MockRequest::Choose(_));
+    let num_records = dumps.iter().map(|x| x.history.len()).sum::<usize>();
+    let num_chooses = dumps
+        .iter()
+        .flat_map(|x| &x.history)
+        .filter(|x| matches!(x, LlmRecord::Choose { .. }))
+        .count();
+    assert_eq!(num_records, requests.len());
+    assert_eq!(num_chooses, requests.iter().filter(choose).count());
+}
+
+#[test]
+fn failing_tables_forfeit_or_abort() {
+    let server = MockLlm::new().fail(usize::MAX).serve();
+    let output = TempDir::new();
+
+    let rules = Rules::default();
+    let settings = Settings {
+        url: server.url.clone(),
+        retry: impatient(),
+        output: output.to_path_buf(),
+        num_players: 4,
+        max_rounds: 2,
+        seed: Some(42),
+        actors: vec![ActorKind::Llm],
+        ..Default::default()
+    };
+
+    // every table fails, where someone pays the minimum stake and no card is drawn
+    let (exit, players) = run_game(&settings, &rules);
+    assert_eq!(exit, AppExit::Success);
+    let star: usize = players.iter().map(|(_, x)| x.star).sum();
+    assert_eq!(star, settings.num_players * rules.inventory.star);
+    assert!(players
+        .iter()
+        .all(|(_, x)| x.num_cards() == rules.inventory.num_cards()));
+    assert!(players.iter().any(|(_, x)| x.star != rules.inventory.star));
+
+    // the first failing table aborts the game
+    let settings = Settings {
+        on_failure: FailurePolicy::Abort,
+        ..settings
+    };
+    let (exit, _) = run_game(&settings, &rules);
+    assert!(exit.is_error());
+}
+
+#[test]
+fn replay_rebuilds_game_from_output() {
+    let output = TempDir::new();
+
+    let rules = Rules::default();
+    let settings = Settings {
+        output: output.to_path_buf(),
+        num_players: 6,
+        max_rounds: 4,
+        seed: Some(7),
+        actors: vec![ActorKind::Random, ActorKind::Nash, ActorKind::Counter],
+        ..Default::default()
+    };
+    let (exit, players) = run_game(&settings, &rules);
+    assert_eq!(exit, AppExit::Success);
+
+    let dir = std::fs::read_dir(&output)
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| path.join(GAME_LOG_FILE).exists())
+        .unwrap();
+    let replay = Replay::load(&dir).unwrap();
+    assert!(Replay::load(&output).is_err());
+
+    assert_eq!(replay.players.len(), settings.num_players);
+    assert!(!replay.rounds.is_empty());
+    for (index, round) in replay.rounds.iter().enumerate() {
+        assert_eq!(round.index, index);
+        assert_eq!(round.inventories.len(), settings.num_players);
+
+        // stars only change hands, and each table starts from the previous snapshot
+        let star: usize = round.inventories.iter().map(|(_, x)| x.star).sum();
+        assert_eq!(star, settings.num_players * rules.inventory.star);
+        let previous = match index {
+            0 => replay
+                .players
+                .iter()
+                .map(|x| (x.name.clone(), x.inventory.clone()))
+                .collect_vec(),
+            _ => replay.rounds[index - 1].inventories.clone(),
+        };
+        for table in &round.tables {
+            assert!(table.report.is_some());
+            for (name, before) in table.players.iter().zip(&table.before) {
+                let (_, inventory) = previous.iter().find(|(x, _)| x == name).unwrap();
+                assert_eq!(inventory.star, before.star);
+                assert_eq!(inventory.coin, before.coin);
+                assert_eq!(inventory.cards, before.cards);
+            }
+        }
+    }
+
+    // the result is the inventories of players at the end of the game
+    assert_eq!(replay.result.len(), players.len());
+    for (name, inventory) in &players {
+        let record = replay.result.iter().find(|x| &x.name == name).unwrap();
+        assert_eq!(record.inventory.star, inventory.star);
+        assert_eq!(record.inventory.coin, inventory.coin);
+        assert_eq!(record.inventory.cards, inventory.cards);
+    }
+
+    let text = replay.to_string();
+    assert!(text.contains("== Round 1 =="));
+    assert!(players.iter().all(|(name, _)| text.contains(name.as_str())));
+}
+
+#[test]
+fn replay_reads_unfinished_game() {
+    let output = TempDir::new();
+    let settings = Settings {
+        output: output.to_path_buf(),
+        num_players: 6,
+        max_rounds: 4,
+        seed: Some(7),
+        actors: vec![ActorKind::Random],
+        ..Default::default()
+    };
+
+    // stop the game as soon as a table finishes, as if it is killed
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, GamePlugin))
+        .insert_resource(settings)
+        .insert_resource(Rules::default());
+    app.finish();
+    app.cleanup();
+    while app.world().resource::<GameLog>().tables.is_empty() {
+        assert!(app.should_exit().is_none());
+        app.update();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    drop(app);
+
+    let games = find_games(&output).unwrap();
+    let replay = Replay::load(&games[0]);
+    let replay = replay.unwrap();
+    assert_eq!(replay.players.len(), 6);
+    assert!(!replay.rounds.is_empty());
+}
+
+#[test]
+fn event_log_records_game() {
+    let output = TempDir::new();
+
+    let rules = Rules::default();
+    let settings = Settings {
+        output: output.to_path_buf(),
+        num_players: 6,
+        max_rounds: 4,
+        seed: Some(11),
+        actors: vec![ActorKind::Random, ActorKind::Seller, ActorKind::Hoarder],
+        ..Default::default()
+    };
+    let (exit, players) = run_game(&settings, &rules);
+    assert_eq!(exit, AppExit::Success);
+
+    let path = std::fs::read_dir(&output)
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.path().join(GAME_EVENTS_FILE))
+        .find(|path| path.exists())
+        .unwrap();
+    let records = read_game_events(&path).unwrap();
+
+    let joined = records
+        .iter()
+        .filter_map(|x| match &x.event {
+            GameEvent::PlayerJoined { player, name, .. } => Some((*player, name.clone())),
+            _ => None,
+        })
+        .collect_vec();
+    assert_eq!(joined.len(), settings.num_players);
+    assert!(matches!(records.last().unwrap().event, GameEvent::GameOver));
+
+    // every table event happens at a table created before, in the same round
+    let mut tables = std::collections::HashMap::new();
+    let mut duels = 0;
+    for record in &records {
+        match &record.event {
+            GameEvent::TableCreated { table, players } => {
+                assert!(players.iter().all(|x| joined.iter().any(|y| y.0 == *x)));
+                tables.insert(*table, (record.round, *players));
+            }
+            event => {
+                if let Some(table) = event.table() {
+                    assert_eq!(tables[&table].0, record.round);
+                }
+            }
+        }
+        if let GameEvent::DuelFinished {
+            players, winner, ..
+        } = &record.event
+        {
+            assert!(winner.is_none_or(|x| players.contains(&x)));
+            duels += 1;
+        }
+    }
+    assert!(duels > 0);
+
+    // the last inventories recorded for each player are the final ones, unless the player buys stars at the end
+    for (entity, name) in &joined {
+        let last = records.iter().rev().find_map(|x| match &x.event {
+            GameEvent::DuelFinished {
+                players,
+                inventories,
+                ..
+            } => players
+                .iter()
+                .position(|x| x == entity)
+                .map(|index| inventories[index].clone()),
+            _ => None,
+        });
+        let purchase = records.iter().any(|x| match &x.event {
+            GameEvent::StarPurchased { buyer, seller, .. } => buyer == entity || seller == entity,
+            _ => false,
+        });
+        let (_, inventory) = players.iter().find(|(x, _)| x == name).unwrap();
+        if let (Some(last), false) = (last, purchase) {
+            assert_eq!(last.star, inventory.star);
+            assert_eq!(last.cards, inventory.cards);
+        }
+    }
+}
+
+#[test]
+fn ratings_favor_winners() {
+    let player = |label: &str, coin, safe| PlayerOutcome {
+        name: label.into(),
+        label: label.into(),
+        inventory: Inventory {
+            coin,
+            ..Default::default()
+        },
+        safe,
+        rank: 0,
+    };
+    let games = (0..20)
+        .map(|index| {
+            let mut players = vec![
+                player("strong", 20, true),
+                player("fair", 10, true),
+                player("weak", 0, false),
+            ];
+            rank_players(&mut players);
+            GameOutcome {
+                index,
+                seed: None,
+                output: Default::default(),
+                duration: Default::default(),
+                players,
+                error: None,
+            }
+        })
+        .collect_vec();
+    assert_eq!(
+        games[0].players.iter().map(|x| x.rank).collect_vec(),
+        [1, 2, 3]
+    );
+
+    let summary = Summary::new(games);
+    let labels = summary
+        .standings
+        .iter()
+        .map(|x| x.label.as_str())
+        .collect_vec();
+    assert_eq!(labels, ["strong", "fair", "weak"]);
+
+    let [strong, fair, weak] = [0, 1, 2].map(|index| &summary.standings[index]);
+    assert!(strong.elo.0 > fair.elo.0 && fair.elo.0 > weak.elo.0);
+    assert!(strong.trueskill.mu > fair.trueskill.mu && fair.trueskill.mu > weak.trueskill.mu);
+    assert!(strong.trueskill.sigma < 25.0 / 3.0);
+    let elo: f64 = summary.standings.iter().map(|x| x.elo.0).sum();
+    assert!((elo - 3.0 * 1500.0).abs() < 1e-6);
+
+    assert_eq!(strong.survival_rate, 1.0);
+    assert_eq!(weak.survival_rate, 0.0);
+    assert_eq!(strong.mean_coins, 20.0);
+    assert_eq!(fair.ranks[&2], 20);
+}
+
+#[test]
+fn tournament_plays_games_in_parallel() {
+    let output = TempDir::new();
+
+    let rules = Rules::default();
+    let settings = Settings {
+        output: output.to_path_buf(),
+        num_players: 4,
+        max_rounds: 4,
+        seed: Some(3),
+        cassette: Some(output.join("tape.jsonl")),
+        ..Default::default()
+    };
+    let args = TournamentArgs {
+        games: 4,
+        jobs: 2,
+        mix: vec!["random,nash".into(), "counter,nash".into()],
+        models: vec![],
+    };
+    let summary = play_tournament(&args, &settings, &rules).unwrap();
+    // each game records its own cassette
+    assert!((0..4).all(|index| output.join(format!("tape-game-{index:03}.jsonl")).exists()));
+
+    assert_eq!(
+        summary.games.iter().map(|x| x.index).collect_vec(),
+        [0, 1, 2, 3]
+    );
+    assert!(summary.games.iter().all(|x| x.error.is_none()));
+    assert_eq!(summary.games[0].seed, Some(3));
+    assert_eq!(summary.games[3].seed, Some(6));
+
+    let players = |label: &str| {
+        summary
+            .standings
+            .iter()
+            .find(|x| x.label == label)
+            .unwrap()
+            .players
+    };
+    assert_eq!(players("nash"), 8);
+    assert_eq!(players("random"), 4);
+    assert_eq!(players("counter"), 4);
+    assert!(summary
+        .standings
+        .iter()
+        .all(|x| (0.0..=1.0).contains(&x.survival_rate)));
+
+    // the same seeds replay the same tournament, however games are scheduled
+    let args = TournamentArgs { jobs: 1, ..args };
+    let again = play_tournament(&args, &settings, &rules).unwrap();
+    for (x, y) in summary.games.iter().zip(&again.games) {
+        let coins =
+            |game: &GameOutcome| game.players.iter().map(|x| x.inventory.coin).collect_vec();
+        assert_eq!(coins(x), coins(y));
+    }
+}
+
+#[test]
+fn stated_offers_and_gini() {
+    let cards = [ROCK, PAPER, Card::new("Scissors")];
+    let offer = stated_offer("Deal! I'll give you 2 coins and a rock.", &cards).unwrap();
+    assert_eq!((offer.star, offer.coin, offer.count(&ROCK)), (0, 2, 1));
+    let offer = stated_offer("You give me one star, I will offer three Scissors", &cards).unwrap();
+    assert_eq!((offer.star, offer.count(&cards[2])), (0, 3));
+    assert!(stated_offer("Give me 2 coins.", &cards).is_none());
+    assert!(stated_offer("I think I would like to trade.", &cards).is_none());
+
+    assert_eq!(gini(&[5, 5, 5, 5]), 0.0);
+    assert_eq!(gini(&[0, 0, 0, 0]), 0.0);
+    assert!((gini(&[0, 0, 0, 12]) - 0.75).abs() < 1e-9);
+}
+
+#[test]
+fn analyze_llm_game() {
+    // everyone promises a coin, but never offers anything
+    let server = MockLlm::new()
+        .complete(|request| match request.bnf_schema.is_empty() {
+            true => "I will give you 1 coin.".into(),
+            false => MockLlm::default_complete(request),
+        })
+        .serve();
+    let output = TempDir::new();
+
+    let rules = Rules::default();
+    let settings = Settings {
+        url: server.url.clone(),
+        output: output.to_path_buf(),
+        num_players: 4,
+        max_rounds: 2,
+        seed: Some(5),
+        actors: vec![ActorKind::Llm, ActorKind::Nash],
+        ..Default::default()
+    };
+    let (exit, _) = run_game(&settings, &rules);
+    assert_eq!(exit, AppExit::Success);
+
+    let dirs = find_games(&output).unwrap();
+    assert_eq!(dirs.len(), 1);
+    let stats = analyze_game(&dirs[0]).unwrap();
+    let analysis = Analysis::new(vec![stats.clone()]);
+    let out = output.join("analysis");
+    analysis.write(&out).unwrap();
+    let games_csv = std::fs::read_to_string(out.join("games.csv")).unwrap();
+    let cards_csv = std::fs::read_to_string(out.join("cards.csv")).unwrap();
+
+    assert_eq!(stats.players, 4);
+    assert_eq!(stats.rounds, 2);
+    assert_eq!(stats.tables, 4);
+    assert!(stats.stated_intentions > 0);
+    assert_eq!(stats.kept_intentions, 0);
+    assert!((0.0..=1.0).contains(&stats.acceptance_rate));
+
+    // everyone holds all their cards at the start
+    let public: usize = stats.cards[0].public.values().sum();
+    assert_eq!(public, 4 * rules.inventory.num_cards());
+    let played: usize = stats.cards.iter().flat_map(|x| x.played.values()).sum();
+    assert_eq!(played, 2 * stats.duels);
+    assert_eq!(stats.survival.len(), 2);
+    assert!(stats.survival.iter().all(|x| x.alive <= 4));
+
+    assert_eq!(games_csv.lines().count(), 2);
+    assert!(cards_csv.lines().skip(1).all(|x| x.split(',').count() == 7));
+    assert_eq!(analysis.overall.survival_curve.len(), 2);
+}
+
+#[test]
+fn dashboard_feeds_game_events() {
+    let output = TempDir::new();
+    let settings = Settings {
+        output: output.to_path_buf(),
+        num_players: 4,
+        max_rounds: 3,
+        seed: Some(2),
+        actors: vec![ActorKind::Random],
+        ..Default::default()
+    };
+
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, GamePlugin, DashboardFeedPlugin))
+        .insert_resource(settings)
+        .insert_resource(Rules::default());
+    app.finish();
+    app.cleanup();
+    while app.should_exit().is_none() {
+        app.update();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    let feed = app.world().resource::<DashboardFeed>();
+    assert!(feed
+        .iter()
+        .any(|x| x.contains("beats") || x.contains("tie with")));
+    assert_eq!(feed.back().map(String::as_str), Some("game over"));
+}
+
+#[test]
+fn tui_shows_game() {
+    let output = TempDir::new();
+    let settings = Settings {
+        output: output.to_path_buf(),
+        num_players: 4,
+        max_rounds: 3,
+        seed: Some(3),
+        actors: vec![ActorKind::Random],
+        ..Default::default()
+    };
+
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, GamePlugin, TuiPlugin))
+        .insert_resource(settings)
+        .insert_resource(Rules::default());
+    app.finish();
+    app.cleanup();
+    while app.should_exit().is_none() {
+        app.update();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    let view = app.world().resource::<TuiView>();
+    assert_eq!(view.players.len(), 4);
+    assert_eq!(view.tally().iter().sum::<usize>(), 4);
+
+    let mut terminal = ratatui::Terminal::new(ratatui::backend::TestBackend::new(120, 40)).unwrap();
+    terminal.draw(|frame| draw(frame, view)).unwrap();
+    let screen = terminal
+        .backend()
+        .buffer()
+        .content()
+        .chunks(120)
+        .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+        .join("\n");
+    assert!(screen.contains(&view.players[0].name));
+    assert!(screen.contains("game over"));
+    assert!(screen.contains(&format!("Round {}", view.round)));
+}
+
+#[test]
+fn llm_bets_chosen_stake() {
+    // alice stakes 2 stars and 3 coins; bob stakes the minimum
+    let server = MockLlm::new()
+        .choose(
+            |request| match (speaker(&request.prompt), stake_item(&request.prompt)) {
+                ("Alice", Some("stars")) => position(request, "2"),
+                ("Alice", Some("coins")) => position(request, "3"),
+                _ => alice_and_bob(request),
+            },
+        )
+        .serve();
+    let rules = Rules::default();
+
+    let alice = llm_actor(&server, 0);
+    let bob = llm_actor(&server, 1);
+    let histories = [alice.history.clone(), bob.history.clone()];
+    let ([alice, bob], report) = duel_alice_bob(alice, bob, &rules).unwrap();
+    let [s0, s1] = report.stakes.unwrap();
+    assert_eq!((s0.star, s0.coin), (2, 3));
+    assert_eq!((s1.star, s1.coin), (1, 0));
+
+    // paper beats rock: alice takes bob's stake, and bob gets nothing of alice's
+    assert_eq!(alice.star, 4);
+    assert_eq!(bob.star, 2);
+
+    // bob sees alice's stake before drawing his card
+    let bob_history = block_on(histories[1].lock()).clone();
+    assert!(bob_history.iter().any(|record| match record {
+        LlmRecord::Choose { request, .. } =>
+            request.prompt.contains("Alice stakes 2 stars and 3 coins"),
+        _ => false,
+    }));
+
+    // a refused stake is explained, and only the stake is asked again
+    let mut actor = llm_actor(&server, 2);
+    let player = player_data(0, "Alice", &rules);
+    let opponent = player_data(1, "Bob", &rules).into();
+    let stake = block_on(async {
+        actor
+            .notify(&player, &rules, &PublicState::default())
+            .await
+            .unwrap();
+        actor.bet(&player, &opponent, &[]).await.unwrap();
+        let count = actor.history.lock().await.len();
+        actor
+            .feedback_error(&player, "Error: you cannot stake that".into())
+            .await
+            .unwrap();
+        let stake = actor.bet(&player, &opponent, &[]).await.unwrap();
+        let history = actor.history.lock().await;
+        assert!(history[count..].iter().all(|record| match record {
+            LlmRecord::Choose { request, .. } =>
+                request.prompt.contains("Error: you cannot stake that"),
+            LlmRecord::Completion { .. } => false,
+        }));
+        stake
+    });
+    assert_eq!((stake.star, stake.coin), (2, 3));
+}
+
+#[test]
+fn llm_chats_before_duel() {
+    // bob bluffs about his card once the trade is over
+    let server = MockLlm::new()
+        .complete(|request| match speaker(&request.prompt) {
+            "Bob"
+                if request.bnf_schema.is_empty() && request.prompt.contains("the duel is next") =>
+            {
+                "I will draw scissors, trust me.".into()
+            }
+            _ => MockLlm::default_complete(request),
+        })
+        .choose(alice_and_bob)
+        .serve();
+    let rules = Rules::default();
+
+    let alice = llm_actor(&server, 0);
+    let bob = llm_actor(&server, 1);
+    let history = alice.history.clone();
+    let actors: [Arc<Mutex<dyn Actor>>; 2] =
+        [Arc::new(Mutex::new(alice)), Arc::new(Mutex::new(bob))];
+    let data = [
+        player_data(0, "Alice", &rules),
+        player_data(1, "Bob", &rules),
+    ];
+    let progress = DuelProgress::new(Entity::from_raw(2), data.clone().map(|x| x.entity));
+    let (_, report) = block_on(duel_with_progress(
+        rules.clone(),
+        PublicState::default(),
+        actors,
+        data,
+        progress.clone(),
+    ))
+    .unwrap();
+    assert_eq!(report.duel_chat.len(), 2 * rules.num_chat_rounds);
+    assert!(report
+        .duel_chat
+        .iter()
+        .filter(|line| line.player == 1)
+        .all(|line| line.content == "I will draw scissors, trust me."));
+    assert!(report
+        .chat
+        .iter()
+        .all(|line| !line.content.contains("scissors")));
+
+    // alice hears all of it, including the last word, before drawing her card
+    let history = block_on(history.lock()).clone();
+    let Some(LlmRecord::Choose { request, .. }) = history
+        .iter()
+        .rev()
+        .find(|record| matches!(record, LlmRecord::Choose { .. }))
+    else {
+        panic!("Alice never chooses");
+    };
+    assert_eq!(
+        request.prompt.matches("Bob: I will draw scissors").count(),
+        rules.num_chat_rounds
+    );
+    assert!(request.prompt.contains("Bob stakes 1 stars and 0 coins"));
+
+    // the duel chat is sent as events
+    let events = progress.drain();
+    let duel_chat = events
+        .iter()
+        .filter(|event| matches!(event, GameEvent::ChatSent { duel: true, .. }))
+        .count();
+    assert_eq!(duel_chat, report.duel_chat.len());
+}
+
+#[test]
+fn trades_are_countered() {
+    let play = |rules: &Rules| {
+        let seller = SellerActor::new(fastrand::Rng::with_seed(0));
+        let buyer = DummyActor::new(fastrand::Rng::with_seed(1));
+        let actors: [Arc<Mutex<dyn Actor>>; 2] =
+            [Arc::new(Mutex::new(seller)), Arc::new(Mutex::new(buyer))];
+        let mut data = [player_data(0, "Alice", rules), player_data(1, "Bob", rules)];
+        data[0].inventory.star = 5;
+        let progress = DuelProgress::new(Entity::from_raw(2), data.clone().map(|x| x.entity));
+        let (inventories, report) = block_on(duel_with_progress(
+            rules.clone(),
+            PublicState::default(),
+            actors,
+            data,
+            progress.clone(),
+        ))
+        .unwrap();
+        (inventories, report, progress.drain())
+    };
+
+    // the seller asks 3 coins for each of the 2 stars above the safe threshold, and the buyer signs
+    let rules = Rules::default();
+    let ([alice, bob], report, events) = play(&rules);
+    assert_eq!(report.counters.len(), 1);
+    let counter = &report.counters[0];
+    assert_eq!(counter.player, 0);
+    assert_eq!(counter.trades[0].star, 2);
+    assert_eq!(counter.trades[1].coin, 6);
+    assert_eq!(counter.trades[1].cards, report.trades[1].cards);
+    assert_eq!(report.accepted, [true, true]);
+    assert_eq!((alice.coin, bob.coin), (16, 4));
+
+    let events = events
+        .into_iter()
+        .filter(|event| !matches!(event, GameEvent::ChatSent { .. }))
+        .collect_vec();
+    assert!(matches!(
+        &events[..2],
+        [
+            GameEvent::TradeProposed { .. },
+            GameEvent::TradeCountered { .. }
+        ]
+    ));
+
+    // without counter-offers, the seller refuses to sell for nothing
+    let rules = Rules {
+        max_counter_offers: 0,
+        ..Default::default()
+    };
+    let ([alice, bob], report, _) = play(&rules);
+    assert!(report.counters.is_empty());
+    assert_eq!(report.accepted, [false, true]);
+    assert_eq!((alice.coin, bob.coin), (10, 10));
+}
+
+#[test]
+fn llm_counters_trade() {
+    // alice counters the contract, then bob signs the amended one
+    let server = MockLlm::new()
+        .complete(|request| match speaker(&request.prompt) {
+            "Alice" if request.bnf_schema.contains("Counter") => "Counter\".".into(),
+            _ => MockLlm::default_complete(request),
+        })
+        .choose(
+            |request| match (speaker(&request.prompt), trade_item(&request.prompt)) {
+                ("Alice", Some("stars")) if request.prompt.contains("Counter") => {
+                    match request.prompt.ends_with("would like to offer Bob") {
+                        true => position(request, "1"),
+                        false => position(request, "I would like to"),
+                    }
+                }
+                _ => alice_and_bob(request),
+            },
+        )
+        .serve();
+    let rules = Rules::default();
+
+    let alice = llm_actor(&server, 0);
+    let bob = llm_actor(&server, 1);
+    let history = bob.history.clone();
+    let (_, report) = duel_alice_bob(alice, bob, &rules).unwrap();
+    assert_eq!(report.trades[0].coin, 2);
+    assert_eq!(report.counters.len(), 1);
+    let [t0, t1] = &report.counters[0].trades;
+    assert_eq!((t0.star, t0.coin), (1, 2));
+    assert_eq!(t1.star + t1.coin + t1.cards.values().sum::<usize>(), 0);
+    assert_eq!(report.accepted, [true, true]);
+
+    // bob reviews the amended contract
+    let history = block_on(history.lock()).clone();
+    let contracts = history
+        .iter()
+        .filter(|record| match record {
+            LlmRecord::Completion { request, .. } => request.bnf_schema.contains("Yes"),
+            _ => false,
+        })
+        .count();
+    assert_eq!(contracts, 2);
+}
+
+#[test]
+fn llm_trades_in_one_json_completion() {
+    let server = MockLlm::new()
+        .complete(
+            |request| match request.bnf_schema.contains("__json_0_0_json") {
+                true => r#"{"star": 0, "coin": 2, "rock": 0, "paper": 1, "scissors": 0}"#.into(),
+                false => MockLlm::default_complete(request),
+            },
+        )
+        .serve();
+    let rules = Rules::default();
+    let mut alice = player_data(0, "Alice", &rules);
+    alice.inventory.coin = 3;
+    let bob = player_data(1, "Bob", &rules);
+
+    let mut actor = LlmActor {
+        trade_mode: TradeMode::Json,
+        ..llm_actor(&server, 0)
+    };
+    let trade = block_on(async {
+        actor
+            .notify(&alice, &rules, &PublicState::default())
+            .await
+            .unwrap();
+        actor.trade(&alice, &bob.into(), &[]).await.unwrap()
+    });
+    assert_eq!((trade.star, trade.coin), (0, 2));
+    assert_eq!(trade.count(&PAPER), 1);
+    assert_eq!(trade.count(&ROCK), 0);
+
+    // the summary and the offer, without any choices
+    let requests = server.requests();
+    let offers = requests
+        .iter()
+        .filter_map(|request| match request {
+            MockRequest::Completion(request) if request.bnf_schema.contains("__json_0_0_json") => {
+                Some(request)
+            }
+            MockRequest::Completion(_) => None,
+            MockRequest::Choose(_) => panic!("json trades make no choices"),
+        })
+        .collect_vec();
+    assert_eq!(offers.len(), 1);
+    // coins are bounded by the inventory, keys by the cards in the rules
+    let grammar = &offers[0].bnf_schema;
+    assert!(grammar.contains(r#"::= "0" | "1" | "2";"#));
+    assert!(grammar.contains(r#"'"scissors"'"#));
+    assert!(grammar.ends_with("start ::= __json_0_0_json '\\n';"));
+}
+
+#[test]
+fn llm_remembers_past_opponents() {
+    let server = MockLlm::new()
+        .complete(bob_tells_plan)
+        .choose(alice_and_bob)
+        .serve();
+    let rules = Rules::default();
+
+    let alice = LlmActor {
+        memory: Memory::new(MemoryPolicy::Full),
+        ..llm_actor(&server, 0)
+    };
+    let bob = LlmActor {
+        memory: Memory::new(MemoryPolicy::Summarized),
+        ..llm_actor(&server, 1)
+    };
+    let actors: [Arc<Mutex<dyn Actor>>; 2] =
+        [Arc::new(Mutex::new(alice)), Arc::new(Mutex::new(bob))];
+    let mut data = [
+        player_data(0, "Alice", &rules),
+        player_data(1, "Bob", &rules),
+    ];
+
+    let prompts = |skip: usize, name: &str| {
+        server.requests()[skip..]
+            .iter()
+            .map(|request| match request {
+                MockRequest::Completion(request) => request.prompt.clone(),
+                MockRequest::Choose(request) => request.prompt.clone(),
+            })
+            .filter(|prompt| speaker(prompt) == name)
+            .collect_vec()
+    };
+
+    // nothing to remember at the first table
+    let (inventories, _) = block_on(duel(
+        rules.clone(),
+        PublicState::default(),
+        actors.clone(),
+        data.clone(),
+    ))
+    .unwrap();
+    assert!(server
+        .requests()
+        .iter()
+        .all(|request| !format!("{request:?}").contains("in previous rounds")));
+
+    let skip = server.requests().len();
+    for (data, inventory) in data.iter_mut().zip(inventories) {
+        data.inventory = inventory;
+    }
+    block_on(duel(rules.clone(), PublicState::default(), actors, data)).unwrap();
+
+    // alice remembers the table, and bob what alice did in total
+    let table = "- Table 1: Bob offered nothing and signed the contract, \
+                 and the trade went through; Bob drew \"Rock\" against your \"Paper\", and you won.";
+    let summary = "- Alice, met once: offered 2 coins in total, signed 1 of 1 contracts, \
+                   and drew \"Paper\"; you won 0, lost 1 and tied 0.";
+    let alice = prompts(skip, "Alice");
+    let bob = prompts(skip, "Bob");
+    assert!(!alice.is_empty());
+    assert!(alice.iter().all(|prompt| prompt.contains(table)));
+    assert!(bob.iter().all(|prompt| prompt.contains(summary)));
+    assert!(alice
+        .iter()
+        .any(|prompt| prompt.contains("you have met Bob before:")));
+}
+
+#[test]
+fn llm_summarizes_long_story() {
+    let complete = |request: &CompletionRequest| {
+        if request.prompt.contains("Summarize the story above") {
+            return "Alice offered Bob 2 coins.".into();
+        }
+        match speaker(&request.prompt) {
+            // number each line of Bob, so that repeated ones can be told apart
+            "Bob" if request.bnf_schema.is_empty() => {
+                let count = request.prompt.matches("I am going to draw rock").count();
+                format!("I am going to draw rock, plan #{count}.")
+            }
+            _ => MockLlm::default_complete(request),
+        }
+    };
+    let play = |context_budget: usize| {
+        let server = MockLlm::new()
+            .complete(complete)
+            .choose(alice_and_bob)
+            .serve();
+        let rules = Rules::default();
+        let alice = LlmActor {
+            context_budget,
+            ..llm_actor(&server, 0)
+        };
+        let history = alice.history.clone();
+        let (_, report) = duel_alice_bob(alice, llm_actor(&server, 1), &rules).unwrap();
+        let history = block_on(history.lock()).clone();
+        (report, history)
+    };
+
+    let (report, history) = play(0);
+    assert!(matches!(report.result, Some(DuelResult::Win(..))));
+    let prompts = |history: &[LlmRecord]| {
+        history
+            .iter()
+            .map(|record| match record {
+                LlmRecord::Completion { request, .. } => request.prompt.clone(),
+                LlmRecord::Choose { request, .. } => request.prompt.clone(),
+            })
+            .collect_vec()
+    };
+    let unbounded = prompts(&history);
+
+    // the same duel, with older records summarized as the story grows
+    let (report, history) = play(900);
+    assert!(matches!(report.result, Some(DuelResult::Win(..))));
+    let summaries = history
+        .iter()
+        .filter(|record| {
+            matches!(
+                record,
+                LlmRecord::Completion {
+                    role: Role::Think(_),
+                    ..
+                }
+            )
+        })
+        .count();
+    assert!(summaries > 0);
+
+    let bounded = prompts(&history);
+    let summary = "*What happened earlier, in short: Alice offered Bob 2 coins.*";
+    assert!(bounded.last().unwrap().contains(summary));
+    assert!(bounded.last().unwrap().len() < unbounded.last().unwrap().len());
+    // public records are not added again once summarized, so no line of Bob appears twice
+    for prompt in bounded.iter().filter(|prompt| prompt.contains(summary)) {
+        let (_, story) = prompt.rsplit_once(summary).unwrap();
+        let plans = story
+            .lines()
+            .filter_map(|line| line.split_once("plan #"))
+            .map(|(_, plan)| plan.trim_end_matches('.').parse::<usize>().unwrap())
+            .collect_vec();
+        assert!(plans.iter().tuple_windows().all(|(x, y)| x < y));
+    }
+    // the rules are never summarized
+    assert!(bounded
+        .iter()
+        .filter(|prompt| !prompt.contains("Summarize the story above"))
+        .all(|prompt| prompt.contains("beats")));
+}
+
+#[test]
+fn dossiers_follow_game_events() {
+    let output = TempDir::new();
+    let settings = Settings {
+        output: output.to_path_buf(),
+        num_players: 4,
+        max_rounds: 3,
+        seed: Some(4),
+        actors: vec![ActorKind::Random],
+        ..Default::default()
+    };
+
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, GamePlugin))
+        .insert_resource(settings)
+        .insert_resource(Rules::default());
+    app.finish();
+    app.cleanup();
+    while app.should_exit().is_none() {
+        app.update();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    // the dossiers add up to the duels in the event log
+    let events = read_game_events(find_games(&output).unwrap()[0].join(GAME_EVENTS_FILE)).unwrap();
+    let duels = events
+        .iter()
+        .filter(|x| matches!(x.event, GameEvent::DuelFinished { .. }))
+        .count();
+    let dossiers = app
+        .world_mut()
+        .query::<&Dossier>()
+        .iter(app.world())
+        .cloned()
+        .collect_vec();
+    assert!(duels > 0);
+    assert_eq!(dossiers.iter().map(|x| x.duels).sum::<usize>(), 2 * duels);
+    assert_eq!(
+        dossiers.iter().map(|x| x.wins).sum::<usize>(),
+        dossiers.iter().map(|x| x.losses).sum::<usize>()
+    );
+    for dossier in &dossiers {
+        assert_eq!(dossier.wins + dossier.losses + dossier.ties, dossier.duels);
+        assert_eq!(dossier.signed + dossier.refused, dossier.stakes);
+    }
+
+    // a card drawn against a refused duel is not revealed
+    let (player, before) = app
+        .world_mut()
+        .query_filtered::<(Entity, &Dossier), With<Player>>()
+        .iter(app.world())
+        .map(|(entity, dossier)| (entity, dossier.clone()))
+        .next()
+        .unwrap();
+    app.world_mut().send_event(GameEvent::CardsDrawn {
+        table: Entity::PLACEHOLDER,
+        players: [player, Entity::PLACEHOLDER],
+        cards: [Some(ROCK), None],
+    });
+    app.update();
+    let after = app.world().get::<Dossier>(player).unwrap();
+    assert_eq!(after.cards, before.cards);
+
+    // bob has revealed all his rocks and papers, so he must be holding scissors
+    let rules = Rules::default();
+    let alice = player_data(0, "Alice", &rules);
+    let mut bob = player_data(1, "Bob", &rules);
+    bob.dossier = Dossier {
+        duels: 8,
+        losses: 8,
+        cards: [(ROCK, 4), (PAPER, 4)].into(),
+        ..default()
+    };
+    let bob: OpponentData = bob.into();
+    let mut actor = CounterActor::new(fastrand::Rng::with_seed(0));
+    let stake = Default::default();
+    let state = StakeState {
+        this: &stake,
+        that: &stake,
+    };
+    let card = block_on(async {
+        actor
+            .notify(&alice, &rules, &PublicState::default())
+            .await
+            .unwrap();
+        actor.accept_duel(&alice, &bob, &[], state).await.unwrap()
+    });
+    assert_eq!(card, Some(ROCK));
+
+    let text = bob.dossier.describe("Bob", &rules).unwrap();
+    assert!(text.contains("played 8 duels"));
+    assert!(text.contains("4 rock cards"));
+}
+
+#[test]
+fn llm_skips_items_it_does_not_hold() {
+    // alice would like to offer everything, but has neither coins nor paper
+    let server = MockLlm::new()
+        .choose(|request| match trade_item(&request.prompt) {
+            Some(_) if !request.prompt.ends_with("would like to offer Bob") => {
+                position(request, "I would like to")
+            }
+            _ => request.choices.len().saturating_sub(1),
+        })
+        .serve();
+    let rules = Rules::default();
+    let mut alice = player_data(0, "Alice", &rules);
+    alice.inventory.coin = 0;
+    alice.inventory.cards.insert(PAPER, 0);
+    let bob = player_data(1, "Bob", &rules);
+
+    let mut actor = LlmActor {
+        retry: impatient(),
+        ..llm_actor(&server, 0)
+    };
+    let trade = block_on(async {
+        actor
+            .notify(&alice, &rules, &PublicState::default())
+            .await
+            .unwrap();
+        actor.trade(&alice, &bob.into(), &[]).await.unwrap()
+    });
+    assert_eq!((trade.star, trade.coin), (2, 0));
+    assert_eq!(trade.count(&PAPER), 0);
+    assert_eq!(trade.count(&ROCK), 3);
+
+    // no choices are asked about coins or paper
+    let items = server
+        .requests()
+        .iter()
+        .filter_map(|request| match request {
+            MockRequest::Choose(request) => trade_item(&request.prompt).map(str::to_owned),
+            MockRequest::Completion(_) => None,
+        })
+        .collect_vec();
+    assert!(items.iter().any(|item| item == "stars"));
+    assert!(!items
+        .iter()
+        .any(|item| item == "coins" || item == "paper cards"));
+}
+
+#[test]
+fn llm_counters_trade_in_json_without_coins() {
+    let server = MockLlm::new()
+        .complete(|request| match speaker(&request.prompt) {
+            "Alice" if request.bnf_schema.contains("__json_0_0_json") => {
+                r#"{"star": 1, "coin": 0, "rock": 0, "paper": 0, "scissors": 0}"#.into()
+            }
+            "Alice" if request.bnf_schema.contains("Counter") => "Counter\".".into(),
+            _ => MockLlm::default_complete(request),
+        })
+        .serve();
+    let rules = Rules::default();
+
+    let alice = LlmActor {
+        trade_mode: TradeMode::Json,
+        retry: impatient(),
+        ..llm_actor(&server, 0)
+    };
+    let mut data = [
+        player_data(0, "Alice", &rules),
+        player_data(1, "Bob", &rules),
+    ];
+    data[0].inventory.coin = 0;
+    let actors: [Arc<Mutex<dyn Actor>>; 2] = [
+        Arc::new(Mutex::new(alice)),
+        Arc::new(Mutex::new(llm_actor(&server, 1))),
+    ];
+
+    let (_, report) = block_on(duel(rules.clone(), PublicState::default(), actors, data)).unwrap();
+    assert_eq!(report.counters.len(), 1);
+    assert_eq!(report.counters[0].trades[0].star, 1);
+    assert_eq!(report.accepted, [true, true]);
+
+    // the counter is filled in as json as well, with no coins to give
+    let offers = server
+        .requests()
+        .into_iter()
+        .filter_map(|request| match request {
+            MockRequest::Completion(request) if request.bnf_schema.contains("__json_0_0_json") => {
+                Some(request.bnf_schema)
+            }
+            _ => None,
+        })
+        .collect_vec();
+    assert_eq!(offers.len(), 2);
+    assert!(offers[1].contains(r#"__json_0_0_json_1 ::= "0";"#));
+}
+
+#[test]
+fn logprobs_do_not_rank_shared_prefixes() {
+    let token = |token: &str, logprob: f32| TokenLogprob {
+        token: token.into(),
+        logprob,
+        ..Default::default()
+    };
+    let choices = [
+        " I think I wouldn't like to",
+        " I would like to",
+        " Hmm... I would like to",
+    ]
+    .map(String::from);
+
+    // "I" starts both of the first two choices, so the model has to be asked instead
+    let tokens = [token("I", -0.1), token("Hmm", -2.0)];
+    assert!(OpenAiBackend::rank_by_logprobs(&choices, &tokens).is_none());
+    let numbers = [" 1", " 10", " 11"].map(String::from);
+    assert!(OpenAiBackend::rank_by_logprobs(&numbers, &[token("1", -0.1)]).is_none());
+
+    // a unique best choice is ranked first
+    let tokens = [token("Hmm", -0.1), token("I", -2.0)];
+    let response = OpenAiBackend::rank_by_logprobs(&choices, &tokens).unwrap();
+    assert_eq!(response.data[0].index, 2);
+    assert!(OpenAiBackend::rank_by_logprobs(&choices, &[token("No", -0.1)]).is_none());
+}
+
+#[test]
+fn llm_asks_again_for_malformed_json_offer() {
+    // the first answer is no json at all, the second has extra and negative fields
+    let answers = std::sync::atomic::AtomicUsize::new(0);
+    let server = MockLlm::new()
+        .complete(move |request| {
+            if !request.bnf_schema.contains("__json_0_0_json") {
+                return MockLlm::default_complete(request);
+            }
+            match answers.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                0 => "I would give Bob 2 coins.".into(),
+                _ => r#"{"star": -1, "coin": 2, "paper": "1", "mood": "happy"}"#.into(),
+            }
+        })
+        .serve();
+    let rules = Rules::default();
+    let alice = player_data(0, "Alice", &rules);
+    let bob = player_data(1, "Bob", &rules);
+
+    let mut actor = LlmActor {
+        trade_mode: TradeMode::Json,
+        retry: impatient(),
+        ..llm_actor(&server, 0)
+    };
+    let trade = block_on(async {
+        actor
+            .notify(&alice, &rules, &PublicState::default())
+            .await
+            .unwrap();
+        actor.trade(&alice, &bob.into(), &[]).await.unwrap()
+    });
+    assert_eq!((trade.star, trade.coin), (0, 2));
+    assert_eq!(trade.count(&PAPER), 1);
+    assert_eq!(trade.count(&ROCK), 0);
+}
+
+#[test]
+fn rules_load_from_toml_and_json() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("rules.toml");
+    let rules = Rules::load(path).unwrap();
+    let default = Rules::default();
+    assert_eq!(rules.safe_stars, default.safe_stars);
+    assert_eq!(rules.min_stake, default.min_stake);
+    assert_eq!(rules.inventory.count(&ROCK), 4);
+    assert!(rules.beats(&PAPER, &ROCK));
+
+    let output = TempDir::new();
+    std::fs::create_dir_all(&output).unwrap();
+    let load = |name: &str, text: &str| {
+        let path = output.join(name);
+        std::fs::write(&path, text).unwrap();
+        Rules::load(path)
+    };
+
+    let rules = load(
+        "rules.json",
+        r#"{"min_stake": 2, "inventory": {"star": 5, "cards": {"Rock": 1, "Paper": 2}}}"#,
+    )
+    .unwrap();
+    assert_eq!(rules.min_stake, 2);
+    assert_eq!(rules.inventory.star, 5);
+    assert_eq!(rules.inventory.num_cards(), 3);
+    assert_eq!(rules.num_chat_rounds, default.num_chat_rounds);
+
+    // rules that cannot be played are rejected
+    assert!(load("stake.toml", "min_stake = 4").is_err());
+    assert!(load("safe.toml", "safe_stars = 0").is_err());
+    assert!(load("card.json", r#"{"inventory": {"cards": {"Lizard": 4}}}"#).is_err());
+    assert!(load("empty.json", r#"{"cards": []}"#).is_err());
+    assert!(load("invalid.json", "min_stake = 1").is_err());
+}
+
+#[test]
+fn stakes_are_capped_by_stars() {
+    // alice holds less than the minimum stake, and stakes all she has
+    let rules = Rules {
+        min_stake: 2,
+        ..Default::default()
+    };
+    let alice = DummyActor::new(fastrand::Rng::with_seed(0));
+    let bob = DummyActor::new(fastrand::Rng::with_seed(1));
+    let actors: [Arc<Mutex<dyn Actor>>; 2] =
+        [Arc::new(Mutex::new(alice)), Arc::new(Mutex::new(bob))];
+    let mut data = [
+        player_data(0, "Alice", &rules),
+        player_data(1, "Bob", &rules),
+    ];
+    data[0].inventory.star = 1;
+
+    let (_, report) = block_on(duel(rules, PublicState::default(), actors, data)).unwrap();
+    let [s0, s1] = report.stakes.unwrap();
+    assert_eq!(s0.star, 1);
+    assert_eq!(s1.star, 2);
+}
+
+/// Completes with the mock server, but answers every choice with a card not in the game.
+#[derive(Debug)]
+struct StrayChoices(Ai00Backend);
+
+impl LlmBackend for StrayChoices {
+    fn complete<'a>(
+        &'a self,
+        request: &'a CompletionRequest,
+    ) -> BoxedFuture<'a, anyhow::Result<CompletionResponse>> {
+        self.0.complete(request)
+    }
+
+    fn choose<'a>(
+        &'a self,
+        _request: &'a ChooseRequest,
+    ) -> BoxedFuture<'a, anyhow::Result<ChooseResponse>> {
+        let data = vec![ChooseItem {
+            choice: "Lizard".into(),
+            ..Default::default()
+        }];
+        Box::pin(async move { Ok(ChooseResponse { data }) })
+    }
+}
+
+#[test]
+fn llm_rejects_cards_outside_the_deck() {
+    let server = MockLlm::new().serve();
+    let rules = Rules::default();
+    let alice = player_data(0, "Alice", &rules);
+    let bob = player_data(1, "Bob", &rules).into();
+
+    let mut actor = LlmActor {
+        backend: Arc::new(StrayChoices(Ai00Backend::new(&server.url))),
+        ..llm_actor(&server, 0)
+    };
+    let stake = Default::default();
+    let state = StakeState {
+        this: &stake,
+        that: &stake,
+    };
+    let result = block_on(async {
+        actor
+            .notify(&alice, &rules, &PublicState::default())
+            .await
+            .unwrap();
+        actor.accept_duel(&alice, &bob, &[], state).await
+    });
+    assert!(matches!(
+        result,
+        Err(ActorError::Llm(LlmError::Parse(text))) if text.contains("Lizard")
+    ));
+}
+
+#[test]
+fn constrained_answers_match_whole_choices() {
+    let numbers = [" 1", " 10", " 11"].map(String::from);
+    let chosen = |answer: &str| {
+        OpenAiBackend::rank_by_answer(&numbers, answer).map(|response| response.data[0].index)
+    };
+    assert_eq!(chosen("10"), Some(1));
+    assert_eq!(chosen(" \"10\"."), Some(1));
+    assert_eq!(chosen("I stake 11 coins"), Some(2));
+    assert_eq!(chosen("1"), Some(0));
+    assert_eq!(chosen("none of them"), None);
+
+    // a choice that starts another is not named by it
+    let choices = [" I would like to", " I would like to offer"].map(String::from);
+    let response = OpenAiBackend::rank_by_answer(&choices, "I would like to offer").unwrap();
+    assert_eq!(response.data[0].index, 1);
+
+    // stop sequences beyond what OpenAI accepts are not sent
+    let backend = OpenAiBackend::default();
+    let request = CompletionRequest {
+        stop: ["\n\n", "\n", "Alice:", "Bob:", "System:"]
+            .map(String::from)
+            .into(),
+        ..Default::default()
+    };
+    let chat = backend.chat_request(&request);
+    assert_eq!(chat.stop.len(), OpenAiBackend::MAX_STOP);
+}
+
+/// Takes the events sent by its table so far when asked to bet.
+struct BetWatcher {
+    progress: DuelProgress,
+    events: Vec<GameEvent>,
+    rng: fastrand::Rng,
+}
+
+impl Actor for BetWatcher {
+    fn trade<'a>(
+        &'a mut self,
+        _player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+    ) -> BoxedFuture<'a, Result<Trade, ActorError>> {
+        Box::pin(async move { Ok(Trade::default()) })
+    }
+
+    fn bet<'a>(
+        &'a mut self,
+        _player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+    ) -> BoxedFuture<'a, Result<Stake, ActorError>> {
+        self.events = self.progress.drain();
+        Box::pin(async move { Ok(Stake::default()) })
+    }
+
+    fn accept_duel<'a>(
+        &'a mut self,
+        player: &'a PlayerData,
+        _opponent: &'a OpponentData,
+        _history: &'a [ChatRecord],
+        _state: StakeState<'a>,
+    ) -> BoxedFuture<'a, Result<Option<Card>, ActorError>> {
+        Box::pin(async move { Ok(self.rng.choice(&player.inventory.deck()).cloned()) })
+    }
+}
+
+#[test]
+fn duel_events_are_sent_as_steps_happen() {
+    let rules = Rules::default();
+    let data = [
+        player_data(0, "Alice", &rules),
+        player_data(1, "Bob", &rules),
+    ];
+    let progress = DuelProgress::new(Entity::from_raw(2), data.clone().map(|x| x.entity));
+    let alice = Arc::new(Mutex::new(BetWatcher {
+        progress: progress.clone(),
+        events: vec![],
+        rng: fastrand::Rng::with_seed(0),
+    }));
+    let bob = DummyActor::new(fastrand::Rng::with_seed(1));
+    let actors: [Arc<Mutex<dyn Actor>>; 2] = [alice.clone(), Arc::new(Mutex::new(bob))];
+    block_on(duel_with_progress(
+        rules,
+        PublicState::default(),
+        actors,
+        data,
+        progress.clone(),
+    ))
+    .unwrap();
+
+    // the trade is sent before the stakes are placed, and the duel after
+    let before = block_on(alice.lock()).events.clone();
+    assert!(matches!(
+        before.last(),
+        Some(GameEvent::TradeAccepted { .. })
+    ));
+    let after = progress.drain();
+    assert!(matches!(
+        after.as_slice(),
+        [
+            GameEvent::StakesPlaced { .. },
+            GameEvent::CardsDrawn { .. },
+            GameEvent::DuelFinished { .. }
+        ]
+    ));
+}
+
+#[test]
+fn nash_actor_solves_weighted_duels() {
+    let odds = |rules: &Rules| {
+        NashActor::solve(rules)
+            .into_iter()
+            .map(|(card, p)| (card.to_string(), p))
+            .collect_vec()
+    };
+    let close = |odds: Vec<(String, f64)>, expected: [(Card, f64); 3]| {
+        assert_eq!(odds.len(), expected.len());
+        for ((card, p), (expected_card, q)) in odds.into_iter().zip(expected) {
+            assert_eq!(card, expected_card.to_string());
+            assert!((p - q).abs() < 0.02, "{card}: {p} vs {q}");
+        }
+    };
+
+    // plain rock-paper-scissors is played uniformly
+    let mut rules = Rules::default();
+    let third = 1.0 / 3.0;
+    close(
+        odds(&rules),
+        [(ROCK, third), (PAPER, third), (SCISSORS, third)],
+    );
+
+    // rock winning double makes paper twice as likely, to keep rock in check
+    rules.cards[0].weight = 2;
+    close(odds(&rules), [(ROCK, 0.25), (PAPER, 0.5), (SCISSORS, 0.25)]);
+
+    // the strategy only draws cards the player holds
+    let mut alice = player_data(0, "Alice", &rules);
+    alice.inventory.cards = [(ROCK, 1), (SCISSORS, 1)].into();
+    let bob: OpponentData = player_data(1, "Bob", &rules).into();
+    let mut actor = NashActor::new(fastrand::Rng::with_seed(0));
+    let stake = Default::default();
+    let cards = block_on(async {
+        actor
+            .notify(&alice, &rules, &PublicState::default())
+            .await
+            .unwrap();
+        let mut cards = vec![];
+        for _ in 0..50 {
+            let state = StakeState {
+                this: &stake,
+                that: &stake,
+            };
+            cards.push(actor.accept_duel(&alice, &bob, &[], state).await.unwrap());
+        }
+        cards
+    });
+    assert!(cards
+        .iter()
+        .all(|card| matches!(card, Some(x) if *x != PAPER)));
+    assert!(cards.contains(&Some(ROCK)) && cards.contains(&Some(SCISSORS)));
+}
+
+/// Requests received by [`serve_remote`], with the index of their connections.
+type RemoteRequests = Arc<std::sync::Mutex<Vec<(usize, serde_json::Value)>>>;
+
+/// A remote peer on a local port. Each request line is answered with the result of `respond`,
+/// given the index of the connection, or left unanswered if it returns `None`.
+fn serve_remote(
+    respond: impl Fn(usize, &serde_json::Value) -> Option<serde_json::Value> + Send + Sync + 'static,
+) -> (String, RemoteRequests) {
+    use std::io::{BufRead, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let requests: RemoteRequests = default();
+    let respond = Arc::new(respond);
+
+    let received = requests.clone();
+    std::thread::spawn(move || {
+        for (index, stream) in listener.incoming().flatten().enumerate() {
+            let requests = received.clone();
+            let respond = respond.clone();
+            std::thread::spawn(move || {
+                let mut writer = stream.try_clone().unwrap();
+                for line in std::io::BufReader::new(stream).lines() {
+                    let Ok(line) = line else { break };
+                    let request: serde_json::Value = serde_json::from_str(&line).unwrap();
+                    requests.lock().unwrap().push((index, request.clone()));
+                    if let Some(result) = respond(index, &request) {
+                        let response = serde_json::json!({ "id": request["id"], "result": result });
+                        let _ = writeln!(writer, "{response}");
+                    }
+                }
+            });
+        }
+    });
+    (addr, requests)
+}
+
+#[test]
+fn remote_actor_speaks_json_lines() {
+    let (addr, requests) = serve_remote(|_, request| match request["method"].as_str() {
+        Some("bet") => Some(serde_json::json!({ "star": 2, "coin": 1 })),
+        Some("accept_duel") => Some("rock".into()),
+        _ => Some(serde_json::Value::Null),
+    });
+
+    let rules = Rules::default();
+    let alice = player_data(0, "Alice", &rules);
+    let bob: OpponentData = player_data(1, "Bob", &rules).into();
+    let mut actor = RemoteActor::new(addr, Duration::from_secs(5), fastrand::Rng::with_seed(0));
+    let (stake, card) = block_on(async {
+        Actor::notify(&mut actor, &alice, &rules, &PublicState::default())
+            .await
+            .unwrap();
+        let stake = Actor::bet(&mut actor, &alice, &bob, &[]).await.unwrap();
+        let state = StakeState {
+            this: &stake,
+            that: &stake,
+        };
+        let card = Actor::accept_duel(&mut actor, &alice, &bob, &[], state)
+            .await
+            .unwrap();
+        (stake, card)
+    });
+    assert_eq!((stake.star, stake.coin), (2, 1));
+    // card names from the peer are matched to the rules
+    assert_eq!(card, Some(ROCK));
+
+    let requests = requests.lock().unwrap();
+    let methods = requests
+        .iter()
+        .map(|(_, x)| x["method"].as_str().unwrap())
+        .collect_vec();
+    assert_eq!(methods, ["notify", "bet", "accept_duel"]);
+    assert!(requests
+        .iter()
+        .enumerate()
+        .all(|(index, (connection, x))| *connection == 0 && x["id"] == index + 1));
+    assert_eq!(requests[0].1["player"]["name"], "Alice");
+    assert_eq!(requests[1].1["opponent"]["name"], "Bob");
+    assert_eq!(requests[2].1["this"]["star"], 2);
+}
+
+#[test]
+fn remote_actor_falls_back_and_reconnects() {
+    let rules = Rules::default();
+    let alice = player_data(0, "Alice", &rules);
+    let bob: OpponentData = player_data(1, "Bob", &rules).into();
+    let stake = Default::default();
+    let state = StakeState {
+        this: &stake,
+        that: &stake,
+    };
+    let timeout = Duration::from_millis(200);
+
+    // nobody listens, so the actor plays as a dummy
+    let addr = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    };
+    let mut actor = RemoteActor::new(addr, timeout, fastrand::Rng::with_seed(0));
+    let card = block_on(async {
+        Actor::notify(&mut actor, &alice, &rules, &PublicState::default())
+            .await
+            .unwrap();
+        Actor::accept_duel(&mut actor, &alice, &bob, &[], state)
+            .await
+            .unwrap()
+    });
+    assert!(card.is_some_and(|card| alice.inventory.count(&card) > 0));
+
+    // the first connection hangs on the duel, later ones draw paper
+    let (addr, requests) = serve_remote(|index, request| match request["method"].as_str() {
+        Some("accept_duel") if index == 0 => None,
+        Some("accept_duel") => Some("Paper".into()),
+        _ => Some(serde_json::Value::Null),
+    });
+    let mut actor = RemoteActor::new(addr, timeout, fastrand::Rng::with_seed(0));
+    block_on(async {
+        Actor::notify(&mut actor, &alice, &rules, &PublicState::default())
+            .await
+            .unwrap();
+
+        let start = Instant::now();
+        let card = Actor::accept_duel(&mut actor, &alice, &bob, &[], state)
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= timeout);
+        assert!(card.is_some());
+
+        // the connection is dropped after timing out, and restored at the next round
+        Actor::accept_duel(&mut actor, &alice, &bob, &[], state)
+            .await
+            .unwrap();
+        Actor::notify(&mut actor, &alice, &rules, &PublicState::default())
+            .await
+            .unwrap();
+        let card = Actor::accept_duel(&mut actor, &alice, &bob, &[], state)
+            .await
+            .unwrap();
+        assert_eq!(card, Some(PAPER));
+    });
+
+    let requests = requests.lock().unwrap();
+    let calls = requests
+        .iter()
+        .map(|(index, x)| (*index, x["method"].as_str().unwrap()))
+        .collect_vec();
+    assert_eq!(
+        calls,
+        [
+            (0, "notify"),
+            (0, "accept_duel"),
+            (1, "notify"),
+            (1, "accept_duel")
+        ]
+    );
+}
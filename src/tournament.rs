@@ -0,0 +1,534 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use bevy::prelude::*;
+use clap::ValueEnum;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::BackendKind,
+    game::{ActorKind, GameLog, GamePlugin, Inventory, Player},
+    rules::Rules,
+    Settings,
+};
+
+/// File names of the tournament summary in the output directory.
+pub const SUMMARY_JSON_FILE: &str = "summary.json";
+pub const SUMMARY_TEXT_FILE: &str = "summary.txt";
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct TournamentArgs {
+    /// Number of games to play.
+    #[arg(long, default_value = "10")]
+    pub games: usize,
+    /// Number of games to play at the same time.
+    #[arg(long, default_value = "1")]
+    pub jobs: usize,
+    /// Actor mix of a game, e.g., `llm,nash`. Repeat to add more mixes, which games cycle through. Defaults to `--actors`.
+    #[arg(long)]
+    pub mix: Vec<String>,
+    /// Models for LLM players. Games cycle through them, and LLM players are rated per model.
+    #[arg(long, value_delimiter = ',')]
+    pub models: Vec<String>,
+}
+
+/// Parse an actor mix such as `llm,nash`.
+pub fn parse_mix(mix: &str) -> Result<Vec<ActorKind>> {
+    let kinds: Vec<_> = mix
+        .split(',')
+        .map(|kind| ActorKind::from_str(kind.trim(), true).map_err(|err| anyhow!(err)))
+        .try_collect()?;
+    match kinds.is_empty() {
+        true => bail!("empty actor mix"),
+        false => Ok(kinds),
+    }
+}
+
+/// A player at the end of a game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerOutcome {
+    pub name: String,
+    /// The actor kind, or `llm:<model>` for LLM players if models are compared.
+    pub label: String,
+    pub inventory: Inventory,
+    pub safe: bool,
+    /// Survivors are ranked by coins from 1; eliminated players share the rank after the last survivor.
+    pub rank: usize,
+}
+
+/// The result of a game in the tournament.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameOutcome {
+    pub index: usize,
+    pub seed: Option<u64>,
+    pub output: PathBuf,
+    pub duration: Duration,
+    pub players: Vec<PlayerOutcome>,
+    /// Why the game fails, if it does; failed games are left out of the statistics.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Rank players as the game does: survivors by coins, and everyone else after them.
+pub fn rank_players(players: &mut [PlayerOutcome]) {
+    let coins = players
+        .iter()
+        .filter(|x| x.safe)
+        .map(|x| x.inventory.coin)
+        .collect_vec();
+    let survivors = coins.len();
+    for player in players.iter_mut() {
+        player.rank = match player.safe {
+            true => 1 + coins.iter().filter(|&&x| x > player.inventory.coin).count(),
+            false => survivors + 1,
+        };
+    }
+}
+
+/// Run a game to the end without window, and collect the final inventories.
+pub fn play_game(
+    settings: &Settings,
+    rules: &Rules,
+) -> (AppExit, GameLog, Vec<(String, Inventory)>) {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, GamePlugin))
+        .insert_resource(settings.clone())
+        .insert_resource(rules.clone());
+    app.finish();
+    app.cleanup();
+
+    let exit = loop {
+        app.update();
+        if let Some(exit) = app.should_exit() {
+            break exit;
+        }
+        thread::sleep(Duration::from_millis(1));
+    };
+
+    let world = app.world_mut();
+    let log = world.resource::<GameLog>().clone();
+    let players = world
+        .query_filtered::<(&Name, &Inventory), With<Player>>()
+        .iter(world)
+        .map(|(name, inventory)| (name.to_string(), inventory.clone()))
+        .collect();
+    (exit, log, players)
+}
+
+/// Elo rating, updated pairwise between players of different labels.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Elo(pub f64);
+
+impl Default for Elo {
+    fn default() -> Self {
+        Self(1500.0)
+    }
+}
+
+impl Elo {
+    pub const K: f64 = 32.0;
+
+    /// Expected score against the opponent.
+    pub fn expect(self, other: Self) -> f64 {
+        1.0 / (1.0 + 10f64.powf((other.0 - self.0) / 400.0))
+    }
+}
+
+/// TrueSkill rating, updated pairwise between players of different labels as in two-player games.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TrueSkill {
+    pub mu: f64,
+    pub sigma: f64,
+}
+
+impl Default for TrueSkill {
+    fn default() -> Self {
+        Self {
+            mu: Self::MU,
+            sigma: Self::MU / 3.0,
+        }
+    }
+}
+
+impl TrueSkill {
+    pub const MU: f64 = 25.0;
+    pub const BETA: f64 = Self::MU / 6.0;
+    pub const TAU: f64 = Self::MU / 300.0;
+    /// Draw margin for a draw probability of 10%, i.e., `Φ⁻¹(0.55) * √2 * β`.
+    pub const DRAW_MARGIN: f64 = 0.125_661 * std::f64::consts::SQRT_2 * Self::BETA;
+
+    /// A conservative estimate of the skill, `μ - 3σ`.
+    pub fn exposed(self) -> f64 {
+        self.mu - 3.0 * self.sigma
+    }
+
+    /// Updates of mean and the factor of variance for `self` against `other`, with the score of `self`.
+    fn update(self, other: Self, score: f64) -> (f64, f64) {
+        let c = (2.0 * Self::BETA.powi(2) + self.sigma.powi(2) + other.sigma.powi(2)).sqrt();
+        let t = (self.mu - other.mu) / c;
+        let e = Self::DRAW_MARGIN / c;
+        let (v, w) = match score {
+            x if x > 0.75 => v_w_win(t, e),
+            x if x < 0.25 => {
+                let (v, w) = v_w_win(-t, e);
+                (-v, w)
+            }
+            _ => v_w_draw(t, e),
+        };
+        let variance = self.sigma.powi(2);
+        let mu = variance / c * v;
+        let factor = (1.0 - variance / c.powi(2) * w).max(1e-4);
+        (mu, factor)
+    }
+}
+
+fn pdf(x: f64) -> f64 {
+    (-x * x / 2.0).exp() / (2.0 * std::f64::consts::PI).sqrt()
+}
+
+fn cdf(x: f64) -> f64 {
+    // Abramowitz and Stegun 7.1.26, accurate to 1.5e-7
+    let z = x.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.327_591_1 * z);
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let erf = 1.0 - poly * (-z * z).exp();
+    match x >= 0.0 {
+        true => 0.5 * (1.0 + erf),
+        false => 0.5 * (1.0 - erf),
+    }
+}
+
+fn v_w_win(t: f64, e: f64) -> (f64, f64) {
+    let x = t - e;
+    let denominator = cdf(x);
+    let v = match denominator < 1e-12 {
+        true => -x,
+        false => pdf(x) / denominator,
+    };
+    (v, v * (v + x))
+}
+
+fn v_w_draw(t: f64, e: f64) -> (f64, f64) {
+    let a = t.abs();
+    let denominator = cdf(e - a) - cdf(-e - a);
+    if denominator < 1e-12 {
+        return (-t, 1.0);
+    }
+    let v = (pdf(-e - a) - pdf(e - a)) / denominator;
+    let w = v * v + ((e - a) * pdf(e - a) + (e + a) * pdf(-e - a)) / denominator;
+    (v * t.signum(), w)
+}
+
+/// Aggregate statistics of players with the same label.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Standing {
+    pub label: String,
+    /// Number of players over all games.
+    pub players: usize,
+    pub survivors: usize,
+    pub survival_rate: f64,
+    pub mean_coins: f64,
+    pub mean_stars: f64,
+    /// How many times survivors finish at each rank.
+    pub ranks: BTreeMap<usize, usize>,
+    pub elo: Elo,
+    pub trueskill: TrueSkill,
+}
+
+/// The summary report of a tournament.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Summary {
+    pub games: Vec<GameOutcome>,
+    /// Standings sorted by TrueSkill, best first.
+    pub standings: Vec<Standing>,
+}
+
+impl Summary {
+    /// Aggregate the outcomes of games, rating players in the order of games.
+    pub fn new(games: Vec<GameOutcome>) -> Self {
+        let mut standings: HashMap<String, Standing> = HashMap::new();
+        let mut coins: HashMap<String, usize> = HashMap::new();
+        let mut stars: HashMap<String, usize> = HashMap::new();
+
+        for game in games.iter().filter(|x| x.error.is_none()) {
+            for player in &game.players {
+                let standing = standings
+                    .entry(player.label.clone())
+                    .or_insert_with(|| Standing {
+                        label: player.label.clone(),
+                        ..Default::default()
+                    });
+                standing.players += 1;
+                if player.safe {
+                    standing.survivors += 1;
+                    *standing.ranks.entry(player.rank).or_default() += 1;
+                }
+                *coins.entry(player.label.clone()).or_default() += player.inventory.coin;
+                *stars.entry(player.label.clone()).or_default() += player.inventory.star;
+            }
+
+            // each player plays everyone else once, and a game weighs as much as a match for each player
+            let weight = 1.0 / game.players.len().saturating_sub(1).max(1) as f64;
+            let mut elo: HashMap<&str, f64> = HashMap::new();
+            let mut mu: HashMap<&str, f64> = HashMap::new();
+            let mut factor: HashMap<&str, f64> = HashMap::new();
+            for (x, y) in game.players.iter().tuple_combinations() {
+                if x.label == y.label {
+                    continue;
+                }
+                let score = match x.rank.cmp(&y.rank) {
+                    std::cmp::Ordering::Less => 1.0,
+                    std::cmp::Ordering::Equal => 0.5,
+                    std::cmp::Ordering::Greater => 0.0,
+                };
+                let (sx, sy) = (&standings[&x.label], &standings[&y.label]);
+
+                let delta = Elo::K * weight * (score - sx.elo.expect(sy.elo));
+                *elo.entry(&x.label).or_default() += delta;
+                *elo.entry(&y.label).or_default() -= delta;
+
+                for (this, that, score, label) in [
+                    (sx.trueskill, sy.trueskill, score, &x.label),
+                    (sy.trueskill, sx.trueskill, 1.0 - score, &y.label),
+                ] {
+                    let this = TrueSkill {
+                        sigma: (this.sigma.powi(2) + TrueSkill::TAU.powi(2)).sqrt(),
+                        ..this
+                    };
+                    let (m, f) = this.update(that, score);
+                    *mu.entry(label).or_default() += m * weight;
+                    *factor.entry(label).or_insert(1.0) *= f.powf(weight);
+                }
+            }
+            for (label, standing) in standings.iter_mut() {
+                standing.elo.0 += elo.get(label.as_str()).copied().unwrap_or_default();
+                standing.trueskill.mu += mu.get(label.as_str()).copied().unwrap_or_default();
+                if let Some(f) = factor.get(label.as_str()) {
+                    standing.trueskill.sigma *= f.sqrt();
+                }
+            }
+        }
+
+        let standings = standings
+            .into_values()
+            .map(|mut standing| {
+                let players = standing.players.max(1) as f64;
+                standing.survival_rate = standing.survivors as f64 / players;
+                standing.mean_coins = coins[&standing.label] as f64 / players;
+                standing.mean_stars = stars[&standing.label] as f64 / players;
+                standing
+            })
+            .sorted_by(|x, y| {
+                let ordering = y.trueskill.exposed().total_cmp(&x.trueskill.exposed());
+                ordering.then_with(|| x.label.cmp(&y.label))
+            })
+            .collect();
+        Self { games, standings }
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let failed = self.games.iter().filter(|x| x.error.is_some()).count();
+        writeln!(f, "{} games, {failed} failed", self.games.len())?;
+        writeln!(
+            f,
+            "{:<16} {:>7} {:>9} {:>10} {:>10} {:>7} {:>16}  ranks",
+            "actor", "players", "survival", "coins", "stars", "elo", "trueskill"
+        )?;
+        for standing in &self.standings {
+            let ranks = standing
+                .ranks
+                .iter()
+                .map(|(rank, count)| format!("#{rank}: {count}"))
+                .join(", ");
+            let trueskill = format!(
+                "{:.1} ± {:.1}",
+                standing.trueskill.mu,
+                3.0 * standing.trueskill.sigma
+            );
+            writeln!(
+                f,
+                "{:<16} {:>7} {:>8.1}% {:>10.2} {:>10.2} {:>7.0} {:>16}  {ranks}",
+                standing.label,
+                standing.players,
+                100.0 * standing.survival_rate,
+                standing.mean_coins,
+                standing.mean_stars,
+                standing.elo.0,
+                trueskill,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Settings of the game at `index` in the tournament.
+fn game_settings(
+    args: &TournamentArgs,
+    mixes: &[Vec<ActorKind>],
+    settings: &Settings,
+    index: usize,
+) -> Settings {
+    let mut settings = settings.clone();
+    settings.output = settings.output.join(format!("game-{index:03}"));
+    settings.seed = settings.seed.map(|seed| seed.wrapping_add(index as u64));
+    // games running in parallel must not write to the same cassette
+    settings.cassette = settings.cassette.map(|path| {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(ext) => format!("{stem}-game-{index:03}.{}", ext.to_string_lossy()),
+            None => format!("{stem}-game-{index:03}"),
+        };
+        path.with_file_name(name)
+    });
+    if !mixes.is_empty() {
+        settings.actors = mixes[index % mixes.len()].clone();
+    }
+    if !args.models.is_empty() {
+        settings.model = args.models[index % args.models.len()].clone();
+    }
+    settings
+}
+
+/// Label of players of an actor kind, as rated in the tournament.
+fn label(kind: ActorKind, args: &TournamentArgs, settings: &Settings) -> String {
+    let name = kind
+        .to_possible_value()
+        .map(|x| x.get_name().to_owned())
+        .unwrap_or_default();
+    match kind {
+        ActorKind::Llm if !args.models.is_empty() || settings.backend == BackendKind::Openai => {
+            format!("{name}:{}", settings.model)
+        }
+        _ => name,
+    }
+}
+
+fn run_game(
+    args: &TournamentArgs,
+    settings: &Settings,
+    rules: &Rules,
+    index: usize,
+) -> GameOutcome {
+    let start = Instant::now();
+    let mut outcome = GameOutcome {
+        index,
+        seed: settings.seed,
+        output: settings.output.clone(),
+        duration: Duration::ZERO,
+        players: vec![],
+        error: None,
+    };
+
+    let (exit, log, players) = play_game(settings, rules);
+    outcome.duration = start.elapsed();
+    if let AppExit::Error(code) = exit {
+        outcome.error = Some(format!("game aborted with exit code {code}"));
+    }
+
+    outcome.players = log
+        .players
+        .iter()
+        .filter_map(|record| {
+            let (_, inventory) = players.iter().find(|(name, _)| name == &record.name)?;
+            Some(PlayerOutcome {
+                name: record.name.clone(),
+                label: label(record.actor, args, settings),
+                inventory: inventory.clone(),
+                safe: inventory.is_safe(rules),
+                rank: 0,
+            })
+        })
+        .collect();
+    rank_players(&mut outcome.players);
+    outcome
+}
+
+/// Play the games of the tournament, and aggregate their outcomes.
+pub fn play_tournament(
+    args: &TournamentArgs,
+    settings: &Settings,
+    rules: &Rules,
+) -> Result<Summary> {
+    if settings.actors.contains(&ActorKind::Human) || args.mix.iter().any(|x| x.contains("human")) {
+        bail!("human players cannot take part in tournaments");
+    }
+    let mixes: Vec<_> = args.mix.iter().map(|x| parse_mix(x)).try_collect()?;
+
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::with_capacity(args.games));
+    thread::scope(|scope| {
+        for _ in 0..args.jobs.clamp(1, args.games.max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                if index >= args.games {
+                    break;
+                }
+                let settings = game_settings(args, &mixes, settings, index);
+                let outcome = run_game(args, &settings, rules, index);
+                match &outcome.error {
+                    Some(err) => println!("game {index} failed: {err}"),
+                    None => println!(
+                        "game {index} finished in {:.1}s, {} of {} players safe",
+                        outcome.duration.as_secs_f64(),
+                        outcome.players.iter().filter(|x| x.safe).count(),
+                        outcome.players.len()
+                    ),
+                }
+                outcomes.lock().unwrap().push(outcome);
+            });
+        }
+    });
+
+    let games = outcomes
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .sorted_by_key(|x| x.index)
+        .collect();
+    Ok(Summary::new(games))
+}
+
+/// Write the summary report as JSON and text into the directory.
+pub fn write_summary(summary: &Summary, dir: impl AsRef<Path>) -> Result<()> {
+    let dir = dir.as_ref();
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(
+        dir.join(SUMMARY_JSON_FILE),
+        serde_json::to_vec_pretty(summary)?,
+    )?;
+    std::fs::write(dir.join(SUMMARY_TEXT_FILE), summary.to_string())?;
+    Ok(())
+}
+
+/// Play the tournament into a new directory under the output directory, and write the summary there.
+pub fn run(args: TournamentArgs, settings: Settings, rules: Rules) -> Result<()> {
+    let time = chrono::Local::now().format("%Y-%m-%d-%H-%M").to_string();
+    let settings = Settings {
+        output: settings.output.join(format!("tournament-{time}")),
+        ..settings
+    };
+    let summary = play_tournament(&args, &settings, &rules)?;
+    write_summary(&summary, &settings.output)?;
+    print!("{summary}");
+    println!(
+        "summary written to {:?}",
+        settings.output.join(SUMMARY_JSON_FILE)
+    );
+    Ok(())
+}
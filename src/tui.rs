@@ -0,0 +1,344 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, time::common_conditions::on_timer};
+use itertools::Itertools;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Text},
+    widgets::{Block, Paragraph, Row, Table as TableWidget, Wrap},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    backend::LlmTraffic,
+    dashboard::{describe_cards, update_feed, DashboardFeed, DashboardFeedPlugin},
+    game::{
+        DuelProgress, DuelResult, GameRound, Inventory, Player, PlayerDead, PlayerSafe,
+        PlayerTimer, PublicState, SharedLlm, Table,
+    },
+};
+
+/// How often the terminal is redrawn.
+pub const TUI_REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Most chat lines shown for each table.
+const TUI_CHAT_LINES: usize = 6;
+
+/// A terminal dashboard for headless runs, e.g., over SSH: players with their inventories and timers,
+/// tables with their live chat, the LLM traffic and a feed of what happens.
+///
+/// The terminal is only drawn if a [`TuiTerminal`] is inserted; the [`TuiView`] is kept up to date regardless.
+#[derive(Debug, Default)]
+pub struct TuiPlugin;
+
+impl Plugin for TuiPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<DashboardFeedPlugin>() {
+            app.add_plugins(DashboardFeedPlugin);
+        }
+        app.init_resource::<TuiView>().add_systems(
+            Update,
+            (
+                update_view.after(update_feed),
+                (read_input, draw_tui.run_if(on_timer(TUI_REFRESH_INTERVAL)))
+                    .after(update_view)
+                    .run_if(resource_exists::<TuiTerminal>),
+            ),
+        );
+    }
+}
+
+/// The terminal the TUI takes over; restored when dropped.
+#[derive(Debug, Deref, DerefMut, Resource)]
+pub struct TuiTerminal(pub DefaultTerminal);
+
+impl TuiTerminal {
+    /// Enter raw mode and the alternate screen.
+    pub fn new() -> std::io::Result<Self> {
+        ratatui::try_init().map(Self)
+    }
+}
+
+impl Drop for TuiTerminal {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerStatus {
+    #[default]
+    Alive,
+    AtTable,
+    Safe,
+    Dead,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayerView {
+    pub name: String,
+    pub inventory: Inventory,
+    pub timer: usize,
+    pub status: PlayerStatus,
+}
+
+#[derive(Debug, Clone)]
+pub struct TableView {
+    pub players: [String; 2],
+    /// Chat lines, prefixed with the speakers.
+    pub chat: Vec<String>,
+    /// What the table is doing now.
+    pub stage: String,
+}
+
+/// What the TUI shows, collected from the world each frame.
+#[derive(Debug, Default, Clone, Resource)]
+pub struct TuiView {
+    pub round: usize,
+    pub players: Vec<PlayerView>,
+    pub tables: Vec<TableView>,
+    pub state: PublicState,
+    pub traffic: Option<LlmTraffic>,
+    pub feed: Vec<String>,
+}
+
+impl TuiView {
+    /// Number of players that are alive, safe and dead.
+    pub fn tally(&self) -> [usize; 3] {
+        let count = |status: &[PlayerStatus]| {
+            self.players
+                .iter()
+                .filter(|x| status.contains(&x.status))
+                .count()
+        };
+        [
+            count(&[PlayerStatus::Alive, PlayerStatus::AtTable]),
+            count(&[PlayerStatus::Safe]),
+            count(&[PlayerStatus::Dead]),
+        ]
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn update_view(
+    mut view: ResMut<TuiView>,
+    round: Res<GameRound>,
+    state: Res<PublicState>,
+    feed: Res<DashboardFeed>,
+    llm: Option<Res<SharedLlm>>,
+    players: Query<
+        (
+            Entity,
+            &Name,
+            &Inventory,
+            &PlayerTimer,
+            Has<PlayerDead>,
+            Has<PlayerSafe>,
+        ),
+        With<Player>,
+    >,
+    tables: Query<(Entity, &Table, Option<&DuelProgress>)>,
+) {
+    let name = |entity: Entity| {
+        players
+            .get(entity)
+            .map(|x| x.1.to_string())
+            .unwrap_or_else(|_| format!("{entity}"))
+    };
+    let seated = tables
+        .iter()
+        .flat_map(|(_, table, _)| table.0)
+        .collect_vec();
+
+    view.round = round.0;
+    view.state = state.clone();
+    view.traffic = llm.map(|llm| llm.limiter.traffic());
+    view.feed = feed.iter().cloned().collect();
+    view.players = players
+        .iter()
+        .sorted_by_key(|x| x.0)
+        .map(|(entity, name, inventory, timer, dead, safe)| {
+            let status = match (dead, safe, seated.contains(&entity)) {
+                (true, _, _) => PlayerStatus::Dead,
+                (_, true, _) => PlayerStatus::Safe,
+                (_, _, true) => PlayerStatus::AtTable,
+                _ => PlayerStatus::Alive,
+            };
+            PlayerView {
+                name: name.to_string(),
+                inventory: inventory.clone(),
+                timer: timer.0,
+                status,
+            }
+        })
+        .collect();
+    view.tables = tables
+        .iter()
+        .sorted_by_key(|x| x.0)
+        .map(|(_, table, progress)| {
+            let players = table.0.map(name);
+            let report = progress.map(DuelProgress::report).unwrap_or_default();
+            let chat = report
+                .chat
+                .iter()
+                .chain(&report.duel_chat)
+                .map(|line| format!("{}: {}", players[line.player], line.content))
+                .collect();
+            let [p0, p1] = &players;
+            let stage = match (&report.result, &report.stakes) {
+                (Some(DuelResult::Win(x, y)), _) => format!("{p0} ({x}) beats {p1} ({y})"),
+                (Some(DuelResult::Lose(x, y)), _) => format!("{p1} ({y}) beats {p0} ({x})"),
+                (Some(DuelResult::Tie(x)), _) => format!("tie with {x}"),
+                (None, Some(_)) => "drawing cards".into(),
+                (None, None) if report.accepted.iter().any(|&x| x) => "betting".into(),
+                (None, None) => "negotiating".into(),
+            };
+            TableView {
+                players,
+                chat,
+                stage,
+            }
+        })
+        .collect();
+}
+
+/// Quit on `q`, `Esc` or `Ctrl-C`, as the raw terminal does not send interrupts.
+fn read_input(mut exit: EventWriter<AppExit>) {
+    while let Ok(true) = event::poll(Duration::ZERO) {
+        let Ok(Event::Key(key)) = event::read() else {
+            continue;
+        };
+        let quit = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => true,
+            KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+            _ => false,
+        };
+        if quit && key.kind == KeyEventKind::Press {
+            exit.send(AppExit::from_code(130));
+        }
+    }
+}
+
+fn draw_tui(mut terminal: ResMut<TuiTerminal>, view: Res<TuiView>) {
+    if let Err(err) = terminal.draw(|frame| draw(frame, &view)) {
+        bevy::log::error!("failed to draw the terminal: {err}");
+    }
+}
+
+/// Render the view into a frame.
+pub fn draw(frame: &mut Frame, view: &TuiView) {
+    let [header, body, feed] = Layout::vertical([
+        Constraint::Length(4),
+        Constraint::Min(8),
+        Constraint::Length(8),
+    ])
+    .areas(frame.area());
+    let [players, tables] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(body);
+
+    let [alive, safe, dead] = view.tally();
+    let traffic = match view.traffic {
+        Some(traffic) => {
+            let LlmTraffic {
+                waiting,
+                in_flight,
+                completed,
+                ..
+            } = traffic;
+            let latency = traffic
+                .mean_latency()
+                .map_or("-".into(), |x| format!("{x:.2?}"));
+            format!(
+                "LLM: {waiting} waiting, {in_flight} in flight, {completed} completed, {latency} on average"
+            )
+        }
+        None => "LLM: -".into(),
+    };
+    let summary = Text::from(vec![
+        Line::from(vec![
+            format!("Round {}  ", view.round).bold(),
+            format!("{alive} alive").green(),
+            ", ".into(),
+            format!("{safe} safe").cyan(),
+            ", ".into(),
+            format!("{dead} dead").red(),
+            format!("  |  {} cards left: ", view.state.total_cards()).into(),
+            describe_cards(&view.state.cards).into(),
+        ]),
+        Line::from(traffic),
+    ]);
+    frame.render_widget(
+        Paragraph::new(summary).block(Block::bordered().title(" Ark of Destinies ")),
+        header,
+    );
+
+    let rows = view.players.iter().map(|player| {
+        let (status, style) = match player.status {
+            PlayerStatus::Alive => ("", Style::default()),
+            PlayerStatus::AtTable => ("at table", Style::default().fg(Color::Yellow)),
+            PlayerStatus::Safe => ("safe", Style::default().fg(Color::Cyan)),
+            PlayerStatus::Dead => ("dead", Style::default().fg(Color::DarkGray)),
+        };
+        Row::new([
+            player.name.clone(),
+            player.inventory.star.to_string(),
+            player.inventory.coin.to_string(),
+            describe_cards(&player.inventory.cards),
+            player.timer.to_string(),
+            status.into(),
+        ])
+        .style(style)
+    });
+    let widths = [
+        Constraint::Min(10),
+        Constraint::Length(5),
+        Constraint::Length(5),
+        Constraint::Min(20),
+        Constraint::Length(6),
+        Constraint::Length(8),
+    ];
+    let header_row = Row::new(["Name", "Stars", "Coins", "Cards", "Rounds", "Status"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+    frame.render_widget(
+        TableWidget::new(rows, widths)
+            .header(header_row)
+            .block(Block::bordered().title(" Players ")),
+        players,
+    );
+
+    let lines = view
+        .tables
+        .iter()
+        .flat_map(|table| {
+            let [p0, p1] = &table.players;
+            let head = Line::from(vec![
+                format!("{p0} vs {p1}").bold(),
+                format!("  {}", table.stage).italic(),
+            ]);
+            let skip = table.chat.len().saturating_sub(TUI_CHAT_LINES);
+            std::iter::once(head)
+                .chain(
+                    table.chat[skip..]
+                        .iter()
+                        .map(|x| Line::from(format!("  {x}"))),
+                )
+                .chain(std::iter::once(Line::default()))
+        })
+        .collect_vec();
+    frame.render_widget(
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title(format!(" Tables ({}) ", view.tables.len()))),
+        tables,
+    );
+
+    let height = feed.height.saturating_sub(2) as usize;
+    let skip = view.feed.len().saturating_sub(height);
+    let lines = view.feed[skip..].iter().map(|x| Line::from(x.as_str()));
+    frame.render_widget(
+        Paragraph::new(lines.collect_vec()).block(Block::bordered().title(" Feed (q to quit) ")),
+        feed,
+    );
+}